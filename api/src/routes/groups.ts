@@ -21,6 +21,7 @@ const router = Router();
 const GROUPS_PREFIX = 'group:';
 const GROUP_MEMBERS_PREFIX = 'group:members:';
 const USER_GROUPS_PREFIX = 'user:groups:';
+const DEFAULT_INVITATION_TTL = 7 * 24 * 60 * 60; // 7 days
 
 interface CreateGroupRequest {
     name: string;
@@ -59,11 +60,12 @@ router.post('/build-create-transaction', async (req: Request, res: Response) =>
             inviteOnly = true,
             maxMembers = 100,
             allowMemberInvites = true,
-            groupEncryptionKey, // Base64
+            keyCommitment, // Base64, SHA-256 of the group key
+            ownerEncryptedGroupKey, // Base64, group key encrypted for the owner
             ownerPubkey,
         } = req.body;
 
-        if (!name || !groupEncryptionKey || !ownerPubkey) {
+        if (!name || !keyCommitment || !ownerEncryptedGroupKey || !ownerPubkey) {
             return res.status(400).json({ error: 'Missing required fields' });
         }
 
@@ -80,7 +82,8 @@ router.post('/build-create-transaction', async (req: Request, res: Response) =>
             inviteOnly,
             maxMembers,
             allowMemberInvites,
-            groupEncryptionKey: Buffer.from(groupEncryptionKey, 'base64'),
+            keyCommitment: Buffer.from(keyCommitment, 'base64'),
+            ownerEncryptedGroupKey: Buffer.from(ownerEncryptedGroupKey, 'base64'),
             ownerPubkey: new PublicKey(ownerPubkey),
         });
 
@@ -164,7 +167,7 @@ router.post('/create', async (req: Request, res: Response) => {
 router.post('/:groupId/build-invite-transaction', async (req: Request, res: Response) => {
     try {
         const { groupId } = req.params;
-        const { invitedUserPubkey, inviterPubkey, encryptedGroupKey } = req.body;
+        const { invitedUserPubkey, inviterPubkey, encryptedGroupKey, ttl } = req.body;
 
         if (!invitedUserPubkey || !inviterPubkey || !encryptedGroupKey) {
             return res.status(400).json({ error: 'Missing required fields' });
@@ -178,6 +181,7 @@ router.post('/:groupId/build-invite-transaction', async (req: Request, res: Resp
             invitedUserPubkey: new PublicKey(invitedUserPubkey),
             inviterPubkey: new PublicKey(inviterPubkey),
             encryptedGroupKey: Buffer.from(encryptedGroupKey, 'base64'),
+            ttl: ttl ?? DEFAULT_INVITATION_TTL,
         });
 
         const serializedTx = transaction.serialize({
@@ -13,6 +13,7 @@ import {
 import { rateLimitMiddleware } from '../middleware/rateLimit.js';
 import { isBlocked, redis as redisClient } from '../services/redis.js';
 import { uploadToArweave } from '../services/arweaveService.js';
+import { getGroupMemberAccount, isMemberMuted } from '../services/solana-groups.js';
 import { spendingLimitMiddleware } from '../middleware/spendingLimits.js';
 
 const router = Router();
@@ -360,6 +361,19 @@ router.post('/group/:groupId/send', rateLimitMiddleware, async (req: Request, re
             console.warn('Ban check failed:', err);
         }
 
+        // Group mutes live on the sender's member account
+        const senderMember = await getGroupMemberAccount(
+            Buffer.from(groupId, 'hex'),
+            new PublicKey(senderPubkey)
+        );
+        if (senderMember && isMemberMuted(senderMember)) {
+            return res.status(403).json({
+                error: 'Muted',
+                message: 'You have been muted in this group.',
+                mutedUntil: senderMember.mutedUntil,
+            });
+        }
+
         // Store message on Arweave
         const messageData = {
             groupId,
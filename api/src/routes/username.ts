@@ -115,14 +115,19 @@ router.get('/:name', async (req: Request, res: Response) => {
             });
         }
 
-        // Read encryption key from on-chain account data
-        const encryptionKey = userAccount.encryptionKey;
+        // Read encryption key from on-chain account data; an expired name's
+        // key may belong to a lost wallet, so don't hand it out
+        const encryptionKey = userAccount.expired ? null : userAccount.encryptionKey;
 
         return res.json({
             username,
             publicKey: userAccount.owner,
             encryptionKey: encryptionKey || null,
             registeredAt: new Date(userAccount.createdAt * 1000).toISOString(),
+            expiresAt: userAccount.expiresAt
+                ? new Date(userAccount.expiresAt * 1000).toISOString()
+                : null,
+            expired: userAccount.expired,
         });
     } catch (error) {
         console.error('❌ User lookup error:', error);
@@ -7,7 +7,7 @@ import {
 } from '@solana/web3.js';
 import crypto from 'crypto';
 import bs58 from 'bs58';
-import { connection, getFeePayer } from './solana.js';
+import { connection, getFeePayer, getProgramConfigPDA } from './solana.js';
 
 // Program ID from deployed Anchor program
 const PROGRAM_ID = new PublicKey('96hG67JxhNEptr1LkdtDcrqvtWiHH3x4GibDBcdh4MYQ');
@@ -34,6 +34,9 @@ const DISCRIMINATORS = {
     JOIN_GROUP: getDiscriminator('join_group'),
     LEAVE_GROUP: getDiscriminator('leave_group'),
     INVITE_MEMBER: getDiscriminator('invite_member'),
+    ACCEPT_INVITATION: getDiscriminator('accept_invitation'),
+    DECLINE_INVITATION: getDiscriminator('decline_invitation'),
+    REVOKE_INVITATION: getDiscriminator('revoke_invitation'),
     KICK_MEMBER: getDiscriminator('kick_member'),
     UPDATE_MEMBER_ROLE: getDiscriminator('update_member_role'),
     CREATE_INVITE_LINK: getDiscriminator('create_invite_link'),
@@ -67,6 +70,39 @@ export function getGroupMemberPDA(groupId: Buffer, memberPubkey: PublicKey): [Pu
     );
 }
 
+/**
+ * Get PDA for a group ban record
+ * Seeds: [b"group:ban", group_id, member_pubkey]
+ */
+export function getGroupBanPDA(groupId: Buffer, memberPubkey: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('group:ban'), groupId, memberPubkey.toBuffer()],
+        PROGRAM_ID
+    );
+}
+
+/**
+ * Get PDA for a pending direct invitation
+ * Seeds: [b"group:invitation", group_id, invited_user]
+ */
+export function getGroupInvitationPDA(groupId: Buffer, invitedUserPubkey: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('group:invitation'), groupId, invitedUserPubkey.toBuffer()],
+        PROGRAM_ID
+    );
+}
+
+/**
+ * Get PDA for a group's custom role table
+ * Seeds: [b"group:roles", group_id]
+ */
+export function getGroupRolesPDA(groupId: Buffer): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('group:roles'), groupId],
+        PROGRAM_ID
+    );
+}
+
 /**
  * Get PDA for a group code lookup account
  * Seeds: [b"group:code", public_code.to_lowercase()]
@@ -80,11 +116,11 @@ export function getGroupCodeLookupPDA(publicCode: string): [PublicKey, number] {
 
 /**
  * Get PDA for an invite link account
- * Seeds: [b"group:invite", group_id, invite_code]
+ * Seeds: [b"group:invite", group_id, invite_code.to_lowercase()]
  */
 export function getInviteLinkPDA(groupId: Buffer, inviteCode: string): [PublicKey, number] {
     return PublicKey.findProgramAddressSync(
-        [Buffer.from('group:invite'), groupId, Buffer.from(inviteCode)],
+        [Buffer.from('group:invite'), groupId, Buffer.from(inviteCode.toLowerCase())],
         PROGRAM_ID
     );
 }
@@ -154,7 +190,8 @@ export interface CreateGroupParams {
     inviteOnly: boolean;
     maxMembers: number; // u16
     allowMemberInvites: boolean;
-    groupEncryptionKey: Buffer; // 32 bytes
+    keyCommitment: Buffer; // 32 bytes, SHA-256 of the group key
+    ownerEncryptedGroupKey: Buffer; // 64 bytes
     ownerPubkey: PublicKey;
 }
 
@@ -180,6 +217,17 @@ export interface InviteMemberParams {
     invitedUserPubkey: PublicKey;
     encryptedGroupKey: Buffer; // 64 bytes
     inviterPubkey: PublicKey;
+    ttl: number; // seconds the invitation stays open (1 hour to 30 days)
+}
+
+export interface InvitationResponseParams {
+    groupId: Buffer;
+    invitedUserPubkey: PublicKey;
+    inviterPubkey: PublicKey;
+}
+
+export interface RevokeInvitationParams extends InvitationResponseParams {
+    revokerPubkey: PublicKey;
 }
 
 export interface KickMemberParams {
@@ -227,7 +275,8 @@ function buildCreateGroupData(params: CreateGroupParams): Buffer {
         serializeBool(params.inviteOnly),
         serializeU16(params.maxMembers),
         serializeBool(params.allowMemberInvites),
-        params.groupEncryptionKey, // 32 bytes (fixed)
+        params.keyCommitment, // 32 bytes (fixed)
+        params.ownerEncryptedGroupKey, // 64 bytes (fixed)
     ]);
 }
 
@@ -271,6 +320,17 @@ function buildInviteMemberData(params: InviteMemberParams): Buffer {
         DISCRIMINATORS.INVITE_MEMBER,
         params.groupId, // 32 bytes
         params.encryptedGroupKey, // 64 bytes
+        serializeI64(params.ttl),
+    ]);
+}
+
+/**
+ * Build instruction data for accept_invitation / decline_invitation / revoke_invitation
+ */
+function buildInvitationResponseData(discriminator: Buffer, params: InvitationResponseParams): Buffer {
+    return Buffer.concat([
+        discriminator,
+        params.groupId, // 32 bytes
     ]);
 }
 
@@ -364,6 +424,7 @@ export async function buildCreateGroupTransaction(
             { pubkey: ownerMemberPDA, isSigner: false, isWritable: true },
             { pubkey: params.ownerPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -412,6 +473,7 @@ export async function buildSetGroupCodeTransaction(
             { pubkey: lookupPDA, isSigner: false, isWritable: true },
             { pubkey: params.ownerPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -439,6 +501,7 @@ export async function buildJoinGroupTransaction(
     const feePayer = getFeePayer();
     const [groupPDA] = getGroupPDA(params.groupId);
     const [memberPDA] = getGroupMemberPDA(params.groupId, params.memberPubkey);
+    const [banPDA] = getGroupBanPDA(params.groupId, params.memberPubkey);
 
     // Calculate rent for member account
     const memberSpace = 8 + 200;
@@ -457,8 +520,10 @@ export async function buildJoinGroupTransaction(
         keys: [
             { pubkey: groupPDA, isSigner: false, isWritable: true },
             { pubkey: memberPDA, isSigner: false, isWritable: true },
+            { pubkey: banPDA, isSigner: false, isWritable: false },
             { pubkey: params.memberPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -494,6 +559,7 @@ export async function buildLeaveGroupTransaction(
             { pubkey: memberPDA, isSigner: false, isWritable: true },
             { pubkey: params.memberPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -521,29 +587,32 @@ export async function buildInviteMemberTransaction(
     const feePayer = getFeePayer();
     const [groupPDA] = getGroupPDA(params.groupId);
     const [inviterMemberPDA] = getGroupMemberPDA(params.groupId, params.inviterPubkey);
-    const [invitedMemberPDA] = getGroupMemberPDA(params.groupId, params.invitedUserPubkey);
+    const [invitationPDA] = getGroupInvitationPDA(params.groupId, params.invitedUserPubkey);
+    const [banPDA] = getGroupBanPDA(params.groupId, params.invitedUserPubkey);
 
-    // Calculate rent for invited member account
-    const memberSpace = 8 + 200;
-    const memberRent = await connection.getMinimumBalanceForRentExemption(memberSpace);
+    // Calculate rent for the pending invitation account
+    const invitationSpace = 8 + 181;
+    const invitationRent = await connection.getMinimumBalanceForRentExemption(invitationSpace);
 
-    // Fund inviter for creating invited member account
+    // Fund inviter for creating the invitation account
     const fundInviterIx = SystemProgram.transfer({
         fromPubkey: feePayer.publicKey,
         toPubkey: params.inviterPubkey,
-        lamports: memberRent + 50_000,
+        lamports: invitationRent + 50_000,
     });
 
     // Create invite instruction
     const instructionData = buildInviteMemberData(params);
     const inviteIx = new TransactionInstruction({
         keys: [
-            { pubkey: groupPDA, isSigner: false, isWritable: true },
+            { pubkey: groupPDA, isSigner: false, isWritable: false },
             { pubkey: inviterMemberPDA, isSigner: false, isWritable: false },
-            { pubkey: invitedMemberPDA, isSigner: false, isWritable: true },
+            { pubkey: invitationPDA, isSigner: false, isWritable: true },
+            { pubkey: banPDA, isSigner: false, isWritable: false },
             { pubkey: params.invitedUserPubkey, isSigner: false, isWritable: false },
             { pubkey: params.inviterPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -558,6 +627,131 @@ export async function buildInviteMemberTransaction(
     return { transaction, blockhash, lastValidBlockHeight };
 }
 
+/**
+ * Build an unsigned transaction for the invited wallet to accept a pending invitation
+ */
+export async function buildAcceptInvitationTransaction(
+    params: InvitationResponseParams
+): Promise<{
+    transaction: Transaction;
+    blockhash: string;
+    lastValidBlockHeight: number;
+}> {
+    const feePayer = getFeePayer();
+    const [groupPDA] = getGroupPDA(params.groupId);
+    const [invitationPDA] = getGroupInvitationPDA(params.groupId, params.invitedUserPubkey);
+    const [memberPDA] = getGroupMemberPDA(params.groupId, params.invitedUserPubkey);
+    const [banPDA] = getGroupBanPDA(params.groupId, params.invitedUserPubkey);
+
+    // Calculate rent for the new member account
+    const memberSpace = 8 + 200;
+    const memberRent = await connection.getMinimumBalanceForRentExemption(memberSpace);
+
+    // Fund the invited wallet for creating its member account
+    const fundMemberIx = SystemProgram.transfer({
+        fromPubkey: feePayer.publicKey,
+        toPubkey: params.invitedUserPubkey,
+        lamports: memberRent + 50_000,
+    });
+
+    const instructionData = buildInvitationResponseData(DISCRIMINATORS.ACCEPT_INVITATION, params);
+    const acceptIx = new TransactionInstruction({
+        keys: [
+            { pubkey: groupPDA, isSigner: false, isWritable: true },
+            { pubkey: invitationPDA, isSigner: false, isWritable: true },
+            { pubkey: memberPDA, isSigner: false, isWritable: true },
+            { pubkey: banPDA, isSigner: false, isWritable: false },
+            { pubkey: params.inviterPubkey, isSigner: false, isWritable: true },
+            { pubkey: params.invitedUserPubkey, isSigner: true, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
+        ],
+        programId: PROGRAM_ID,
+        data: instructionData,
+    });
+
+    const transaction = new Transaction().add(fundMemberIx, acceptIx);
+    transaction.feePayer = feePayer.publicKey;
+
+    const { blockhash, lastValidBlockHeight } = await connection.getLatestBlockhash();
+    transaction.recentBlockhash = blockhash;
+
+    return { transaction, blockhash, lastValidBlockHeight };
+}
+
+/**
+ * Build an unsigned transaction for the invited wallet to decline a pending invitation
+ */
+export async function buildDeclineInvitationTransaction(
+    params: InvitationResponseParams
+): Promise<{
+    transaction: Transaction;
+    blockhash: string;
+    lastValidBlockHeight: number;
+}> {
+    const feePayer = getFeePayer();
+    const [invitationPDA] = getGroupInvitationPDA(params.groupId, params.invitedUserPubkey);
+
+    const instructionData = buildInvitationResponseData(DISCRIMINATORS.DECLINE_INVITATION, params);
+    const declineIx = new TransactionInstruction({
+        keys: [
+            { pubkey: invitationPDA, isSigner: false, isWritable: true },
+            { pubkey: params.inviterPubkey, isSigner: false, isWritable: true },
+            { pubkey: params.invitedUserPubkey, isSigner: true, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
+        ],
+        programId: PROGRAM_ID,
+        data: instructionData,
+    });
+
+    const transaction = new Transaction().add(declineIx);
+    transaction.feePayer = feePayer.publicKey;
+
+    const { blockhash, lastValidBlockHeight } = await connection.getLatestBlockhash();
+    transaction.recentBlockhash = blockhash;
+
+    return { transaction, blockhash, lastValidBlockHeight };
+}
+
+/**
+ * Build an unsigned transaction to withdraw a pending invitation
+ * (the inviter or a moderator-ranked member at any time, anyone once it has expired)
+ */
+export async function buildRevokeInvitationTransaction(
+    params: RevokeInvitationParams
+): Promise<{
+    transaction: Transaction;
+    blockhash: string;
+    lastValidBlockHeight: number;
+}> {
+    const feePayer = getFeePayer();
+    const [invitationPDA] = getGroupInvitationPDA(params.groupId, params.invitedUserPubkey);
+    const [revokerMemberPDA] = getGroupMemberPDA(params.groupId, params.revokerPubkey);
+
+    const instructionData = buildInvitationResponseData(DISCRIMINATORS.REVOKE_INVITATION, params);
+    const revokeIx = new TransactionInstruction({
+        keys: [
+            { pubkey: invitationPDA, isSigner: false, isWritable: true },
+            { pubkey: revokerMemberPDA, isSigner: false, isWritable: false },
+            { pubkey: getGroupRolesPDA(params.groupId)[0], isSigner: false, isWritable: false },
+            { pubkey: params.invitedUserPubkey, isSigner: false, isWritable: false },
+            { pubkey: params.inviterPubkey, isSigner: false, isWritable: true },
+            { pubkey: params.revokerPubkey, isSigner: true, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
+        ],
+        programId: PROGRAM_ID,
+        data: instructionData,
+    });
+
+    const transaction = new Transaction().add(revokeIx);
+    transaction.feePayer = feePayer.publicKey;
+
+    const { blockhash, lastValidBlockHeight } = await connection.getLatestBlockhash();
+    transaction.recentBlockhash = blockhash;
+
+    return { transaction, blockhash, lastValidBlockHeight };
+}
+
 /**
  * Build an unsigned transaction to kick a member from a group
  */
@@ -572,6 +766,10 @@ export async function buildKickMemberTransaction(
     const [groupPDA] = getGroupPDA(params.groupId);
     const [kickerMemberPDA] = getGroupMemberPDA(params.groupId, params.kickerPubkey);
     const [kickedMemberPDA] = getGroupMemberPDA(params.groupId, params.kickedUserPubkey);
+    const kickedMember = await getGroupMemberAccount(params.groupId, params.kickedUserPubkey);
+    const rentPayer = kickedMember
+        ? new PublicKey(kickedMember.rentPayer)
+        : params.kickedUserPubkey;
 
     const instructionData = buildKickMemberData(params);
     const kickIx = new TransactionInstruction({
@@ -580,8 +778,11 @@ export async function buildKickMemberTransaction(
             { pubkey: kickerMemberPDA, isSigner: false, isWritable: false },
             { pubkey: kickedMemberPDA, isSigner: false, isWritable: true },
             { pubkey: params.kickedUserPubkey, isSigner: false, isWritable: false },
+            { pubkey: rentPayer, isSigner: false, isWritable: true },
+            { pubkey: getGroupRolesPDA(params.groupId)[0], isSigner: false, isWritable: false },
             { pubkey: params.kickerPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -618,7 +819,9 @@ export async function buildUpdateMemberRoleTransaction(
             { pubkey: updaterMemberPDA, isSigner: false, isWritable: false },
             { pubkey: targetMemberPDA, isSigner: false, isWritable: true },
             { pubkey: params.targetUserPubkey, isSigner: false, isWritable: false },
+            { pubkey: getGroupRolesPDA(params.groupId)[0], isSigner: false, isWritable: false },
             { pubkey: params.updaterPubkey, isSigner: true, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -668,6 +871,7 @@ export async function buildCreateInviteLinkTransaction(
             { pubkey: inviteLinkPDA, isSigner: false, isWritable: true },
             { pubkey: params.creatorPubkey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -704,6 +908,7 @@ export async function buildRevokeInviteLinkTransaction(
             { pubkey: revokerMemberPDA, isSigner: false, isWritable: false },
             { pubkey: inviteLinkPDA, isSigner: false, isWritable: true },
             { pubkey: params.revokerPubkey, isSigner: true, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -742,7 +947,9 @@ export async function getGroupAccount(groupId: Buffer): Promise<{
     enableReactions: boolean;
     enableReadReceipts: boolean;
     enableTypingIndicators: boolean;
-    groupEncryptionKey: string; // Base64
+    keyCommitment: string; // Base64
+    keyEpoch: number;
+    dissolvedAt: number; // 0 = active
     memberCount: number;
     createdAt: number;
     updatedAt: number;
@@ -810,8 +1017,8 @@ export async function getGroupAccount(groupId: Buffer): Promise<{
         const enableTypingIndicators = data.readUInt8(offset) === 1;
         offset += 1;
 
-        // Encryption key (32 bytes)
-        const groupEncryptionKey = data.slice(offset, offset + 32).toString('base64');
+        // Group key commitment (32 bytes)
+        const keyCommitment = data.slice(offset, offset + 32).toString('base64');
         offset += 32;
 
         // u16 member count
@@ -824,6 +1031,13 @@ export async function getGroupAccount(groupId: Buffer): Promise<{
         const updatedAt = Number(data.readBigInt64LE(offset));
         offset += 8;
 
+        // u8 bump, then the u32 key epoch and i64 dissolution timestamp
+        offset += 1;
+        const keyEpoch = data.readUInt32LE(offset);
+        offset += 4;
+        const dissolvedAt = Number(data.readBigInt64LE(offset));
+        offset += 8;
+
         return {
             owner,
             groupId: groupIdBytes.toString('hex'),
@@ -841,7 +1055,9 @@ export async function getGroupAccount(groupId: Buffer): Promise<{
             enableReactions,
             enableReadReceipts,
             enableTypingIndicators,
-            groupEncryptionKey,
+            keyCommitment,
+            keyEpoch,
+            dissolvedAt,
             memberCount,
             createdAt,
             updatedAt,
@@ -867,6 +1083,11 @@ export async function getGroupMemberAccount(groupId: Buffer, memberPubkey: Publi
     isMuted: boolean;
     isBanned: boolean;
     invitedBy: string;
+    keyEpoch: number; // Stale if behind the group's keyEpoch
+    rentPayer: string; // Wallet refunded when the membership is closed
+    roleId: number; // Custom role from the group's role table (0 = none)
+    mutedUntil: number; // When isMuted ends (0 = until unmuted)
+    notificationsMuted: boolean; // Muted by the member, for themselves
 } | null> {
     try {
         const [pda] = getGroupMemberPDA(groupId, memberPubkey);
@@ -911,6 +1132,23 @@ export async function getGroupMemberAccount(groupId: Buffer, memberPubkey: Publi
         const invitedBy = new PublicKey(data.slice(offset, offset + 32)).toBase58();
         offset += 32;
 
+        // u8 bump, then the u32 key epoch (absent on members from before key epochs)
+        offset += 1;
+        const keyEpoch = data.length >= offset + 4 ? data.readUInt32LE(offset) : 0;
+        offset += 4;
+
+        // Rent payer, then the custom role id and mute fields (absent on older members)
+        const rentPayerBytes = data.slice(offset, offset + 32);
+        const rentPayer = rentPayerBytes.length === 32 && rentPayerBytes.some((byte) => byte !== 0)
+            ? new PublicKey(rentPayerBytes).toBase58()
+            : member;
+        offset += 32;
+        const roleId = data.length > offset ? data.readUInt8(offset) : 0;
+        offset += 1;
+        const mutedUntil = data.length >= offset + 8 ? Number(data.readBigInt64LE(offset)) : 0;
+        offset += 8;
+        const notificationsMuted = data.length > offset && data.readUInt8(offset) === 1;
+
         return {
             groupId: groupIdBytes.toString('hex'),
             member,
@@ -923,6 +1161,11 @@ export async function getGroupMemberAccount(groupId: Buffer, memberPubkey: Publi
             isMuted,
             isBanned,
             invitedBy,
+            keyEpoch,
+            rentPayer,
+            roleId,
+            mutedUntil,
+            notificationsMuted,
         };
     } catch (error) {
         console.error('Error fetching group member account:', error);
@@ -930,6 +1173,16 @@ export async function getGroupMemberAccount(groupId: Buffer, memberPubkey: Publi
     }
 }
 
+/**
+ * Whether a moderator mute currently stops the member from sending
+ */
+export function isMemberMuted(
+    member: { isMuted: boolean; mutedUntil: number },
+    now: number = Math.floor(Date.now() / 1000)
+): boolean {
+    return member.isMuted && (member.mutedUntil === 0 || now < member.mutedUntil);
+}
+
 /**
  * Lookup group by public code
  */
@@ -9,6 +9,7 @@ import {
     TransactionInstruction,
 } from '@solana/web3.js';
 import bs58 from 'bs58';
+import { createHash } from 'crypto';
 import { config } from '../config.js';
 
 // Program ID from deployed Anchor program
@@ -56,6 +57,113 @@ export function getUsernamePDA(username: string): [PublicKey, number] {
     );
 }
 
+/**
+ * Get the PDA for an owner's reverse lookup (wallet -> username)
+ */
+export function getOwnerLookupPDA(owner: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('owner'), owner.toBuffer()],
+        PROGRAM_ID
+    );
+}
+
+/**
+ * Get the PDA for a username's encryption key history
+ */
+export function getKeyHistoryPDA(username: string): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('key_history'), Buffer.from(username.toLowerCase())],
+        PROGRAM_ID
+    );
+}
+
+/**
+ * Get the PDA for a username's pending transfer
+ */
+export function getPendingTransferPDA(username: string): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from('transfer'), Buffer.from(username.toLowerCase())],
+        PROGRAM_ID
+    );
+}
+
+/**
+ * Get the PDA for the program config (authority, pause flags and length limits)
+ */
+export function getProgramConfigPDA(): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([Buffer.from('program_config')], PROGRAM_ID);
+}
+
+/**
+ * Get the PDA for the registry config (fee schedule and reserved names)
+ */
+export function getRegistryConfigPDA(): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([Buffer.from('registry_config')], PROGRAM_ID);
+}
+
+/**
+ * Get the PDA for the treasury collecting registration fees
+ */
+export function getTreasuryPDA(): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([Buffer.from('treasury')], PROGRAM_ID);
+}
+
+/**
+ * Registration fee in lamports for a username, from the registry config's
+ * fee schedule (first tier whose max length covers the name)
+ */
+export async function getRegistrationFee(username: string): Promise<number> {
+    const [configPDA] = getRegistryConfigPDA();
+    const configInfo = await connection.getAccountInfo(configPDA);
+    if (!configInfo) {
+        throw new Error('Registry config not initialized');
+    }
+
+    // 8 (discriminator) + 32 (authority), then a vec of (1 max_length + 8 fee)
+    const data = configInfo.data;
+    const tierCount = data.readUInt32LE(40);
+    for (let i = 0; i < tierCount; i++) {
+        const offset = 44 + i * 9;
+        if (username.length <= data[offset]) {
+            return Number(data.readBigUInt64LE(offset + 1));
+        }
+    }
+    return 0;
+}
+
+// 8 (discriminator) + 32 (owner) + 4+20 (username string) + 1 (bump)
+const OWNER_LOOKUP_SPACE = 8 + 32 + 4 + 20 + 1;
+
+// 8 (discriminator) + 32 (owner) + 4+20 (username) + 8 (created_at) + 1 (bump)
+// + 32 (encryption key) + 4+8*4 (device ids) + 8 (expires_at)
+const USER_ACCOUNT_SPACE = 8 + 32 + 4 + 20 + 8 + 1 + 32 + 4 + 8 * 4 + 8;
+
+// 8 (discriminator) + 4+20 (username) + 4+16 entries of
+// (32 key + 8 valid_from + 8 valid_until + 1 reason + 8 compromised_at) + 1 (bump)
+const KEY_HISTORY_SPACE = 8 + 4 + 20 + 4 + 16 * (32 + 8 + 8 + 1 + 8) + 1;
+
+// Anchor account discriminator; matches UserAccount of any layout version
+const USER_ACCOUNT_DISCRIMINATOR = bs58.encode(
+    createHash('sha256').update('account:UserAccount').digest().subarray(0, 8)
+);
+
+/**
+ * Read a username's lease expiry (0 = never), given the offset of its device
+ * list. Accounts on a layout from before leases never expire: the bytes after
+ * their device list may be stale device ids
+ */
+function readLeaseExpiry(data: Buffer, devicesOffset: number): number {
+    if (data.length < USER_ACCOUNT_SPACE) {
+        return 0;
+    }
+    const deviceCount = data.readUInt32LE(devicesOffset);
+    return Number(data.readBigInt64LE(devicesOffset + 4 + deviceCount * 4));
+}
+
+function isLeaseExpired(expiresAt: number): boolean {
+    return expiresAt !== 0 && Date.now() / 1000 >= expiresAt;
+}
+
 /**
  * Check if a username is available (checks if PDA exists)
  */
@@ -74,6 +182,8 @@ export async function getUserAccount(username: string): Promise<{
     createdAt: number;
     bump: number;
     encryptionKey: string; // Base64 encoded
+    expiresAt: number; // Unix seconds, 0 = never
+    expired: boolean; // Expired names must not be encrypted to
 } | null> {
     try {
         const [pda] = getUsernamePDA(username);
@@ -114,6 +224,9 @@ export async function getUserAccount(username: string): Promise<{
         const encryptionKey = encryptionKeyBuf.length === 32
             ? encryptionKeyBuf.toString('base64')
             : '';
+        offset += 32;
+
+        const expiresAt = readLeaseExpiry(data, offset);
 
         return {
             owner,
@@ -121,6 +234,8 @@ export async function getUserAccount(username: string): Promise<{
             createdAt,
             bump,
             encryptionKey,
+            expiresAt,
+            expired: isLeaseExpired(expiresAt),
         };
     } catch (error) {
         console.error('Error fetching user account:', error);
@@ -137,11 +252,26 @@ export async function findUserByOwner(ownerPubkey: string): Promise<{
     createdAt: number;
     bump: number;
     encryptionKey: string;
+    expiresAt: number;
+    expired: boolean;
 } | null> {
     try {
         const owner = new PublicKey(ownerPubkey);
+
+        // Fast path: the owner lookup PDA points straight at the username
+        const [lookupPDA] = getOwnerLookupPDA(owner);
+        const lookupInfo = await connection.getAccountInfo(lookupPDA);
+        if (lookupInfo) {
+            const data = lookupInfo.data;
+            const usernameLen = data.readUInt32LE(8 + 32);
+            const username = data.slice(8 + 32 + 4, 8 + 32 + 4 + usernameLen).toString('utf-8');
+            return getUserAccount(username);
+        }
+
+        // Usernames registered before reverse lookups existed need a scan
         const accounts = await connection.getProgramAccounts(PROGRAM_ID, {
             filters: [
+                { memcmp: { offset: 0, bytes: USER_ACCOUNT_DISCRIMINATOR } },
                 {
                     memcmp: {
                         offset: 8, // discriminator
@@ -171,6 +301,9 @@ export async function findUserByOwner(ownerPubkey: string): Promise<{
         const encryptionKey = encryptionKeyBuf.length === 32
             ? encryptionKeyBuf.toString('base64')
             : '';
+        offset += 32;
+
+        const expiresAt = readLeaseExpiry(data, offset);
 
         return {
             owner: ownerKey,
@@ -178,6 +311,8 @@ export async function findUserByOwner(ownerPubkey: string): Promise<{
             createdAt,
             bump,
             encryptionKey,
+            expiresAt,
+            expired: isLeaseExpired(expiresAt),
         };
     } catch (error) {
         console.error('Error finding user by owner:', error);
@@ -231,8 +366,12 @@ export async function registerUsernameOnChain(
     console.log(`   Owner/Payer: ${feePayer.publicKey.toBase58()}`);
 
     // 8 (discriminator) + 32 (owner) + 4+20 (username string) + 8 (created_at) + 1 (bump) + 32 (encryption_key)
-    const space = 8 + 32 + 4 + 20 + 8 + 1 + 32;
+    const space = USER_ACCOUNT_SPACE;
     const lamports = await connection.getMinimumBalanceForRentExemption(space);
+    const [ownerLookupPDA] = getOwnerLookupPDA(feePayer.publicKey);
+    const [keyHistoryPDA] = getKeyHistoryPDA(username);
+    const [registryConfigPDA] = getRegistryConfigPDA();
+    const [treasuryPDA] = getTreasuryPDA();
 
     const encryptionKeyBuf = Buffer.from(encryptionKey, 'base64');
     const instructionData = buildRegisterUsernameData(username, encryptionKeyBuf);
@@ -240,8 +379,15 @@ export async function registerUsernameOnChain(
     const instruction = new TransactionInstruction({
         keys: [
             { pubkey: userAccountPDA, isSigner: false, isWritable: true },
+            { pubkey: ownerLookupPDA, isSigner: false, isWritable: true },
+            { pubkey: keyHistoryPDA, isSigner: false, isWritable: true },
+            { pubkey: registryConfigPDA, isSigner: false, isWritable: false },
+            { pubkey: treasuryPDA, isSigner: false, isWritable: true },
             { pubkey: feePayer.publicKey, isSigner: true, isWritable: true },
+            // No program authority co-signer (reserved names are assigned separately)
+            { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -284,6 +430,10 @@ export async function buildRegisterUsernameTransaction(
     const feePayer = getFeePayer();
     const owner = new PublicKey(ownerPubkey);
     const [userAccountPDA] = getUsernamePDA(username);
+    const [ownerLookupPDA] = getOwnerLookupPDA(owner);
+    const [keyHistoryPDA] = getKeyHistoryPDA(username);
+    const [registryConfigPDA] = getRegistryConfigPDA();
+    const [treasuryPDA] = getTreasuryPDA();
 
     console.log(`📦 Building registration tx for @${username}...`);
     console.log(`   Owner: ${ownerPubkey.slice(0, 8)}...`);
@@ -293,19 +443,26 @@ export async function buildRegisterUsernameTransaction(
     const encryptionKeyBuf = Buffer.from(encryptionKey, 'base64');
     const instructionData = buildRegisterUsernameData(username, encryptionKeyBuf);
 
-    // Calculate rent for the PDA account
-    const accountSpace = 8 + 32 + 4 + 20 + 8 + 1 + 32; // discriminator + struct
-    const pdaRentLamports = await connection.getMinimumBalanceForRentExemption(accountSpace);
+    // Calculate rent for the PDA accounts (username + owner lookup + key history)
+    const accountSpace = USER_ACCOUNT_SPACE;
+    const pdaRentLamports =
+        (await connection.getMinimumBalanceForRentExemption(accountSpace)) +
+        (await connection.getMinimumBalanceForRentExemption(OWNER_LOOKUP_SPACE)) +
+        (await connection.getMinimumBalanceForRentExemption(KEY_HISTORY_SPACE));
 
     // Owner account must ALSO remain rent-exempt after paying for PDA creation
     // A standard 0-data account requires ~890880 lamports to be rent-exempt
     const ownerRentExempt = await connection.getMinimumBalanceForRentExemption(0);
 
-    // Total = PDA rent + owner rent-exempt minimum + small buffer
+    // Short names carry a registration fee paid into the treasury
+    const registrationFee = await getRegistrationFee(username);
+
+    // Total = PDA rent + registration fee + owner rent-exempt minimum + small buffer
     // This ensures the owner has enough to pay for PDA AND stay rent-exempt
-    const totalFunding = pdaRentLamports + ownerRentExempt + 100_000;
+    const totalFunding = pdaRentLamports + registrationFee + ownerRentExempt + 100_000;
 
     console.log(`   PDA rent: ${pdaRentLamports} lamports`);
+    console.log(`   Registration fee: ${registrationFee} lamports`);
     console.log(`   Owner rent-exempt: ${ownerRentExempt} lamports`);
     console.log(`   Total funding: ${totalFunding} lamports (~${(totalFunding / 1e9).toFixed(4)} SOL)`);
 
@@ -318,8 +475,15 @@ export async function buildRegisterUsernameTransaction(
     const instruction = new TransactionInstruction({
         keys: [
             { pubkey: userAccountPDA, isSigner: false, isWritable: true },
+            { pubkey: ownerLookupPDA, isSigner: false, isWritable: true },
+            { pubkey: keyHistoryPDA, isSigner: false, isWritable: true },
+            { pubkey: registryConfigPDA, isSigner: false, isWritable: false },
+            { pubkey: treasuryPDA, isSigner: false, isWritable: true },
             { pubkey: owner, isSigner: true, isWritable: true }, // User is owner and signer
+            // No program authority co-signer (reserved names are assigned separately)
+            { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -424,6 +588,9 @@ function buildCloseAccountData(username: string): Buffer {
 export async function closeUsernameOnChain(username: string): Promise<string> {
     const feePayer = getFeePayer();
     const [userAccountPDA] = getUsernamePDA(username);
+    const [ownerLookupPDA] = getOwnerLookupPDA(feePayer.publicKey);
+    const [keyHistoryPDA] = getKeyHistoryPDA(username);
+    const [pendingTransferPDA] = getPendingTransferPDA(username);
 
     console.log(`🗑️ Closing account for @${username}...`);
     console.log(`   PDA: ${userAccountPDA.toBase58()}`);
@@ -434,7 +601,13 @@ export async function closeUsernameOnChain(username: string): Promise<string> {
     const instruction = new TransactionInstruction({
         keys: [
             { pubkey: userAccountPDA, isSigner: false, isWritable: true },
+            { pubkey: ownerLookupPDA, isSigner: false, isWritable: true },
+            { pubkey: keyHistoryPDA, isSigner: false, isWritable: true },
+            { pubkey: pendingTransferPDA, isSigner: false, isWritable: true },
+            // A pending transfer is refunded to whoever proposed it: the owner
+            { pubkey: feePayer.publicKey, isSigner: false, isWritable: true },
             { pubkey: feePayer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
@@ -475,6 +648,9 @@ export async function buildCloseUsernameTransaction(
     const feePayer = getFeePayer();
     const owner = new PublicKey(ownerPubkey);
     const [userAccountPDA] = getUsernamePDA(username);
+    const [ownerLookupPDA] = getOwnerLookupPDA(owner);
+    const [keyHistoryPDA] = getKeyHistoryPDA(username);
+    const [pendingTransferPDA] = getPendingTransferPDA(username);
 
     console.log(`📦 Building close tx for @${username}...`);
     console.log(`   Owner: ${ownerPubkey}`);
@@ -485,7 +661,13 @@ export async function buildCloseUsernameTransaction(
     const instruction = new TransactionInstruction({
         keys: [
             { pubkey: userAccountPDA, isSigner: false, isWritable: true },
+            { pubkey: ownerLookupPDA, isSigner: false, isWritable: true },
+            { pubkey: keyHistoryPDA, isSigner: false, isWritable: true },
+            { pubkey: pendingTransferPDA, isSigner: false, isWritable: true },
+            // A pending transfer is refunded to whoever proposed it: the owner
+            { pubkey: owner, isSigner: false, isWritable: true },
             { pubkey: owner, isSigner: true, isWritable: true }, // User must sign
+            { pubkey: getProgramConfigPDA()[0], isSigner: false, isWritable: false },
         ],
         programId: PROGRAM_ID,
         data: instructionData,
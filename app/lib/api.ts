@@ -64,6 +64,7 @@ export interface BuildGroupTransactionResponse {
     groupId: string;
 }
 
+import * as Crypto from 'expo-crypto';
 import { getStoredKeypair } from './keychain';
 import { signMessage, uint8ToBase64, signTransaction, getEncryptionKeypair } from './crypto';
 
@@ -636,9 +637,16 @@ export async function buildCreateGroupTransaction(
         throw new Error('No identity keypair found');
     }
 
-    // Generate group encryption key (32 bytes)
+    // Generate group encryption key (32 bytes). Only its SHA-256 commitment
+    // goes on chain; members hold encrypted copies of the key itself
     const groupEncryptionKey = crypto.getRandomValues(new Uint8Array(32));
-    const groupEncryptionKeyBase64 = uint8ToBase64(groupEncryptionKey);
+    const keyCommitment = new Uint8Array(
+        await Crypto.digest(Crypto.CryptoDigestAlgorithm.SHA256, groupEncryptionKey)
+    );
+
+    // For now, use a placeholder for the owner's encrypted copy
+    // In production, this should be the group key encrypted with the owner's X25519 key
+    const ownerEncryptedGroupKey = crypto.getRandomValues(new Uint8Array(64));
 
     const response = await fetch(`${API_BASE_URL}/api/groups/build-create-transaction`, {
         method: 'POST',
@@ -651,7 +659,8 @@ export async function buildCreateGroupTransaction(
             inviteOnly,
             maxMembers,
             allowMemberInvites,
-            groupEncryptionKey: groupEncryptionKeyBase64,
+            keyCommitment: uint8ToBase64(keyCommitment),
+            ownerEncryptedGroupKey: uint8ToBase64(ownerEncryptedGroupKey),
             ownerPubkey,
         }),
     });
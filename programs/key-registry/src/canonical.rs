@@ -0,0 +1,128 @@
+//! Canonical forms of the names users pick: usernames, group public codes
+//! and invite codes.
+//!
+//! Every PDA derived from one of these names is seeded with [`normalize`],
+//! and every name stored on chain is the canonical form returned by the
+//! validators below, so `Alice` and `alice` can never be two accounts.
+//! Usernames and public codes are also checked against reserved words,
+//! including lookalike spellings such as `adm1n` or `supp0rt`.
+
+use std::ops::RangeInclusive;
+
+use anchor_lang::prelude::*;
+
+use crate::{GroupError, KeyError};
+
+/// Names that could pass for the app itself or its staff
+pub const RESERVED_NAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "anchor",
+    "everyone",
+    "help",
+    "helpdesk",
+    "key",
+    "keyapp",
+    "keyregistry",
+    "mod",
+    "moderator",
+    "null",
+    "official",
+    "root",
+    "security",
+    "solana",
+    "staff",
+    "support",
+    "system",
+    "team",
+    "undefined",
+];
+
+/// The form a name takes in PDA seeds (ASCII lowercase)
+pub fn normalize(raw: &str) -> String {
+    raw.to_ascii_lowercase()
+}
+
+/// Validate a username and return its canonical form
+/// Letters, numbers and underscores within `length`, not a reserved name
+pub fn username(raw: &str, length: RangeInclusive<usize>) -> Result<String> {
+    let username = username_syntax(raw, length)?;
+    require!(!is_reserved(&username), KeyError::ReservedUsername);
+    Ok(username)
+}
+
+/// Validate a username's length and characters and return its canonical
+/// form, without checking it against reserved names
+pub fn username_syntax(raw: &str, length: RangeInclusive<usize>) -> Result<String> {
+    require!(length.contains(&raw.len()), KeyError::InvalidUsernameLength);
+    require!(
+        raw.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        KeyError::InvalidUsernameCharacters
+    );
+
+    Ok(normalize(raw))
+}
+
+/// Validate a group public code and return its canonical form
+/// 3-20 letters, numbers and hyphens, not a reserved name
+pub fn public_code(raw: &str) -> Result<String> {
+    require!(
+        raw.len() >= 3 && raw.len() <= 20,
+        GroupError::InvalidPublicCodeLength
+    );
+    require!(
+        raw.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+        GroupError::InvalidPublicCodeCharacters
+    );
+
+    let public_code = normalize(raw);
+    require!(!is_reserved(&public_code), GroupError::ReservedPublicCode);
+    Ok(public_code)
+}
+
+/// Validate an invite code and return its canonical form
+/// 8-16 letters and numbers; codes are random, so no reserved words
+pub fn invite_code(raw: &str) -> Result<String> {
+    require!(
+        raw.len() >= 8 && raw.len() <= 16,
+        GroupError::InvalidInviteCodeLength
+    );
+    require!(
+        raw.chars().all(|c| c.is_ascii_alphanumeric()),
+        GroupError::InvalidInviteCodeCharacters
+    );
+
+    Ok(normalize(raw))
+}
+
+/// Whether a normalized name is a reserved name or looks like one
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| is_lookalike(name, reserved))
+}
+
+/// Whether two normalized names are the same or easily mistaken for one
+/// another
+pub fn is_lookalike(name: &str, other: &str) -> bool {
+    skeleton(name) == skeleton(other)
+}
+
+/// Collapse characters that are easily mistaken for one another, and drop
+/// separators, so lookalike names compare equal
+fn skeleton(name: &str) -> String {
+    let mapped: String = name
+        .chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '3' => 'e',
+            '4' => 'a',
+            '5' => 's',
+            '7' => 't',
+            other => other,
+        })
+        .collect();
+    mapped.replace("rn", "m").replace("vv", "w")
+}
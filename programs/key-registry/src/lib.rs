@@ -1,6 +1,14 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::sysvar::instructions::get_instruction_relative;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use std::ops::RangeInclusive;
 
-declare_id!("3emebPATdE5JTXp7TckzZKbNUhFMka7LdRbeCqpscHc1crpKBZ96Ry9BGbs94fzXRNc5FhVTLQGEsdoPzS2tbDmH");
+pub mod canonical;
+
+declare_id!("96hG67JxhNEptr1LkdtDcrqvtWiHH3x4GibDBcdh4MYQ");
 
 #[program]
 pub mod key_registry {
@@ -12,25 +20,82 @@ pub mod key_registry {
         username: String,
         encryption_key: [u8; 32],
     ) -> Result<()> {
-        // Validate username
-        require!(
-            username.len() >= 3 && username.len() <= 20,
-            KeyError::InvalidUsernameLength
-        );
-        require!(
-            username
-                .chars()
-                .all(|c| c.is_ascii_alphanumeric() || c == '_'),
-            KeyError::InvalidUsernameCharacters
-        );
+        require_not_paused(&ctx.accounts.program_config, Operation::RegisterUsername)?;
+
+        let username = canonical::username_syntax(
+            &username,
+            username_length(&ctx.accounts.program_config.limits),
+        )?;
+
+        // Reserved names, built-in or configured, can only be assigned by the
+        // program authority, who also waives the registration fee
+        let config = &ctx.accounts.registry_config;
+        let fee = match &ctx.accounts.registry_authority {
+            Some(authority) => {
+                require_keys_eq!(
+                    authority.key(),
+                    ctx.accounts.program_config.authority,
+                    ConfigError::NotProgramAuthority
+                );
+                0
+            }
+            None => {
+                require!(
+                    !canonical::is_reserved(&username)
+                        && !config
+                            .reserved_names
+                            .iter()
+                            .any(|reserved| canonical::is_lookalike(&username, reserved)),
+                    KeyError::ReservedUsername
+                );
+
+                let fee = registration_fee(&config.fee_tiers, username.len());
+                pay_registration_fee(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.owner,
+                    &ctx.accounts.treasury,
+                    fee,
+                )?;
+                fee
+            }
+        };
 
         // Initialize the username account
+        let now = Clock::get()?.unix_timestamp;
         let user_account = &mut ctx.accounts.user_account;
         user_account.owner = ctx.accounts.owner.key();
-        user_account.username = username.to_lowercase();
-        user_account.created_at = Clock::get()?.unix_timestamp;
+        user_account.username = username.clone();
+        user_account.created_at = now;
         user_account.bump = ctx.bumps.user_account;
         user_account.encryption_key = encryption_key;
+        user_account.devices = Vec::new();
+        user_account.expires_at = lease_expiry(config, now);
+
+        // Initialize the reverse lookup (one username per wallet)
+        let owner_lookup = &mut ctx.accounts.owner_lookup;
+        owner_lookup.owner = ctx.accounts.owner.key();
+        owner_lookup.username = user_account.username.clone();
+        owner_lookup.bump = ctx.bumps.owner_lookup;
+
+        // Start the key history with the registration key
+        let key_history = &mut ctx.accounts.key_history;
+        key_history.username = user_account.username.clone();
+        key_history.entries = Vec::new();
+        key_history.bump = ctx.bumps.key_history;
+        record_key_change(
+            key_history,
+            encryption_key,
+            user_account.created_at,
+            KeyChangeReason::Registered,
+        );
+
+        emit!(UsernameRegistered {
+            username: username.clone(),
+            owner: user_account.owner,
+            encryption_key,
+            fee,
+            expires_at: user_account.expires_at,
+        });
 
         msg!(
             "Username @{} registered for {}",
@@ -42,30 +107,135 @@ pub mod key_registry {
     }
 
     /// Lookup a username to get the owner's public key
-    pub fn lookup_username(_ctx: Context<LookupUsername>) -> Result<()> {
+    /// Fails for usernames whose lease has expired
+    pub fn lookup_username(ctx: Context<LookupUsername>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::LookupUsername)?;
+
         // The account data is returned automatically by Anchor
         // This instruction is mainly for on-chain verification
         Ok(())
     }
 
-    /// Transfer username ownership (optional - for future use)
-    pub fn transfer_username(ctx: Context<TransferUsername>, new_owner: Pubkey) -> Result<()> {
-        let user_account = &mut ctx.accounts.user_account;
+    /// Propose handing a username to another wallet
+    /// The recipient must accept, and can only do so once `timelock` seconds
+    /// have passed (0 = no timelock)
+    pub fn propose_transfer(
+        ctx: Context<ProposeTransfer>,
+        _username: String,
+        new_owner: Pubkey,
+        timelock: i64,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ProposeTransfer)?;
+
+        require!(
+            new_owner != ctx.accounts.owner.key(),
+            KeyError::CannotTransferToSelf
+        );
+        require!(
+            (0..=MAX_TRANSFER_TIMELOCK).contains(&timelock),
+            KeyError::InvalidTransferTimelock
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let pending = &mut ctx.accounts.pending_transfer;
+        pending.username = ctx.accounts.user_account.username.clone();
+        pending.current_owner = ctx.accounts.owner.key();
+        pending.new_owner = new_owner;
+        pending.proposed_at = now;
+        pending.unlocks_at = now + timelock;
+        pending.bump = ctx.bumps.pending_transfer;
+
+        emit!(UsernameTransferProposed {
+            username: pending.username.clone(),
+            current_owner: pending.current_owner,
+            new_owner,
+            unlocks_at: pending.unlocks_at,
+        });
+
+        msg!(
+            "Transfer of @{} proposed from {} to {} (unlocks at {})",
+            pending.username,
+            pending.current_owner,
+            new_owner,
+            pending.unlocks_at
+        );
+
+        Ok(())
+    }
+
+    /// Accept a proposed username transfer
+    /// The recipient supplies their own X25519 key so messages stop being
+    /// encrypted to the previous owner
+    pub fn accept_transfer(
+        ctx: Context<AcceptTransfer>,
+        _username: String,
+        encryption_key: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::AcceptTransfer)?;
 
         require!(
-            user_account.owner == ctx.accounts.current_owner.key(),
-            KeyError::NotOwner
+            Clock::get()?.unix_timestamp >= ctx.accounts.pending_transfer.unlocks_at,
+            KeyError::TransferLocked
+        );
+
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.owner = ctx.accounts.new_owner.key();
+        user_account.encryption_key = encryption_key;
+        // Devices belonged to the previous owner
+        user_account.devices.clear();
+
+        // Old owner's lookup is closed by Anchor; point the new one at the username
+        let new_owner_lookup = &mut ctx.accounts.new_owner_lookup;
+        new_owner_lookup.owner = user_account.owner;
+        new_owner_lookup.username = user_account.username.clone();
+        new_owner_lookup.bump = ctx.bumps.new_owner_lookup;
+
+        record_key_change(
+            &mut ctx.accounts.key_history,
+            encryption_key,
+            Clock::get()?.unix_timestamp,
+            KeyChangeReason::Transferred,
         );
 
-        user_account.owner = new_owner;
+        emit!(UsernameTransferred {
+            username: user_account.username.clone(),
+            previous_owner: ctx.accounts.current_owner.key(),
+            new_owner: user_account.owner,
+            encryption_key,
+        });
 
         msg!(
             "Username @{} transferred from {} to {}",
             user_account.username,
             ctx.accounts.current_owner.key(),
-            new_owner
+            user_account.owner
+        );
+
+        // Pending transfer will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Cancel a proposed transfer (either party may cancel)
+    /// The rent lamports are returned to the proposing owner
+    pub fn cancel_transfer(ctx: Context<CancelTransfer>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::CancelTransfer)?;
+
+        let pending = &ctx.accounts.pending_transfer;
+        emit!(UsernameTransferCancelled {
+            username: pending.username.clone(),
+            current_owner: pending.current_owner,
+            new_owner: pending.new_owner,
+            cancelled_by: ctx.accounts.signer.key(),
+        });
+
+        msg!(
+            "Transfer of @{} to {} cancelled by {}",
+            ctx.accounts.pending_transfer.username,
+            ctx.accounts.pending_transfer.new_owner,
+            ctx.accounts.signer.key()
         );
 
+        // Account will be closed automatically by Anchor's close constraint
         Ok(())
     }
 
@@ -73,12 +243,19 @@ pub mod key_registry {
     /// The rent lamports are returned to the owner
     /// This allows the username to be claimed by someone else
     pub fn close_account(ctx: Context<CloseAccount>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::CloseAccount)?;
+
+        close_pending_transfer(
+            &ctx.accounts.pending_transfer,
+            &ctx.accounts.transfer_proposer,
+        )?;
+
         let user_account = &ctx.accounts.user_account;
 
-        require!(
-            user_account.owner == ctx.accounts.owner.key(),
-            KeyError::NotOwner
-        );
+        emit!(UsernameReleased {
+            username: user_account.username.clone(),
+            owner: user_account.owner,
+        });
 
         msg!(
             "Username @{} released by {}",
@@ -86,963 +263,7251 @@ pub mod key_registry {
             ctx.accounts.owner.key()
         );
 
-        // Account will be closed automatically by Anchor's close constraint
+        // The other accounts will be closed automatically by Anchor's close constraint
         Ok(())
     }
-    pub fn update_encryption_key(
-        ctx: Context<UpdateEncryptionKey>,
-        new_encryption_key: [u8; 32],
-    ) -> Result<()> {
+
+    /// Extend a username's lease by the current lease duration, paying the
+    /// registration fee again. Expired names can be renewed until reclaimed
+    pub fn renew_username(ctx: Context<RenewUsername>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RenewUsername)?;
+
+        let expires_at = lease_expires_at(&ctx.accounts.user_account);
+        require!(expires_at != 0, KeyError::NoLease);
+
+        let config = &ctx.accounts.registry_config;
+        let fee = registration_fee(&config.fee_tiers, ctx.accounts.user_account.username.len());
+        pay_registration_fee(
+            &ctx.accounts.system_program,
+            &ctx.accounts.owner,
+            &ctx.accounts.treasury,
+            fee,
+        )?;
+
+        // With leases switched off since, renewing makes the name permanent
+        let now = Clock::get()?.unix_timestamp;
         let user_account = &mut ctx.accounts.user_account;
+        user_account.expires_at = lease_expiry(config, expires_at.max(now));
 
-        require!(
-            user_account.owner == ctx.accounts.owner.key(),
-            KeyError::NotOwner
-        );
+        emit!(UsernameRenewed {
+            username: user_account.username.clone(),
+            owner: user_account.owner,
+            fee,
+            expires_at: user_account.expires_at,
+        });
 
-        user_account.encryption_key = new_encryption_key;
+        msg!(
+            "Username @{} renewed until {}",
+            user_account.username,
+            user_account.expires_at
+        );
 
-        msg!("Encryption key updated for @{}", user_account.username);
         Ok(())
     }
 
-    // ========================================================================
-    // Group Chat Instructions
-    // ========================================================================
-
-    /// Create a new group (public or private)
-    #[allow(clippy::too_many_arguments)]
-    pub fn create_group(
-        ctx: Context<CreateGroup>,
-        group_id: [u8; 32],
-        name: String,
-        description: String,
-        is_public: bool,
-        is_searchable: bool,
-        invite_only: bool,
-        max_members: u16,
-        allow_member_invites: bool,
-        group_encryption_key: [u8; 32],
+    /// Free a username whose lease ran out more than the grace period ago
+    /// Anyone can call this; the rent goes back to the lapsed owner
+    pub fn reclaim_expired_username(
+        ctx: Context<ReclaimExpiredUsername>,
+        _username: String,
     ) -> Result<()> {
-        // Validate group name
-        require!(
-            !name.is_empty() && name.len() <= 100,
-            GroupError::InvalidGroupNameLength
-        );
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::ReclaimExpiredUsername,
+        )?;
 
-        // Validate description
+        let expires_at = lease_expires_at(&ctx.accounts.user_account);
         require!(
-            description.len() <= 500,
-            GroupError::InvalidGroupDescriptionLength
+            expires_at != 0
+                && Clock::get()?.unix_timestamp
+                    >= expires_at + ctx.accounts.registry_config.grace_period,
+            KeyError::LeaseNotReclaimable
         );
 
-        // Initialize group account
-        let group = &mut ctx.accounts.group_account;
-        group.owner = ctx.accounts.owner.key();
-        group.group_id = group_id;
-        group.public_code = String::new(); // Set via set_group_code if needed
-        group.name = name.clone();
-        group.description = description;
-        group.avatar_arweave_id = String::new();
-        group.is_public = is_public;
-        group.is_searchable = is_searchable;
-        group.invite_only = invite_only;
-        group.max_members = max_members;
-        group.allow_member_invites = allow_member_invites;
-        group.require_approval = false;
-        group.enable_replies = true;
-        group.enable_reactions = true;
-        group.enable_read_receipts = true;
-        group.enable_typing_indicators = true;
-        group.group_encryption_key = group_encryption_key;
-        group.member_count = 1; // Owner is first member
-        group.created_at = Clock::get()?.unix_timestamp;
-        group.updated_at = Clock::get()?.unix_timestamp;
-        group.bump = ctx.bumps.group_account;
+        // The reverse lookup and key history go with the name, if the
+        // username is recent enough to have them
+        let owner = ctx.accounts.owner.to_account_info();
+        for account in [&ctx.accounts.owner_lookup, &ctx.accounts.key_history] {
+            if *account.owner == crate::ID {
+                close_program_account(account, &owner)?;
+            }
+        }
+        close_pending_transfer(
+            &ctx.accounts.pending_transfer,
+            &ctx.accounts.transfer_proposer,
+        )?;
 
-        // Initialize owner as first member with Owner role
-        let owner_member = &mut ctx.accounts.owner_member_account;
-        owner_member.group_id = group_id;
-        owner_member.member = ctx.accounts.owner.key();
-        owner_member.role = GroupRole::Owner;
-        owner_member.permissions = 0xFFFF; // All permissions
-        owner_member.encrypted_group_key = [0u8; 64]; // Owner has the key, no need to encrypt
-        owner_member.joined_at = Clock::get()?.unix_timestamp;
-        owner_member.last_read_at = 0;
-        owner_member.is_active = true;
-        owner_member.is_muted = false;
-        owner_member.is_banned = false;
-        owner_member.invited_by = ctx.accounts.owner.key(); // Self-invited
-        owner_member.bump = ctx.bumps.owner_member_account;
+        emit!(UsernameReclaimed {
+            username: ctx.accounts.user_account.username.clone(),
+            previous_owner: ctx.accounts.user_account.owner,
+            reclaimer: ctx.accounts.reclaimer.key(),
+        });
 
         msg!(
-            "Group created: '{}' by {} (public: {})",
-            name,
-            ctx.accounts.owner.key(),
-            is_public
+            "Expired username @{} reclaimed by {}",
+            ctx.accounts.user_account.username,
+            ctx.accounts.reclaimer.key()
         );
 
+        // The username account is closed by Anchor's close constraint
         Ok(())
     }
 
-    /// Set or update a public code for a group
-    pub fn set_group_code(
-        ctx: Context<SetGroupCode>,
-        _group_id: [u8; 32],
-        public_code: String,
-    ) -> Result<()> {
-        // Validate public code
-        require!(
-            public_code.len() >= 3 && public_code.len() <= 20,
-            GroupError::InvalidPublicCodeLength
-        );
-        require!(
-            public_code
-                .chars()
-                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
-            GroupError::InvalidPublicCodeCharacters
-        );
+    /// Grow a username account created under an older layout to the current
+    /// size. Fields added since then start zeroed; anyone can pay the rent
+    pub fn migrate_user_account(ctx: Context<MigrateUserAccount>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::MigrateUserAccount)?;
 
-        // Update group account
-        let group = &mut ctx.accounts.group_account;
-        group.public_code = public_code.clone();
+        let user_account = &ctx.accounts.user_account;
+        let new_len = 8 + UserAccount::INIT_SPACE;
+
+        require!(user_account.data_len() < new_len, KeyError::AlreadyMigrated);
+
+        let shortfall = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(user_account.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: user_account.to_account_info(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        user_account.realloc(new_len, true)?;
 
-        // Initialize lookup account
-        let lookup = &mut ctx.accounts.group_code_lookup;
-        lookup.public_code = public_code.to_lowercase();
-        lookup.group_id = group.group_id;
-        lookup.bump = ctx.bumps.group_code_lookup;
+        // New fields are appended, so zero padding decodes as their defaults.
+        // The lease is the exception: it follows the device list, whose old
+        // entries may have been left behind when it shrank
+        let mut migrated = UserAccount::try_deserialize(&mut &user_account.data.borrow()[..])?;
+        migrated.expires_at = 0;
+        migrated.try_serialize(&mut &mut user_account.data.borrow_mut()[..])?;
+
+        emit!(UserAccountMigrated {
+            username: migrated.username.clone(),
+        });
 
-        msg!("Public code '{}' set for group", lookup.public_code);
+        msg!("Username @{} migrated to current layout", migrated.username);
 
         Ok(())
     }
 
-    /// Join a group (via public code or direct invite)
-    pub fn join_group(
-        ctx: Context<JoinGroup>,
-        group_id: [u8; 32],
-        encrypted_group_key: [u8; 64],
+    /// Create the owner -> username lookup for a username registered before
+    /// reverse lookups existed
+    pub fn backfill_owner_lookup(
+        ctx: Context<BackfillOwnerLookup>,
+        _username: String,
     ) -> Result<()> {
-        let group = &mut ctx.accounts.group_account;
-        let member = &mut ctx.accounts.member_account;
+        require_not_paused(&ctx.accounts.program_config, Operation::BackfillOwnerLookup)?;
 
-        // Check if group has space
-        require!(
-            group.max_members == 0 || group.member_count < group.max_members,
-            GroupError::GroupFull
-        );
+        let user_account = &ctx.accounts.user_account;
 
-        // Initialize member account
-        member.group_id = group_id;
-        member.member = ctx.accounts.new_member.key();
-        member.role = GroupRole::Member;
-        member.permissions = PERM_SEND_MESSAGES;
-        member.encrypted_group_key = encrypted_group_key;
-        member.joined_at = Clock::get()?.unix_timestamp;
-        member.last_read_at = 0;
-        member.is_active = true;
-        member.is_muted = false;
-        member.is_banned = false;
-        member.invited_by = ctx.accounts.new_member.key(); // Will be overridden if invited
-        member.bump = ctx.bumps.member_account;
+        let owner_lookup = &mut ctx.accounts.owner_lookup;
+        owner_lookup.owner = user_account.owner;
+        owner_lookup.username = user_account.username.clone();
+        owner_lookup.bump = ctx.bumps.owner_lookup;
 
-        // Increment member count
-        group.member_count += 1;
-        group.updated_at = Clock::get()?.unix_timestamp;
+        emit!(OwnerLookupBackfilled {
+            username: user_account.username.clone(),
+            owner: user_account.owner,
+        });
 
         msg!(
-            "Member {} joined group (member count: {})",
-            ctx.accounts.new_member.key(),
-            group.member_count
+            "Owner lookup created for @{} ({})",
+            user_account.username,
+            user_account.owner
         );
 
         Ok(())
     }
 
-    /// Leave a group voluntarily
-    pub fn leave_group(ctx: Context<LeaveGroup>, _group_id: [u8; 32]) -> Result<()> {
-        let group = &mut ctx.accounts.group_account;
-        let member = &ctx.accounts.member_account;
+    /// Replace the X25519 encryption key published for a username
+    pub fn update_encryption_key(
+        ctx: Context<UpdateEncryptionKey>,
+        _username: String,
+        new_encryption_key: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::UpdateEncryptionKey)?;
+
+        let key_history = &mut ctx.accounts.key_history;
 
-        // Cannot leave if you're the owner
         require!(
-            member.role != GroupRole::Owner,
-            GroupError::OwnerCannotLeave
+            !is_compromised(key_history, &new_encryption_key),
+            KeyError::KeyCompromised
         );
 
-        // Decrement member count
-        group.member_count = group.member_count.saturating_sub(1);
-        group.updated_at = Clock::get()?.unix_timestamp;
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.encryption_key = new_encryption_key;
 
-        msg!(
-            "Member {} left group (member count: {})",
-            ctx.accounts.member.key(),
-            group.member_count
+        record_key_change(
+            key_history,
+            new_encryption_key,
+            Clock::get()?.unix_timestamp,
+            KeyChangeReason::Rotated,
         );
 
-        // Account will be closed automatically by Anchor's close constraint
+        emit!(EncryptionKeyUpdated {
+            username: user_account.username.clone(),
+            owner: user_account.owner,
+            encryption_key: new_encryption_key,
+        });
+
+        msg!("Encryption key updated for @{}", user_account.username);
         Ok(())
     }
 
-    /// Invite a specific user to the group
-    pub fn invite_member(
-        ctx: Context<InviteMember>,
-        group_id: [u8; 32],
-        encrypted_group_key: [u8; 64],
+    /// Mark a current or past encryption key as compromised since
+    /// `compromised_at`; clients reject ciphertext addressed to it after then
+    /// Revoking the current key requires a replacement in the same instruction
+    pub fn revoke_encryption_key(
+        ctx: Context<RevokeEncryptionKey>,
+        _username: String,
+        key: [u8; 32],
+        compromised_at: i64,
+        replacement_key: Option<[u8; 32]>,
     ) -> Result<()> {
-        let group = &mut ctx.accounts.group_account;
-        let inviter_member = &ctx.accounts.inviter_member_account;
-        let invited_member = &mut ctx.accounts.invited_member_account;
-
-        // Check if inviter has permission
-        let can_invite = inviter_member.role == GroupRole::Owner
-            || inviter_member.role == GroupRole::Admin
-            || inviter_member.role == GroupRole::Moderator
-            || (group.allow_member_invites
-                && inviter_member.permissions & PERM_INVITE_MEMBERS != 0);
-
-        require!(can_invite, GroupError::InsufficientPermissions);
-
-        // Check if group has space
+        require_not_paused(&ctx.accounts.program_config, Operation::RevokeEncryptionKey)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let key_history = &mut ctx.accounts.key_history;
+
+        let entry = key_history
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.key == key)
+            .ok_or(KeyError::KeyNotInHistory)?;
+        require!(entry.compromised_at == 0, KeyError::KeyCompromised);
         require!(
-            group.max_members == 0 || group.member_count < group.max_members,
-            GroupError::GroupFull
+            compromised_at >= entry.valid_from && compromised_at <= now,
+            KeyError::InvalidCompromiseTime
         );
+        entry.compromised_at = compromised_at;
+
+        let user_account = &mut ctx.accounts.user_account;
+        let replaced = user_account.encryption_key == key;
+        if replaced {
+            let replacement_key = replacement_key.ok_or(KeyError::ReplacementKeyRequired)?;
+            require!(
+                !is_compromised(key_history, &replacement_key),
+                KeyError::KeyCompromised
+            );
 
-        // Initialize invited member account
-        invited_member.group_id = group_id;
-        invited_member.member = ctx.accounts.invited_user.key();
-        invited_member.role = GroupRole::Member;
-        invited_member.permissions = PERM_SEND_MESSAGES;
-        invited_member.encrypted_group_key = encrypted_group_key;
-        invited_member.joined_at = Clock::get()?.unix_timestamp;
-        invited_member.last_read_at = 0;
-        invited_member.is_active = true;
-        invited_member.is_muted = false;
-        invited_member.is_banned = false;
-        invited_member.invited_by = ctx.accounts.inviter.key();
-        invited_member.bump = ctx.bumps.invited_member_account;
+            user_account.encryption_key = replacement_key;
+            record_key_change(
+                key_history,
+                replacement_key,
+                now,
+                KeyChangeReason::Compromised,
+            );
+        }
 
-        // Increment member count
-        group.member_count += 1;
-        group.updated_at = Clock::get()?.unix_timestamp;
+        emit!(EncryptionKeyRevoked {
+            username: user_account.username.clone(),
+            key,
+            compromised_at,
+            replacement_key: replacement_key.filter(|_| replaced),
+        });
 
         msg!(
-            "User {} invited to group by {} (member count: {})",
-            ctx.accounts.invited_user.key(),
-            ctx.accounts.inviter.key(),
-            group.member_count
+            "Encryption key revoked for @{} (compromised at {})",
+            user_account.username,
+            compromised_at
         );
 
         Ok(())
     }
 
-    /// Kick/remove a member from the group (moderator+ only)
-    pub fn kick_member(ctx: Context<KickMember>, _group_id: [u8; 32]) -> Result<()> {
-        let group = &mut ctx.accounts.group_account;
-        let kicker_member = &ctx.accounts.kicker_member_account;
-        let kicked_member = &ctx.accounts.kicked_member_account;
-
-        // Permission check
-        let can_kick = kicker_member.role == GroupRole::Owner
-            || kicker_member.role == GroupRole::Admin
-            || kicker_member.role == GroupRole::Moderator;
+    /// Start the key history for a username registered before key histories
+    /// existed, beginning with its current key
+    pub fn init_key_history(ctx: Context<InitKeyHistory>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::InitKeyHistory)?;
 
-        require!(can_kick, GroupError::InsufficientPermissions);
+        let user_account = &ctx.accounts.user_account;
 
-        // Cannot kick owner
-        require!(
-            kicked_member.role != GroupRole::Owner,
-            GroupError::CannotKickOwner
+        let key_history = &mut ctx.accounts.key_history;
+        key_history.username = user_account.username.clone();
+        key_history.entries = Vec::new();
+        key_history.bump = ctx.bumps.key_history;
+        record_key_change(
+            key_history,
+            user_account.encryption_key,
+            Clock::get()?.unix_timestamp,
+            KeyChangeReason::Registered,
         );
 
-        // Cannot kick someone with equal or higher role (except owner can kick anyone)
-        if kicker_member.role != GroupRole::Owner {
-            let kicker_rank = role_to_rank(kicker_member.role);
-            let kicked_rank = role_to_rank(kicked_member.role);
-            require!(
-                kicker_rank > kicked_rank,
-                GroupError::InsufficientPermissions
-            );
-        }
-
-        // Decrement member count
-        group.member_count = group.member_count.saturating_sub(1);
-        group.updated_at = Clock::get()?.unix_timestamp;
+        emit!(KeyHistoryInitialized {
+            username: user_account.username.clone(),
+            encryption_key: user_account.encryption_key,
+        });
 
-        msg!(
-            "Member {} kicked from group by {} (member count: {})",
-            ctx.accounts.kicked_user.key(),
-            ctx.accounts.kicker.key(),
-            group.member_count
-        );
+        msg!("Key history created for @{}", user_account.username);
 
-        // Account will be closed automatically by Anchor's close constraint
         Ok(())
     }
 
-    /// Update a member's role (admin/owner only)
-    pub fn update_member_role(
-        ctx: Context<UpdateMemberRole>,
-        _group_id: [u8; 32],
-        new_role: GroupRole,
+    // ========================================================================
+    // Program Config Instructions
+    // ========================================================================
+
+    /// Create the program config, with nothing paused and the default length
+    /// limits. Only the program's upgrade authority can do this, and only once
+    pub fn init_program_config(ctx: Context<InitProgramConfig>, authority: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.authority = authority;
+        config.pending_authority = Pubkey::default();
+        config.paused = 0;
+        config.limits = LengthLimits {
+            min_username_len: 3,
+            max_username_len: MAX_USERNAME_LEN as u8,
+            max_group_name_len: MAX_GROUP_NAME_LEN as u16,
+            max_group_description_len: MAX_GROUP_DESCRIPTION_LEN as u16,
+        };
+        config.bump = ctx.bumps.program_config;
+
+        emit!(ProgramConfigInitialized {
+            authority,
+            limits: config.limits,
+        });
+
+        msg!("Program config created with authority {}", authority);
+
+        Ok(())
+    }
+
+    /// Propose handing the program authority to another wallet, which must
+    /// accept. Proposing the default pubkey cancels a pending handover
+    pub fn propose_program_authority(
+        ctx: Context<UpdateProgramConfig>,
+        new_authority: Pubkey,
     ) -> Result<()> {
-        let _group = &ctx.accounts.group_account;
-        let updater_member = &ctx.accounts.updater_member_account;
-        let target_member = &mut ctx.accounts.target_member_account;
+        ctx.accounts.program_config.pending_authority = new_authority;
 
-        // Only owner and admin can update roles
-        require!(
-            updater_member.role == GroupRole::Owner || updater_member.role == GroupRole::Admin,
-            GroupError::InsufficientPermissions
-        );
+        emit!(ProgramAuthorityProposed {
+            authority: ctx.accounts.program_config.authority,
+            pending_authority: new_authority,
+        });
 
-        // Cannot change owner role
-        require!(
-            target_member.role != GroupRole::Owner,
-            GroupError::CannotChangeOwnerRole
-        );
+        msg!("Program authority handover to {} proposed", new_authority);
 
-        // Only owner can promote to Admin
-        if new_role == GroupRole::Admin {
-            require!(
-                updater_member.role == GroupRole::Owner,
-                GroupError::InsufficientPermissions
-            );
-        }
+        Ok(())
+    }
 
-        // Update role and permissions
-        let old_role = target_member.role;
-        target_member.role = new_role;
-        target_member.permissions = match new_role {
-            GroupRole::Owner => 0xFFFF, // All permissions
-            GroupRole::Admin => {
-                PERM_SEND_MESSAGES | PERM_INVITE_MEMBERS | PERM_KICK_MEMBERS | PERM_MANAGE_ROLES
-            }
-            GroupRole::Moderator => PERM_SEND_MESSAGES | PERM_INVITE_MEMBERS | PERM_KICK_MEMBERS,
-            GroupRole::Member => PERM_SEND_MESSAGES,
-        };
+    /// Accept a proposed program authority handover
+    pub fn accept_program_authority(ctx: Context<AcceptProgramAuthority>) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        let previous = config.authority;
+        config.authority = config.pending_authority;
+        config.pending_authority = Pubkey::default();
+
+        emit!(ProgramAuthorityAccepted {
+            previous_authority: previous,
+            authority: config.authority,
+        });
 
         msg!(
-            "Member {} role updated from {:?} to {:?} by {}",
-            ctx.accounts.target_user.key(),
-            old_role,
-            new_role,
-            ctx.accounts.updater.key()
+            "Program authority handed from {} to {}",
+            previous,
+            config.authority
         );
 
         Ok(())
     }
 
-    /// Create a temporary invite link for a group
-    pub fn create_invite_link(
-        ctx: Context<CreateInviteLink>,
-        group_id: [u8; 32],
-        invite_code: String,
-        expires_at: i64,
-        max_uses: u16,
+    /// Pause or resume an instruction
+    pub fn set_paused(
+        ctx: Context<UpdateProgramConfig>,
+        operation: Operation,
+        paused: bool,
     ) -> Result<()> {
-        let creator_member = &ctx.accounts.creator_member_account;
-        let invite_link = &mut ctx.accounts.invite_link_account;
-
-        // Permission check
-        let can_create_invite = creator_member.role == GroupRole::Owner
-            || creator_member.role == GroupRole::Admin
-            || creator_member.role == GroupRole::Moderator
-            || (ctx.accounts.group_account.allow_member_invites
-                && creator_member.permissions & PERM_INVITE_MEMBERS != 0);
+        let config = &mut ctx.accounts.program_config;
+        let bit = 1u128 << operation as u8;
+        if paused {
+            config.paused |= bit;
+        } else {
+            config.paused &= !bit;
+        }
 
-        require!(can_create_invite, GroupError::InsufficientPermissions);
+        emit!(OperationPauseUpdated { operation, paused });
 
-        // Validate invite code
-        require!(
-            invite_code.len() >= 8 && invite_code.len() <= 16,
-            GroupError::InvalidInviteCodeLength
+        msg!(
+            "{:?} {}",
+            operation,
+            if paused { "paused" } else { "resumed" }
         );
+
+        Ok(())
+    }
+
+    /// Change the length limits on usernames and group names and
+    /// descriptions, within what their accounts have space for
+    pub fn update_length_limits(
+        ctx: Context<UpdateProgramConfig>,
+        limits: LengthLimits,
+    ) -> Result<()> {
         require!(
-            invite_code.chars().all(|c| c.is_ascii_alphanumeric()),
-            GroupError::InvalidInviteCodeCharacters
+            limits.min_username_len >= 1
+                && limits.min_username_len <= limits.max_username_len
+                && limits.max_username_len as usize <= MAX_USERNAME_LEN
+                && limits.max_group_name_len >= 1
+                && limits.max_group_name_len as usize <= MAX_GROUP_NAME_LEN
+                && limits.max_group_description_len as usize <= MAX_GROUP_DESCRIPTION_LEN,
+            ConfigError::InvalidLengthLimits
         );
 
-        // Initialize invite link
-        invite_link.group_id = group_id;
-        invite_link.invite_code = invite_code.clone();
-        invite_link.created_by = ctx.accounts.creator.key();
-        invite_link.expires_at = expires_at;
-        invite_link.max_uses = max_uses;
-        invite_link.use_count = 0;
-        invite_link.created_at = Clock::get()?.unix_timestamp;
-        invite_link.is_active = true;
-        invite_link.bump = ctx.bumps.invite_link_account;
+        ctx.accounts.program_config.limits = limits;
 
-        msg!(
-            "Invite link '{}' created for group by {}",
-            invite_code,
-            ctx.accounts.creator.key()
-        );
+        emit!(LengthLimitsUpdated { limits });
+
+        msg!("Length limits updated");
 
         Ok(())
     }
 
-    /// Revoke/deactivate an invite link
-    pub fn revoke_invite_link(
-        ctx: Context<RevokeInviteLink>,
-        _group_id: [u8; 32],
-        _invite_code: String,
+    // ========================================================================
+    // Registry Config Instructions
+    // ========================================================================
+
+    /// Create the registry config and fee treasury (program authority only)
+    pub fn init_registry_config(
+        ctx: Context<InitRegistryConfig>,
+        fee_tiers: Vec<FeeTier>,
     ) -> Result<()> {
-        let revoker_member = &ctx.accounts.revoker_member_account;
-        let invite_link = &mut ctx.accounts.invite_link_account;
+        validate_fee_tiers(&fee_tiers)?;
 
-        // Permission check - must be creator, moderator+, or owner
-        let can_revoke = invite_link.created_by == ctx.accounts.revoker.key()
-            || revoker_member.role == GroupRole::Owner
-            || revoker_member.role == GroupRole::Admin
-            || revoker_member.role == GroupRole::Moderator;
+        let config = &mut ctx.accounts.registry_config;
+        config.fee_tiers = fee_tiers.clone();
+        config.reserved_names = Vec::new();
+        config.lease_duration = 0;
+        config.grace_period = MIN_GRACE_PERIOD;
+        config.bump = ctx.bumps.registry_config;
 
-        require!(can_revoke, GroupError::InsufficientPermissions);
+        ctx.accounts.treasury.bump = ctx.bumps.treasury;
 
-        invite_link.is_active = false;
+        emit!(RegistryConfigInitialized {
+            fee_tiers,
+            grace_period: MIN_GRACE_PERIOD,
+        });
 
-        msg!(
-            "Invite link '{}' revoked by {}",
-            invite_link.invite_code,
-            ctx.accounts.revoker.key()
-        );
+        msg!("Registry config created");
 
         Ok(())
     }
 
-    /// Lookup a group by its public code
-    pub fn lookup_group_by_code(_ctx: Context<LookupGroupByCode>) -> Result<()> {
-        // The account data is returned automatically by Anchor
-        // This instruction is mainly for on-chain verification
-        Ok(())
-    }
-}
+    /// Replace the length-based registration fee schedule
+    pub fn update_fee_schedule(
+        ctx: Context<UpdateRegistryConfig>,
+        fee_tiers: Vec<FeeTier>,
+    ) -> Result<()> {
+        validate_fee_tiers(&fee_tiers)?;
+        ctx.accounts.registry_config.fee_tiers = fee_tiers.clone();
 
-// ============================================================================
-// Helper Functions
-// ============================================================================
+        emit!(FeeScheduleUpdated { fee_tiers });
 
-fn role_to_rank(role: GroupRole) -> u8 {
-    match role {
-        GroupRole::Member => 0,
-        GroupRole::Moderator => 1,
-        GroupRole::Admin => 2,
-        GroupRole::Owner => 3,
+        msg!("Registration fee schedule updated");
+
+        Ok(())
     }
-}
 
-// ============================================================================
-// Account Validation Contexts
-// ============================================================================
+    /// Set how long new registrations and renewals last (0 = forever) and
+    /// how long an expired name stays with its owner before it can be
+    /// reclaimed
+    pub fn update_lease_terms(
+        ctx: Context<UpdateRegistryConfig>,
+        lease_duration: i64,
+        grace_period: i64,
+    ) -> Result<()> {
+        require!(
+            (lease_duration == 0
+                || (MIN_LEASE_DURATION..=MAX_LEASE_DURATION).contains(&lease_duration))
+                && (MIN_GRACE_PERIOD..=MAX_GRACE_PERIOD).contains(&grace_period),
+            KeyError::InvalidLeaseTerms
+        );
 
-#[derive(Accounts)]
-#[instruction(username: String)]
-pub struct RegisterUsername<'info> {
-    #[account(
-        init,
-        payer = owner,
-        space = 8 + UserAccount::INIT_SPACE,
-        seeds = [b"username", username.to_lowercase().as_bytes()],
-        bump
-    )]
-    pub user_account: Account<'info, UserAccount>,
+        let config = &mut ctx.accounts.registry_config;
+        config.lease_duration = lease_duration;
+        config.grace_period = grace_period;
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+        emit!(LeaseTermsUpdated {
+            lease_duration,
+            grace_period,
+        });
 
-    pub system_program: Program<'info, System>,
-}
+        msg!(
+            "Lease terms updated: {}s leases, {}s grace period",
+            lease_duration,
+            grace_period
+        );
 
-#[derive(Accounts)]
-#[instruction(username: String)]
-pub struct LookupUsername<'info> {
-    #[account(
-        seeds = [b"username", username.to_lowercase().as_bytes()],
-        bump = user_account.bump
-    )]
-    pub user_account: Account<'info, UserAccount>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct TransferUsername<'info> {
-    #[account(mut)]
-    pub user_account: Account<'info, UserAccount>,
+    /// Reserve a username (and its lookalikes) for the program authority to
+    /// assign
+    pub fn add_reserved_name(ctx: Context<UpdateRegistryConfig>, name: String) -> Result<()> {
+        let name =
+            canonical::username(&name, username_length(&ctx.accounts.program_config.limits))?;
 
-    #[account(mut)]
-    pub current_owner: Signer<'info>,
-}
+        let config = &mut ctx.accounts.registry_config;
+        require!(
+            !config.reserved_names.contains(&name),
+            KeyError::NameAlreadyReserved
+        );
+        require!(
+            config.reserved_names.len() < MAX_RESERVED_NAMES,
+            KeyError::TooManyReservedNames
+        );
+        config.reserved_names.push(name.clone());
 
-#[derive(Accounts)]
-#[instruction(username: String)]
-pub struct CloseAccount<'info> {
-    #[account(
-        mut,
-        seeds = [b"username", username.to_lowercase().as_bytes()],
-        bump = user_account.bump,
-        close = owner  // Returns rent to owner
-    )]
-    pub user_account: Account<'info, UserAccount>,
+        emit!(ReservedNameAdded { name: name.clone() });
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
-}
+        msg!("@{} reserved", name);
 
-#[derive(Accounts)]
-pub struct UpdateEncryptionKey<'info> {
-    #[account(mut)]
-    pub user_account: Account<'info, UserAccount>,
+        Ok(())
+    }
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
-}
+    /// Release a reserved username for anyone to register
+    pub fn remove_reserved_name(ctx: Context<UpdateRegistryConfig>, name: String) -> Result<()> {
+        let name = canonical::normalize(&name);
 
-// ============================================================================
-// Group Chat Contexts
-// ============================================================================
+        let config = &mut ctx.accounts.registry_config;
+        let position = config
+            .reserved_names
+            .iter()
+            .position(|reserved| *reserved == name)
+            .ok_or(KeyError::NameNotReserved)?;
+        config.reserved_names.remove(position);
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32])]
-pub struct CreateGroup<'info> {
-    #[account(
-        init,
-        payer = owner,
-        space = 8 + GroupAccount::INIT_SPACE,
-        seeds = [b"group", group_id.as_ref()],
-        bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+        emit!(ReservedNameRemoved { name: name.clone() });
 
-    #[account(
-        init,
-        payer = owner,
-        space = 8 + GroupMemberAccount::INIT_SPACE,
-        seeds = [b"group:member", group_id.as_ref(), owner.key().as_ref()],
-        bump
-    )]
-    pub owner_member_account: Account<'info, GroupMemberAccount>,
+        msg!("@{} released", name);
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+        Ok(())
+    }
 
-    pub system_program: Program<'info, System>,
-}
+    /// Move collected registration fees out of the treasury
+    /// The treasury keeps its rent-exempt minimum
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        let treasury = ctx.accounts.treasury.to_account_info();
+        let available = treasury
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(treasury.data_len()));
+        require!(amount <= available, KeyError::InsufficientTreasuryBalance);
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32], public_code: String)]
-pub struct SetGroupCode<'info> {
-    #[account(
-        mut,
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump,
-        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+        **treasury.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.recipient.try_borrow_mut_lamports()? += amount;
 
-    #[account(
-        init,
-        payer = owner,
-        space = 8 + GroupCodeLookupAccount::INIT_SPACE,
-        seeds = [b"group:code", public_code.to_lowercase().as_bytes()],
-        bump
-    )]
-    pub group_code_lookup: Account<'info, GroupCodeLookupAccount>,
+        emit!(FeesWithdrawn {
+            amount,
+            recipient: ctx.accounts.recipient.key(),
+        });
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+        msg!(
+            "Withdrew {} lamports of fees to {}",
+            amount,
+            ctx.accounts.recipient.key()
+        );
 
-    pub system_program: Program<'info, System>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32])]
-pub struct JoinGroup<'info> {
-    #[account(
-        mut,
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+    // ========================================================================
+    // Device Instructions
+    // ========================================================================
 
-    #[account(
-        init,
-        payer = new_member,
-        space = 8 + GroupMemberAccount::INIT_SPACE,
-        seeds = [b"group:member", group_id.as_ref(), new_member.key().as_ref()],
-        bump
-    )]
-    pub member_account: Account<'info, GroupMemberAccount>,
+    /// Authorise a new device with its own X25519 encryption key
+    pub fn add_device(
+        ctx: Context<AddDevice>,
+        _username: String,
+        device_id: u32,
+        encryption_key: [u8; 32],
+        label: String,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::AddDevice)?;
+
+        require!(
+            label.len() <= MAX_DEVICE_LABEL_LEN,
+            KeyError::InvalidDeviceLabelLength
+        );
+
+        let user_account = &mut ctx.accounts.user_account;
+        require!(
+            user_account.devices.len() < MAX_DEVICES,
+            KeyError::TooManyDevices
+        );
+        user_account.devices.push(device_id);
+
+        let now = Clock::get()?.unix_timestamp;
+        let device = &mut ctx.accounts.device_account;
+        device.owner = ctx.accounts.owner.key();
+        device.username = user_account.username.clone();
+        device.device_id = device_id;
+        device.encryption_key = encryption_key;
+        device.label = label;
+        device.created_at = now;
+        device.last_rotated_at = now;
+        device.revoked = false;
+        device.bump = ctx.bumps.device_account;
+
+        emit!(DeviceAdded {
+            username: device.username.clone(),
+            owner: device.owner,
+            device_id,
+            encryption_key,
+            label: device.label.clone(),
+        });
+
+        msg!(
+            "Device {} added for @{} ({} devices)",
+            device_id,
+            user_account.username,
+            user_account.devices.len()
+        );
+
+        Ok(())
+    }
+
+    /// Replace a device's X25519 encryption key
+    pub fn rotate_device_key(
+        ctx: Context<RotateDeviceKey>,
+        _username: String,
+        device_id: u32,
+        new_encryption_key: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RotateDeviceKey)?;
+
+        let device = &mut ctx.accounts.device_account;
+
+        require!(!device.revoked, KeyError::DeviceRevoked);
+
+        device.encryption_key = new_encryption_key;
+        device.last_rotated_at = Clock::get()?.unix_timestamp;
+
+        emit!(DeviceKeyRotated {
+            username: device.username.clone(),
+            device_id,
+            encryption_key: new_encryption_key,
+        });
+
+        msg!("Device {} key rotated for @{}", device_id, device.username);
+
+        Ok(())
+    }
+
+    /// Revoke a device; it drops off the device list so senders stop
+    /// encrypting to it, but the account stays as a record until closed
+    pub fn revoke_device(
+        ctx: Context<RevokeDevice>,
+        _username: String,
+        device_id: u32,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RevokeDevice)?;
+
+        let device = &mut ctx.accounts.device_account;
+
+        require!(!device.revoked, KeyError::DeviceRevoked);
+
+        device.revoked = true;
+        device.last_rotated_at = Clock::get()?.unix_timestamp;
+
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.devices.retain(|id| *id != device_id);
+
+        emit!(DeviceRevoked {
+            username: user_account.username.clone(),
+            device_id,
+        });
+
+        msg!(
+            "Device {} revoked for @{} ({} devices)",
+            device_id,
+            user_account.username,
+            user_account.devices.len()
+        );
+
+        Ok(())
+    }
+
+    /// Close a revoked device, or one left behind by a previous owner
+    /// The rent lamports are returned to the wallet that added the device
+    pub fn close_device(
+        ctx: Context<CloseDevice>,
+        _username: String,
+        device_id: u32,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::CloseDevice)?;
+
+        let device = &ctx.accounts.device_account;
+
+        require!(
+            device.revoked || device.owner != ctx.accounts.user_account.owner,
+            KeyError::DeviceStillActive
+        );
+
+        emit!(DeviceClosed {
+            username: device.username.clone(),
+            device_id,
+            closed_by: ctx.accounts.authority.key(),
+        });
+
+        msg!(
+            "Device {} closed for @{} by {}",
+            device_id,
+            device.username,
+            ctx.accounts.authority.key()
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    // ========================================================================
+    // Prekey Bundle Instructions (X3DH)
+    // ========================================================================
+
+    /// Publish an X3DH prekey bundle for a username
+    /// The signed prekey must be signed by `identity_key`, proven by an
+    /// Ed25519 program instruction placed immediately before this one
+    pub fn publish_prekey_bundle(
+        ctx: Context<PublishPrekeyBundle>,
+        _username: String,
+        identity_key: [u8; 32],
+        signed_prekey: SignedPrekey,
+        one_time_prekeys: Vec<OneTimePrekey>,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::PublishPrekeyBundle)?;
+
+        verify_ed25519_signature(
+            &ctx.accounts.instructions_sysvar,
+            &identity_key,
+            &signed_prekey.public_key,
+            &signed_prekey.signature,
+        )?;
+        require!(
+            one_time_prekeys.len() <= MAX_ONE_TIME_PREKEYS,
+            KeyError::TooManyOneTimePrekeys
+        );
+
+        let bundle = &mut ctx.accounts.prekey_bundle;
+        bundle.owner = ctx.accounts.owner.key();
+        bundle.username = ctx.accounts.user_account.username.clone();
+        bundle.identity_key = identity_key;
+        bundle.signed_prekey = signed_prekey;
+        bundle.signed_prekey_updated_at = Clock::get()?.unix_timestamp;
+        bundle.one_time_prekeys = one_time_prekeys;
+        bundle.bump = ctx.bumps.prekey_bundle;
+
+        emit!(PrekeyBundlePublished {
+            username: bundle.username.clone(),
+            owner: bundle.owner,
+            identity_key,
+            signed_prekey,
+            one_time_prekeys: bundle.one_time_prekeys.len() as u16,
+        });
+
+        msg!(
+            "Prekey bundle published for @{} ({} one-time prekeys)",
+            bundle.username,
+            bundle.one_time_prekeys.len()
+        );
+
+        Ok(())
+    }
+
+    /// Replace the signed prekey (must be signed by the bundle's identity key)
+    pub fn rotate_signed_prekey(
+        ctx: Context<UpdatePrekeyBundle>,
+        _username: String,
+        signed_prekey: SignedPrekey,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RotateSignedPrekey)?;
+
+        let bundle = &mut ctx.accounts.prekey_bundle;
+
+        verify_ed25519_signature(
+            &ctx.accounts.instructions_sysvar,
+            &bundle.identity_key,
+            &signed_prekey.public_key,
+            &signed_prekey.signature,
+        )?;
+
+        bundle.signed_prekey = signed_prekey;
+        bundle.signed_prekey_updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(SignedPrekeyRotated {
+            username: bundle.username.clone(),
+            signed_prekey,
+        });
+
+        msg!(
+            "Signed prekey {} published for @{}",
+            signed_prekey.key_id,
+            bundle.username
+        );
+
+        Ok(())
+    }
+
+    /// Top up the one-time prekey ring
+    pub fn add_one_time_prekeys(
+        ctx: Context<UpdatePrekeyBundle>,
+        _username: String,
+        one_time_prekeys: Vec<OneTimePrekey>,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::AddOneTimePrekeys)?;
+
+        let bundle = &mut ctx.accounts.prekey_bundle;
+
+        require!(
+            bundle.one_time_prekeys.len() + one_time_prekeys.len() <= MAX_ONE_TIME_PREKEYS,
+            KeyError::TooManyOneTimePrekeys
+        );
+
+        let added = one_time_prekeys.len() as u16;
+        bundle.one_time_prekeys.extend(one_time_prekeys);
+
+        emit!(OneTimePrekeysAdded {
+            username: bundle.username.clone(),
+            added,
+            available: bundle.one_time_prekeys.len() as u16,
+        });
+
+        msg!(
+            "One-time prekeys added for @{} ({} available)",
+            bundle.username,
+            bundle.one_time_prekeys.len()
+        );
+
+        Ok(())
+    }
+
+    /// Take the oldest one-time prekey for an X3DH handshake
+    /// Returns None once the ring is empty; X3DH then proceeds without one
+    pub fn consume_one_time_prekey(
+        ctx: Context<ConsumeOneTimePrekey>,
+        _username: String,
+    ) -> Result<Option<OneTimePrekey>> {
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::ConsumeOneTimePrekey,
+        )?;
+
+        let bundle = &mut ctx.accounts.prekey_bundle;
+
+        if bundle.one_time_prekeys.is_empty() {
+            emit!(OneTimePrekeyConsumed {
+                username: bundle.username.clone(),
+                consumer: ctx.accounts.consumer.key(),
+                prekey: None,
+            });
+
+            msg!("No one-time prekeys left for @{}", bundle.username);
+            return Ok(None);
+        }
+
+        let prekey = bundle.one_time_prekeys.remove(0);
+
+        emit!(OneTimePrekeyConsumed {
+            username: bundle.username.clone(),
+            consumer: ctx.accounts.consumer.key(),
+            prekey: Some(prekey),
+        });
+
+        msg!(
+            "One-time prekey {} for @{} consumed by {}",
+            prekey.key_id,
+            bundle.username,
+            ctx.accounts.consumer.key()
+        );
+
+        Ok(Some(prekey))
+    }
+
+    /// Close a prekey bundle
+    /// Either the publisher or the username's current owner (to clear a bundle
+    /// left behind by a previous owner) may close it; rent goes to the publisher
+    pub fn close_prekey_bundle(ctx: Context<ClosePrekeyBundle>, _username: String) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ClosePrekeyBundle)?;
+
+        emit!(PrekeyBundleClosed {
+            username: ctx.accounts.prekey_bundle.username.clone(),
+            closed_by: ctx.accounts.authority.key(),
+        });
+
+        msg!(
+            "Prekey bundle for @{} closed by {}",
+            ctx.accounts.prekey_bundle.username,
+            ctx.accounts.authority.key()
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    // ========================================================================
+    // Group Chat Instructions
+    // ========================================================================
+
+    /// Create a new group (public or private)
+    /// The group key never touches the chain: the group stores only its
+    /// commitment, and the owner's copy is encrypted to their X25519 key
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_group(
+        ctx: Context<CreateGroup>,
+        group_id: [u8; 32],
+        name: String,
+        description: String,
+        is_public: bool,
+        is_searchable: bool,
+        invite_only: bool,
+        max_members: u16,
+        allow_member_invites: bool,
+        key_commitment: [u8; 32],
+        owner_encrypted_group_key: [u8; 64],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::CreateGroup)?;
+
+        let limits = &ctx.accounts.program_config.limits;
+        validate_group_name(&name, limits)?;
+        validate_group_description(&description, limits)?;
+
+        // Initialize group account
+        let group = &mut ctx.accounts.group_account;
+        group.owner = ctx.accounts.owner.key();
+        group.group_id = group_id;
+        group.public_code = String::new(); // Set via set_group_code if needed
+        group.name = name.clone();
+        group.description = description;
+        group.avatar_arweave_id = String::new();
+        group.is_public = is_public;
+        group.is_searchable = is_searchable;
+        group.invite_only = invite_only;
+        group.max_members = max_members;
+        group.allow_member_invites = allow_member_invites;
+        group.require_approval = false;
+        group.enable_replies = true;
+        group.enable_reactions = true;
+        group.enable_read_receipts = true;
+        group.enable_typing_indicators = true;
+        group.key_commitment = key_commitment;
+        group.key_epoch = 0;
+        group.dissolved_at = 0;
+        group.role_permissions = DEFAULT_ROLE_PERMISSIONS;
+        group.member_count = 1; // Owner is first member
+        group.created_at = Clock::get()?.unix_timestamp;
+        group.updated_at = Clock::get()?.unix_timestamp;
+        group.bump = ctx.bumps.group_account;
+
+        // Initialize owner as first member with Owner role
+        let owner_member = &mut ctx.accounts.owner_member_account;
+        owner_member.group_id = group_id;
+        owner_member.member = ctx.accounts.owner.key();
+        owner_member.role = GroupRole::Owner;
+        owner_member.permissions = PERM_ALL;
+        owner_member.encrypted_group_key = owner_encrypted_group_key;
+        owner_member.joined_at = Clock::get()?.unix_timestamp;
+        owner_member.last_read_at = 0;
+        owner_member.is_active = true;
+        owner_member.is_muted = false;
+        owner_member.is_banned = false;
+        owner_member.invited_by = ctx.accounts.owner.key(); // Self-invited
+        owner_member.bump = ctx.bumps.owner_member_account;
+        owner_member.key_epoch = 0;
+        owner_member.rent_payer = ctx.accounts.owner.key();
+        owner_member.role_id = 0;
+        owner_member.muted_until = 0;
+        owner_member.notifications_muted = false;
+
+        emit!(GroupCreated {
+            group_id,
+            owner: group.owner,
+            name: group.name.clone(),
+            description: group.description.clone(),
+            is_public,
+            is_searchable,
+            invite_only,
+            max_members,
+            allow_member_invites,
+            key_commitment,
+        });
+
+        msg!(
+            "Group created: '{}' by {} (public: {})",
+            name,
+            ctx.accounts.owner.key(),
+            is_public
+        );
+
+        Ok(())
+    }
+
+    /// Update group settings (owner/admin, or PERM_MANAGE_SETTINGS)
+    /// Fields left as `None` are unchanged
+    pub fn update_group_settings(
+        ctx: Context<UpdateGroupSettings>,
+        _group_id: [u8; 32],
+        settings: GroupSettingsUpdate,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::UpdateGroupSettings)?;
+
+        let updater_member = &ctx.accounts.updater_member_account;
+
+        // Permission check
+        let can_update = updater_member.role == GroupRole::Owner
+            || updater_member.role == GroupRole::Admin
+            || updater_member.permissions & PERM_MANAGE_SETTINGS != 0;
+
+        require!(can_update, GroupError::InsufficientPermissions);
+
+        let group = &mut ctx.accounts.group_account;
+        let update = settings.clone();
+
+        let limits = &ctx.accounts.program_config.limits;
+        if let Some(name) = settings.name {
+            validate_group_name(&name, limits)?;
+            group.name = name;
+        }
+        if let Some(description) = settings.description {
+            validate_group_description(&description, limits)?;
+            group.description = description;
+        }
+        if let Some(max_members) = settings.max_members {
+            require!(
+                max_members == 0 || max_members >= group.member_count,
+                GroupError::MaxMembersBelowMemberCount
+            );
+            group.max_members = max_members;
+        }
+        if let Some(is_public) = settings.is_public {
+            group.is_public = is_public;
+        }
+        if let Some(is_searchable) = settings.is_searchable {
+            group.is_searchable = is_searchable;
+        }
+        if let Some(invite_only) = settings.invite_only {
+            group.invite_only = invite_only;
+        }
+        if let Some(allow_member_invites) = settings.allow_member_invites {
+            group.allow_member_invites = allow_member_invites;
+        }
+        if let Some(require_approval) = settings.require_approval {
+            group.require_approval = require_approval;
+        }
+        if let Some(enable_replies) = settings.enable_replies {
+            group.enable_replies = enable_replies;
+        }
+        if let Some(enable_reactions) = settings.enable_reactions {
+            group.enable_reactions = enable_reactions;
+        }
+        if let Some(enable_read_receipts) = settings.enable_read_receipts {
+            group.enable_read_receipts = enable_read_receipts;
+        }
+        if let Some(enable_typing_indicators) = settings.enable_typing_indicators {
+            group.enable_typing_indicators = enable_typing_indicators;
+        }
+
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(GroupSettingsUpdated {
+            group_id: group.group_id,
+            updated_by: ctx.accounts.updater.key(),
+            settings: update,
+        });
+
+        msg!(
+            "Group '{}' settings updated by {}",
+            group.name,
+            ctx.accounts.updater.key()
+        );
+
+        Ok(())
+    }
+
+    /// Set a public code for a group that doesn't have one
+    /// Use change_group_code to replace an existing code
+    pub fn set_group_code(
+        ctx: Context<SetGroupCode>,
+        _group_id: [u8; 32],
+        public_code: String,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::SetGroupCode)?;
+
+        let public_code = canonical::public_code(&public_code)?;
+
+        // Initialize lookup account
+        init_group_code_lookup(
+            &ctx.accounts.group_code_lookup,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            &ctx.accounts.group_account.group_id,
+            &public_code,
+            ctx.bumps.group_code_lookup,
+        )?;
+
+        // Update group account
+        let group = &mut ctx.accounts.group_account;
+        group.public_code = public_code;
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(GroupCodeSet {
+            group_id: group.group_id,
+            public_code: group.public_code.clone(),
+        });
+
+        msg!("Public code '{}' set for group", group.public_code);
+
+        Ok(())
+    }
+
+    /// Replace a group's public code (owner only)
+    /// The old lookup is closed, refunding its rent to the owner
+    pub fn change_group_code(
+        ctx: Context<ChangeGroupCode>,
+        _group_id: [u8; 32],
+        new_public_code: String,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ChangeGroupCode)?;
+
+        let new_public_code = canonical::public_code(&new_public_code)?;
+
+        init_group_code_lookup(
+            &ctx.accounts.new_group_code_lookup,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            &ctx.accounts.group_account.group_id,
+            &new_public_code,
+            ctx.bumps.new_group_code_lookup,
+        )?;
+
+        let group = &mut ctx.accounts.group_account;
+        let old_public_code = std::mem::replace(&mut group.public_code, new_public_code);
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(GroupCodeChanged {
+            group_id: group.group_id,
+            old_public_code: old_public_code.clone(),
+            new_public_code: group.public_code.clone(),
+        });
+
+        msg!(
+            "Public code changed from '{}' to '{}'",
+            old_public_code,
+            group.public_code
+        );
+
+        // Old lookup will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Release a group's public code so anyone can claim it (owner only)
+    /// The lookup's rent is returned to the owner
+    pub fn release_group_code(ctx: Context<ReleaseGroupCode>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ReleaseGroupCode)?;
+
+        let group = &mut ctx.accounts.group_account;
+        let old_public_code = std::mem::take(&mut group.public_code);
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(GroupCodeReleased {
+            group_id: group.group_id,
+            public_code: old_public_code.clone(),
+        });
+
+        msg!("Public code '{}' released", old_public_code);
+
+        // Lookup will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Join an open group (found via search or its public code)
+    /// Invite-only groups are joined by invitation, and groups that require
+    /// approval through request_to_join
+    pub fn join_group(
+        ctx: Context<JoinGroup>,
+        group_id: [u8; 32],
+        encrypted_group_key: [u8; 64],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::JoinGroup)?;
+
+        let group = &mut ctx.accounts.group_account;
+        let member = &mut ctx.accounts.member_account;
+
+        require_open_to_join(group)?;
+        require!(!group.require_approval, GroupError::RequiresApproval);
+        require_not_banned(&ctx.accounts.ban_record)?;
+
+        // Check if group has space
+        require!(
+            group.max_members == 0 || group.member_count < group.max_members,
+            GroupError::GroupFull
+        );
+
+        // Initialize member account
+        member.group_id = group_id;
+        member.member = ctx.accounts.new_member.key();
+        member.role = GroupRole::Member;
+        member.permissions = group.role_permissions.member;
+        member.encrypted_group_key = encrypted_group_key;
+        member.joined_at = Clock::get()?.unix_timestamp;
+        member.last_read_at = 0;
+        member.is_active = true;
+        member.is_muted = false;
+        member.is_banned = false;
+        member.invited_by = ctx.accounts.new_member.key(); // Will be overridden if invited
+        member.bump = ctx.bumps.member_account;
+        member.key_epoch = group.key_epoch;
+        member.rent_payer = ctx.accounts.new_member.key();
+        member.role_id = 0;
+        member.muted_until = 0;
+        member.notifications_muted = false;
+
+        // Increment member count
+        group.member_count += 1;
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(MemberJoined {
+            group_id,
+            member: member.member,
+            invited_by: member.invited_by,
+            member_count: group.member_count,
+        });
+
+        msg!(
+            "Member {} joined group (member count: {})",
+            ctx.accounts.new_member.key(),
+            group.member_count
+        );
+
+        Ok(())
+    }
+
+    /// Join a group through an invite link
+    /// Links stand in for an invitation, so this works for invite-only and
+    /// approval groups; the link is recorded as the member's invited_by
+    pub fn join_with_invite_link(
+        ctx: Context<JoinWithInviteLink>,
+        group_id: [u8; 32],
+        _invite_code: String,
+        encrypted_group_key: [u8; 64],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::JoinWithInviteLink)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let invite_link = &mut ctx.accounts.invite_link_account;
+
+        require!(invite_link.is_active, GroupError::InvalidInviteLink);
+        require!(
+            invite_link.expires_at == 0 || now < invite_link.expires_at,
+            GroupError::InvalidInviteLink
+        );
+        require!(
+            invite_link.max_uses == 0 || invite_link.use_count < invite_link.max_uses,
+            GroupError::InviteLinkExhausted
+        );
+        require_not_banned(&ctx.accounts.ban_record)?;
+
+        let group = &mut ctx.accounts.group_account;
+
+        // Links left behind by a dissolved group with the same id are void
+        require!(
+            invite_link.created_at >= group.created_at,
+            GroupError::InvalidInviteLink
+        );
+
+        // Check if group has space
+        require!(
+            group.max_members == 0 || group.member_count < group.max_members,
+            GroupError::GroupFull
+        );
+
+        invite_link.use_count += 1;
+        if invite_link.max_uses != 0 && invite_link.use_count >= invite_link.max_uses {
+            invite_link.is_active = false;
+        }
+
+        let member = &mut ctx.accounts.member_account;
+        member.group_id = group_id;
+        member.member = ctx.accounts.new_member.key();
+        member.role = GroupRole::Member;
+        member.permissions = group.role_permissions.member;
+        member.encrypted_group_key = encrypted_group_key;
+        member.joined_at = now;
+        member.last_read_at = 0;
+        member.is_active = true;
+        member.is_muted = false;
+        member.is_banned = false;
+        member.invited_by = invite_link.key();
+        member.bump = ctx.bumps.member_account;
+        member.key_epoch = group.key_epoch;
+        member.rent_payer = member.member;
+        member.role_id = 0;
+        member.muted_until = 0;
+        member.notifications_muted = false;
+
+        // Increment member count
+        group.member_count += 1;
+        group.updated_at = now;
+
+        emit!(MemberJoined {
+            group_id,
+            member: member.member,
+            invited_by: member.invited_by,
+            member_count: group.member_count,
+        });
+
+        msg!(
+            "Member {} joined group via invite link '{}' ({} uses)",
+            member.member,
+            invite_link.invite_code,
+            invite_link.use_count
+        );
+
+        Ok(())
+    }
+
+    /// Ask to join a group that requires approval
+    /// `encryption_key` lets an approver wrap the group key for the applicant
+    /// The request also holds the rent of the member account an approval
+    /// creates, so the applicant rather than the approver pays for it
+    pub fn request_to_join(
+        ctx: Context<RequestToJoin>,
+        group_id: [u8; 32],
+        encryption_key: [u8; 32],
+        note: Vec<u8>,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RequestToJoin)?;
+
+        let group = &ctx.accounts.group_account;
+
+        require_open_to_join(group)?;
+        require_not_banned(&ctx.accounts.ban_record)?;
+        require!(note.len() <= MAX_JOIN_NOTE_LEN, GroupError::JoinNoteTooLong);
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.applicant.to_account_info(),
+                    to: ctx.accounts.join_request.to_account_info(),
+                },
+            ),
+            Rent::get()?.minimum_balance(8 + GroupMemberAccount::INIT_SPACE),
+        )?;
+
+        let request = &mut ctx.accounts.join_request;
+        request.group_id = group_id;
+        request.applicant = ctx.accounts.applicant.key();
+        request.requested_at = Clock::get()?.unix_timestamp;
+        request.encryption_key = encryption_key;
+        request.note = note;
+        request.bump = ctx.bumps.join_request;
+
+        emit!(JoinRequested {
+            group_id,
+            applicant: request.applicant,
+            encryption_key,
+            note: request.note.clone(),
+        });
+
+        msg!("Join request from {} pending approval", request.applicant);
+
+        Ok(())
+    }
+
+    /// Approve a pending join request, handing the applicant their copy of
+    /// the group key (moderator+ or members allowed to invite)
+    /// The approver fronts the member account's rent and is paid back from
+    /// the deposit held by the request; the rest is returned to the applicant
+    pub fn approve_join_request(
+        ctx: Context<ApproveJoinRequest>,
+        group_id: [u8; 32],
+        encrypted_group_key: [u8; 64],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ApproveJoinRequest)?;
+
+        let group = &mut ctx.accounts.group_account;
+        let approver_member = &ctx.accounts.approver_member_account;
+
+        require!(
+            can_decide_join_requests(group, approver_member),
+            GroupError::InsufficientPermissions
+        );
+        require_not_banned(&ctx.accounts.ban_record)?;
+
+        // Check if group has space
+        require!(
+            group.max_members == 0 || group.member_count < group.max_members,
+            GroupError::GroupFull
+        );
+
+        let member = &mut ctx.accounts.member_account;
+        member.group_id = group_id;
+        member.member = ctx.accounts.applicant.key();
+        member.role = GroupRole::Member;
+        member.permissions = group.role_permissions.member;
+        member.encrypted_group_key = encrypted_group_key;
+        member.joined_at = Clock::get()?.unix_timestamp;
+        member.last_read_at = 0;
+        member.is_active = true;
+        member.is_muted = false;
+        member.is_banned = false;
+        member.invited_by = ctx.accounts.approver.key();
+        member.bump = ctx.bumps.member_account;
+        member.key_epoch = group.key_epoch;
+        member.rent_payer = ctx.accounts.applicant.key();
+        member.role_id = 0;
+        member.muted_until = 0;
+        member.notifications_muted = false;
+
+        // Increment member count
+        group.member_count += 1;
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(MemberJoined {
+            group_id,
+            member: member.member,
+            invited_by: member.invited_by,
+            member_count: group.member_count,
+        });
+
+        // The same amount request_to_join put down, whatever the member
+        // account held before it was created
+        let member_rent = Rent::get()?.minimum_balance(8 + GroupMemberAccount::INIT_SPACE);
+        let request_info = ctx.accounts.join_request.to_account_info();
+        let deposit = request_info
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(request_info.data_len()));
+        require!(deposit >= member_rent, GroupError::MissingJoinDeposit);
+        **request_info.try_borrow_mut_lamports()? -= member_rent;
+        **ctx.accounts.approver.try_borrow_mut_lamports()? += member_rent;
+
+        msg!(
+            "Join request from {} approved by {} (member count: {})",
+            ctx.accounts.applicant.key(),
+            ctx.accounts.approver.key(),
+            group.member_count
+        );
+
+        // Request will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Turn down a pending join request (same approvers as
+    /// approve_join_request)
+    /// The request's rent is returned to the applicant
+    pub fn reject_join_request(ctx: Context<RejectJoinRequest>, group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RejectJoinRequest)?;
+
+        require!(
+            can_decide_join_requests(
+                &ctx.accounts.group_account,
+                &ctx.accounts.rejecter_member_account
+            ),
+            GroupError::InsufficientPermissions
+        );
+
+        emit!(JoinRequestRejected {
+            group_id,
+            applicant: ctx.accounts.applicant.key(),
+            rejected_by: ctx.accounts.rejecter.key(),
+        });
+
+        msg!(
+            "Join request from {} rejected by {}",
+            ctx.accounts.applicant.key(),
+            ctx.accounts.rejecter.key()
+        );
+
+        // Request will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Leave a group voluntarily
+    pub fn leave_group(ctx: Context<LeaveGroup>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::LeaveGroup)?;
+
+        let group = &mut ctx.accounts.group_account;
+        let member = &ctx.accounts.member_account;
+
+        // Cannot leave if you're the owner
+        require!(
+            member.role != GroupRole::Owner,
+            GroupError::OwnerCannotLeave
+        );
+
+        // Decrement member count
+        group.member_count = group.member_count.saturating_sub(1);
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(MemberLeft {
+            group_id: group.group_id,
+            member: member.member,
+            member_count: group.member_count,
+        });
+
+        msg!(
+            "Member {} left group (member count: {})",
+            ctx.accounts.member.key(),
+            group.member_count
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Invite a specific user to the group
+    /// Creates a pending invitation that the invited wallet accepts or
+    /// declines; it lapses after `ttl` seconds
+    pub fn invite_member(
+        ctx: Context<InviteMember>,
+        group_id: [u8; 32],
+        encrypted_group_key: [u8; 64],
+        ttl: i64,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::InviteMember)?;
+
+        let group = &ctx.accounts.group_account;
+        let inviter_member = &ctx.accounts.inviter_member_account;
+
+        // Check if inviter has permission
+        let can_invite = inviter_member.role == GroupRole::Owner
+            || inviter_member.role == GroupRole::Admin
+            || inviter_member.role == GroupRole::Moderator
+            || (group.allow_member_invites
+                && inviter_member.permissions & PERM_INVITE_MEMBERS != 0);
+
+        require!(can_invite, GroupError::InsufficientPermissions);
+        require_not_banned(&ctx.accounts.ban_record)?;
+        require!(
+            (MIN_INVITATION_TTL..=MAX_INVITATION_TTL).contains(&ttl),
+            GroupError::InvalidInvitationTtl
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let invitation = &mut ctx.accounts.invitation;
+        invitation.group_id = group_id;
+        invitation.invited_user = ctx.accounts.invited_user.key();
+        invitation.invited_by = ctx.accounts.inviter.key();
+        invitation.encrypted_group_key = encrypted_group_key;
+        invitation.key_epoch = group.key_epoch;
+        invitation.created_at = now;
+        invitation.expires_at = now + ttl;
+        invitation.bump = ctx.bumps.invitation;
+
+        emit!(MemberInvited {
+            group_id,
+            invited_user: invitation.invited_user,
+            invited_by: invitation.invited_by,
+            expires_at: invitation.expires_at,
+        });
+
+        msg!(
+            "User {} invited to group by {} (expires at {})",
+            ctx.accounts.invited_user.key(),
+            ctx.accounts.inviter.key(),
+            invitation.expires_at
+        );
+
+        Ok(())
+    }
+
+    /// Accept a pending invitation, joining the group with the wrapped key
+    /// it carries
+    /// The invitation's rent is returned to the inviter; the invited wallet
+    /// pays for its own member account
+    pub fn accept_invitation(ctx: Context<AcceptInvitation>, group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::AcceptInvitation)?;
+
+        let group = &mut ctx.accounts.group_account;
+        let invitation = &ctx.accounts.invitation;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(now < invitation.expires_at, GroupError::InvitationExpired);
+        require_not_banned(&ctx.accounts.ban_record)?;
+
+        // Check if group has space
+        require!(
+            group.max_members == 0 || group.member_count < group.max_members,
+            GroupError::GroupFull
+        );
+
+        let member = &mut ctx.accounts.member_account;
+        member.group_id = group_id;
+        member.member = ctx.accounts.invited_user.key();
+        member.role = GroupRole::Member;
+        member.permissions = group.role_permissions.member;
+        member.encrypted_group_key = invitation.encrypted_group_key;
+        member.joined_at = now;
+        member.last_read_at = 0;
+        member.is_active = true;
+        member.is_muted = false;
+        member.is_banned = false;
+        member.invited_by = invitation.invited_by;
+        member.bump = ctx.bumps.member_account;
+        // A key wrapped before a rotation leaves the member stale until rewrapped
+        member.key_epoch = invitation.key_epoch;
+        member.rent_payer = ctx.accounts.invited_user.key();
+        member.role_id = 0;
+        member.muted_until = 0;
+        member.notifications_muted = false;
+
+        // Increment member count
+        group.member_count += 1;
+        group.updated_at = now;
+
+        emit!(MemberJoined {
+            group_id,
+            member: member.member,
+            invited_by: member.invited_by,
+            member_count: group.member_count,
+        });
+
+        msg!(
+            "User {} accepted invitation from {} (member count: {})",
+            ctx.accounts.invited_user.key(),
+            ctx.accounts.invited_by.key(),
+            group.member_count
+        );
+
+        // Invitation will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Decline a pending invitation, whether or not it has expired
+    /// The invitation's rent is returned to the inviter
+    pub fn decline_invitation(ctx: Context<DeclineInvitation>, group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::DeclineInvitation)?;
+
+        emit!(InvitationDeclined {
+            group_id,
+            invited_user: ctx.accounts.invited_user.key(),
+            invited_by: ctx.accounts.invited_by.key(),
+        });
+
+        msg!(
+            "User {} declined invitation from {}",
+            ctx.accounts.invited_user.key(),
+            ctx.accounts.invited_by.key()
+        );
+
+        // Invitation will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Withdraw a pending invitation: the inviter, a moderator+ or a custom
+    /// role with PERM_KICK_MEMBERS can at any time, and anyone once it has
+    /// expired, so the wallet can be invited again
+    /// The invitation's rent is returned to the inviter
+    pub fn revoke_invitation(ctx: Context<RevokeInvitation>, group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RevokeInvitation)?;
+
+        let invitation = &ctx.accounts.invitation;
+        let revoker = ctx.accounts.revoker.key();
+        let can_revoke = revoker == invitation.invited_by
+            || Clock::get()?.unix_timestamp >= invitation.expires_at
+            || can_revoke_invitations(
+                &ctx.accounts.revoker_member_account,
+                &ctx.accounts.group_roles,
+            )?;
+        require!(can_revoke, GroupError::InsufficientPermissions);
+
+        emit!(InvitationRevoked {
+            group_id,
+            invited_user: invitation.invited_user,
+            invited_by: invitation.invited_by,
+            revoked_by: revoker,
+        });
+
+        msg!(
+            "Invitation for {} revoked by {}",
+            invitation.invited_user,
+            revoker
+        );
+
+        // Invitation will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Kick/remove a member from the group (moderator+ only)
+    /// The membership's rent is returned to whoever paid it
+    pub fn kick_member(ctx: Context<KickMember>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::KickMember)?;
+
+        let group = &mut ctx.accounts.group_account;
+        let kicker_member = &ctx.accounts.kicker_member_account;
+        let kicked_member = &ctx.accounts.kicked_member_account;
+
+        // Permission check (custom roles kick through PERM_KICK_MEMBERS)
+        let can_kick = kicker_member.role == GroupRole::Owner
+            || kicker_member.role == GroupRole::Admin
+            || kicker_member.role == GroupRole::Moderator
+            || kicker_member.permissions & PERM_KICK_MEMBERS != 0;
+
+        require!(can_kick, GroupError::InsufficientPermissions);
+
+        // Cannot kick owner
+        require!(
+            kicked_member.role != GroupRole::Owner,
+            GroupError::CannotKickOwner
+        );
+
+        // Cannot kick someone with equal or higher role (except owner can kick anyone)
+        if kicker_member.role != GroupRole::Owner {
+            let roles = load_group_roles(&ctx.accounts.group_roles)?;
+            let kicker_rank = member_rank(kicker_member, roles.as_ref());
+            let kicked_rank = member_rank(kicked_member, roles.as_ref());
+            require!(
+                kicker_rank > kicked_rank,
+                GroupError::InsufficientPermissions
+            );
+        }
+
+        // Decrement member count
+        group.member_count = group.member_count.saturating_sub(1);
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(MemberKicked {
+            group_id: group.group_id,
+            member: kicked_member.member,
+            kicked_by: ctx.accounts.kicker.key(),
+            member_count: group.member_count,
+        });
+
+        msg!(
+            "Member {} kicked from group by {} (member count: {})",
+            ctx.accounts.kicked_user.key(),
+            ctx.accounts.kicker.key(),
+            group.member_count
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Stop a member from sending to the group (moderator+ only), with the
+    /// same rank rules as kick_member
+    /// `muted_until` of 0 mutes until unmute_member
+    pub fn mute_member(
+        ctx: Context<ModerateMember>,
+        _group_id: [u8; 32],
+        muted_until: i64,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::MuteMember)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            muted_until == 0 || muted_until > now,
+            GroupError::InvalidMuteExpiry
+        );
+        require_can_moderate(
+            &ctx.accounts.moderator_member_account,
+            &ctx.accounts.target_member_account,
+            &ctx.accounts.group_roles,
+        )?;
+
+        let target_member = &mut ctx.accounts.target_member_account;
+        target_member.is_muted = true;
+        target_member.muted_until = muted_until;
+
+        emit!(MemberMuted {
+            group_id: target_member.group_id,
+            member: target_member.member,
+            muted_until,
+            muted_by: ctx.accounts.moderator.key(),
+        });
+
+        msg!(
+            "Member {} muted until {} by {}",
+            target_member.member,
+            muted_until,
+            ctx.accounts.moderator.key()
+        );
+
+        Ok(())
+    }
+
+    /// Lift a member's mute before it runs out (moderator+ only)
+    pub fn unmute_member(ctx: Context<ModerateMember>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::UnmuteMember)?;
+
+        require_can_moderate(
+            &ctx.accounts.moderator_member_account,
+            &ctx.accounts.target_member_account,
+            &ctx.accounts.group_roles,
+        )?;
+
+        let target_member = &mut ctx.accounts.target_member_account;
+        target_member.is_muted = false;
+        target_member.muted_until = 0;
+
+        emit!(MemberUnmuted {
+            group_id: target_member.group_id,
+            member: target_member.member,
+            unmuted_by: ctx.accounts.moderator.key(),
+        });
+
+        msg!(
+            "Member {} unmuted by {}",
+            target_member.member,
+            ctx.accounts.moderator.key()
+        );
+
+        Ok(())
+    }
+
+    /// Mute or unmute the group's notifications for yourself
+    /// Unrelated to moderator mutes; the member can still send
+    pub fn set_notification_mute(
+        ctx: Context<SetNotificationMute>,
+        _group_id: [u8; 32],
+        muted: bool,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::SetNotificationMute)?;
+
+        let member = &mut ctx.accounts.member_account;
+        member.notifications_muted = muted;
+
+        emit!(NotificationMuteSet {
+            group_id: member.group_id,
+            member: member.member,
+            muted,
+        });
+
+        msg!(
+            "Member {} set notifications muted to {}",
+            member.member,
+            muted
+        );
+
+        Ok(())
+    }
+
+    /// Ban a wallet from the group (moderator+ only), removing its
+    /// membership if it has one
+    /// The ban outlives the membership; `expires_at` of 0 bans permanently
+    pub fn ban_member(
+        ctx: Context<BanMember>,
+        group_id: [u8; 32],
+        expires_at: i64,
+        reason: u8,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::BanMember)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let banner_member = &ctx.accounts.banner_member_account;
+
+        // Permission check (custom roles ban through PERM_KICK_MEMBERS)
+        let can_ban = banner_member.role == GroupRole::Owner
+            || banner_member.role == GroupRole::Admin
+            || banner_member.role == GroupRole::Moderator
+            || banner_member.permissions & PERM_KICK_MEMBERS != 0;
+
+        require!(can_ban, GroupError::InsufficientPermissions);
+        require_keys_neq!(
+            ctx.accounts.banned_user.key(),
+            ctx.accounts.banner.key(),
+            GroupError::CannotBanSelf
+        );
+        require!(
+            expires_at == 0 || expires_at > now,
+            GroupError::InvalidBanExpiry
+        );
+
+        // Remove the membership, with the same rank rules as kick_member
+        let member_info = ctx.accounts.banned_member_account.to_account_info();
+        let removed_member = !member_info.data_is_empty();
+        if removed_member {
+            require_keys_eq!(
+                *member_info.owner,
+                crate::ID,
+                ErrorCode::AccountOwnedByWrongProgram
+            );
+            let banned_member =
+                GroupMemberAccount::try_deserialize(&mut &member_info.try_borrow_data()?[..])?;
+
+            require!(
+                banned_member.role != GroupRole::Owner,
+                GroupError::CannotBanOwner
+            );
+            if banner_member.role != GroupRole::Owner {
+                let roles = load_group_roles(&ctx.accounts.group_roles)?;
+                require!(
+                    member_rank(banner_member, roles.as_ref())
+                        > member_rank(&banned_member, roles.as_ref()),
+                    GroupError::InsufficientPermissions
+                );
+            }
+
+            // Close the membership, refunding its rent to whoever paid it
+            let rent_payer = ctx.accounts.rent_payer.to_account_info();
+            require_keys_eq!(
+                rent_payer.key(),
+                member_rent_payer(&banned_member),
+                GroupError::RentPayerMismatch
+            );
+            close_program_account(&member_info, &rent_payer)?;
+
+            let group = &mut ctx.accounts.group_account;
+            group.member_count = group.member_count.saturating_sub(1);
+            group.updated_at = now;
+        }
+
+        let ban = &mut ctx.accounts.ban_record;
+        ban.group_id = group_id;
+        ban.user = ctx.accounts.banned_user.key();
+        ban.banned_by = ctx.accounts.banner.key();
+        ban.banned_at = now;
+        ban.expires_at = expires_at;
+        ban.reason = reason;
+        ban.bump = ctx.bumps.ban_record;
+
+        emit!(MemberBanned {
+            group_id,
+            user: ban.user,
+            banned_by: ban.banned_by,
+            expires_at,
+            reason,
+            removed_member,
+            member_count: ctx.accounts.group_account.member_count,
+        });
+
+        msg!(
+            "User {} banned from group by {} (reason {}, expires at {})",
+            ban.user,
+            ban.banned_by,
+            reason,
+            expires_at
+        );
+
+        Ok(())
+    }
+
+    /// Lift a ban (moderator+, or members with PERM_KICK_MEMBERS)
+    /// The rent lamports are returned to whoever placed the ban
+    pub fn unban_member(ctx: Context<UnbanMember>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::UnbanMember)?;
+
+        let unbanner_member = &ctx.accounts.unbanner_member_account;
+
+        // Permission check (same as ban_member, so whoever can ban can unban)
+        let can_unban = unbanner_member.role == GroupRole::Owner
+            || unbanner_member.role == GroupRole::Admin
+            || unbanner_member.role == GroupRole::Moderator
+            || unbanner_member.permissions & PERM_KICK_MEMBERS != 0;
+
+        require!(can_unban, GroupError::InsufficientPermissions);
+
+        emit!(MemberUnbanned {
+            group_id: ctx.accounts.ban_record.group_id,
+            user: ctx.accounts.ban_record.user,
+            unbanned_by: ctx.accounts.unbanner.key(),
+        });
+
+        msg!(
+            "User {} unbanned from group by {}",
+            ctx.accounts.ban_record.user,
+            ctx.accounts.unbanner.key()
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Update a member's role (admin/owner only)
+    pub fn update_member_role(
+        ctx: Context<UpdateMemberRole>,
+        _group_id: [u8; 32],
+        new_role: GroupRole,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::UpdateMemberRole)?;
+
+        let group = &ctx.accounts.group_account;
+        let updater_member = &ctx.accounts.updater_member_account;
+        let target_member = &mut ctx.accounts.target_member_account;
+
+        // Only owner and admin can update roles
+        require!(
+            updater_member.role == GroupRole::Owner || updater_member.role == GroupRole::Admin,
+            GroupError::InsufficientPermissions
+        );
+
+        // Cannot change owner role, nor make another owner; ownership only
+        // moves through a transfer or succession claim
+        require!(
+            target_member.role != GroupRole::Owner && new_role != GroupRole::Owner,
+            GroupError::CannotChangeOwnerRole
+        );
+
+        // Only owner can promote to Admin
+        if new_role == GroupRole::Admin {
+            require!(
+                updater_member.role == GroupRole::Owner,
+                GroupError::InsufficientPermissions
+            );
+        }
+
+        // Admins can only change the role of members ranked below them
+        if updater_member.role != GroupRole::Owner {
+            let roles = load_group_roles(&ctx.accounts.group_roles)?;
+            require!(
+                member_rank(updater_member, roles.as_ref())
+                    > member_rank(target_member, roles.as_ref()),
+                GroupError::InsufficientPermissions
+            );
+        }
+
+        // Update role and permissions, dropping any custom role
+        let old_role = target_member.role;
+        target_member.role = new_role;
+        target_member.role_id = 0;
+        target_member.permissions = role_permissions(&group.role_permissions, new_role);
+
+        emit!(RoleChanged {
+            group_id: target_member.group_id,
+            member: target_member.member,
+            old_role,
+            new_role,
+            permissions: target_member.permissions,
+            changed_by: ctx.accounts.updater.key(),
+        });
+
+        msg!(
+            "Member {} role updated from {:?} to {:?} by {}",
+            ctx.accounts.target_user.key(),
+            old_role,
+            new_role,
+            ctx.accounts.updater.key()
+        );
+
+        Ok(())
+    }
+
+    /// Grant individual permissions to a member without changing their role
+    /// Owners and admins can only grant permissions they hold themselves, to
+    /// members ranked below them
+    pub fn grant_permissions(
+        ctx: Context<UpdateMemberPermissions>,
+        _group_id: [u8; 32],
+        permissions: u16,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::GrantPermissions)?;
+
+        let roles = load_group_roles(&ctx.accounts.group_roles)?;
+        let target_member = &mut ctx.accounts.target_member_account;
+        require_can_change_permissions(
+            &ctx.accounts.updater_member_account,
+            target_member,
+            roles.as_ref(),
+            permissions,
+        )?;
+
+        target_member.permissions |= permissions;
+
+        emit!(PermissionsGranted {
+            group_id: target_member.group_id,
+            member: target_member.member,
+            granted: permissions,
+            permissions: target_member.permissions,
+            granted_by: ctx.accounts.updater.key(),
+        });
+
+        msg!(
+            "Permissions {:#06x} granted to {} by {}",
+            permissions,
+            target_member.member,
+            ctx.accounts.updater.key()
+        );
+
+        Ok(())
+    }
+
+    /// Revoke individual permissions from a member without changing their role
+    /// Same rules as grant_permissions
+    pub fn revoke_permissions(
+        ctx: Context<UpdateMemberPermissions>,
+        _group_id: [u8; 32],
+        permissions: u16,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RevokePermissions)?;
+
+        let roles = load_group_roles(&ctx.accounts.group_roles)?;
+        let target_member = &mut ctx.accounts.target_member_account;
+        require_can_change_permissions(
+            &ctx.accounts.updater_member_account,
+            target_member,
+            roles.as_ref(),
+            permissions,
+        )?;
+
+        target_member.permissions &= !permissions;
+
+        emit!(PermissionsRevoked {
+            group_id: target_member.group_id,
+            member: target_member.member,
+            revoked: permissions,
+            permissions: target_member.permissions,
+            revoked_by: ctx.accounts.updater.key(),
+        });
+
+        msg!(
+            "Permissions {:#06x} revoked from {} by {}",
+            permissions,
+            target_member.member,
+            ctx.accounts.updater.key()
+        );
+
+        Ok(())
+    }
+
+    /// Replace the permissions each role starts with (owner only)
+    /// Existing members keep theirs until they join again or change role
+    pub fn set_role_permissions(
+        ctx: Context<SetRolePermissions>,
+        _group_id: [u8; 32],
+        role_permissions: RolePermissions,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::SetRolePermissions)?;
+
+        require!(
+            (role_permissions.member | role_permissions.moderator | role_permissions.admin)
+                & !PERM_ALL
+                == 0,
+            GroupError::InvalidPermissions
+        );
+
+        let group = &mut ctx.accounts.group_account;
+        group.role_permissions = role_permissions;
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(RolePermissionsUpdated {
+            group_id: group.group_id,
+            role_permissions,
+        });
+
+        msg!("Role permissions updated for group {}", group.key());
+
+        Ok(())
+    }
+
+    /// Create the group's custom role table (owner only)
+    pub fn init_group_roles(ctx: Context<InitGroupRoles>, group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::InitGroupRoles)?;
+
+        let group_roles = &mut ctx.accounts.group_roles;
+        group_roles.group_id = group_id;
+        group_roles.created_by = ctx.accounts.owner.key();
+        group_roles.roles = Vec::new();
+        group_roles.next_role_id = 1;
+        group_roles.bump = ctx.bumps.group_roles;
+
+        emit!(GroupRolesInitialized { group_id });
+
+        msg!(
+            "Role table created for group {}",
+            ctx.accounts.group_account.key()
+        );
+
+        Ok(())
+    }
+
+    /// Define a custom role (owner only)
+    /// Custom roles rank anywhere below the owner: Member is 0, Moderator 64
+    /// and Admin 128
+    pub fn add_group_role(
+        ctx: Context<ManageGroupRoles>,
+        group_id: [u8; 32],
+        name: String,
+        rank: u8,
+        permissions: u16,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::AddGroupRole)?;
+
+        validate_custom_role(&name, rank, permissions)?;
+
+        let group_roles = &mut ctx.accounts.group_roles;
+        require!(
+            group_roles.roles.len() < MAX_CUSTOM_ROLES,
+            GroupError::TooManyRoles
+        );
+        let id = group_roles.next_role_id;
+        group_roles.next_role_id = id.checked_add(1).ok_or(GroupError::TooManyRoles)?;
+
+        let role = CustomRole {
+            id,
+            name,
+            rank,
+            permissions,
+        };
+        group_roles.roles.push(role.clone());
+
+        emit!(GroupRoleAdded {
+            group_id,
+            role: role.clone(),
+        });
+
+        msg!(
+            "Role '{}' ({}) added to group with rank {}",
+            role.name,
+            id,
+            rank
+        );
+
+        Ok(())
+    }
+
+    /// Change a custom role's name, rank or permissions (owner only)
+    /// Members already holding it keep their permissions until it is
+    /// assigned again; the new rank applies at once
+    pub fn update_group_role(
+        ctx: Context<ManageGroupRoles>,
+        group_id: [u8; 32],
+        role_id: u8,
+        name: String,
+        rank: u8,
+        permissions: u16,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::UpdateGroupRole)?;
+
+        validate_custom_role(&name, rank, permissions)?;
+
+        let role = ctx
+            .accounts
+            .group_roles
+            .roles
+            .iter_mut()
+            .find(|role| role.id == role_id)
+            .ok_or(GroupError::RoleNotFound)?;
+        role.name = name;
+        role.rank = rank;
+        role.permissions = permissions;
+
+        emit!(GroupRoleUpdated {
+            group_id,
+            role: role.clone(),
+        });
+
+        msg!(
+            "Role '{}' ({}) updated with rank {}",
+            role.name,
+            role_id,
+            rank
+        );
+
+        Ok(())
+    }
+
+    /// Delete a custom role (owner only)
+    /// Members holding it fall back to their built-in role's rank; role ids
+    /// are never reused
+    pub fn remove_group_role(
+        ctx: Context<ManageGroupRoles>,
+        group_id: [u8; 32],
+        role_id: u8,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RemoveGroupRole)?;
+
+        let roles = &mut ctx.accounts.group_roles.roles;
+        let index = roles
+            .iter()
+            .position(|role| role.id == role_id)
+            .ok_or(GroupError::RoleNotFound)?;
+        let role = roles.remove(index);
+
+        emit!(GroupRoleRemoved { group_id, role_id });
+
+        msg!("Role '{}' ({}) removed from group", role.name, role_id);
+
+        Ok(())
+    }
+
+    /// Give a member a custom role, or take it away with role_id 0
+    /// (admin/owner only)
+    /// Admins can only assign roles ranked below them, to members ranked
+    /// below them. The member's permissions become the role's, or their
+    /// built-in role's when it is taken away
+    pub fn assign_group_role(
+        ctx: Context<AssignGroupRole>,
+        _group_id: [u8; 32],
+        role_id: u8,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::AssignGroupRole)?;
+
+        let group = &ctx.accounts.group_account;
+        let group_roles = &ctx.accounts.group_roles;
+        let updater_member = &ctx.accounts.updater_member_account;
+        let target_member = &mut ctx.accounts.target_member_account;
+
+        require!(
+            updater_member.role == GroupRole::Owner || updater_member.role == GroupRole::Admin,
+            GroupError::InsufficientPermissions
+        );
+        require!(
+            target_member.role != GroupRole::Owner,
+            GroupError::CannotChangeOwnerRole
+        );
+
+        let role = match role_id {
+            0 => None,
+            _ => Some(find_custom_role(group_roles, role_id).ok_or(GroupError::RoleNotFound)?),
+        };
+
+        if updater_member.role != GroupRole::Owner {
+            let updater_rank = member_rank(updater_member, Some(group_roles));
+            require!(
+                updater_rank > member_rank(target_member, Some(group_roles)),
+                GroupError::InsufficientPermissions
+            );
+            if let Some(role) = role {
+                require!(
+                    updater_rank > role.rank,
+                    GroupError::InsufficientPermissions
+                );
+            }
+        }
+
+        target_member.role_id = role_id;
+        target_member.permissions = match role {
+            Some(role) => role.permissions,
+            None => role_permissions(&group.role_permissions, target_member.role),
+        };
+
+        emit!(GroupRoleAssigned {
+            group_id: target_member.group_id,
+            member: target_member.member,
+            role_id,
+            permissions: target_member.permissions,
+            assigned_by: ctx.accounts.updater.key(),
+        });
+
+        msg!(
+            "Member {} given role {} by {}",
+            target_member.member,
+            role_id,
+            ctx.accounts.updater.key()
+        );
+
+        Ok(())
+    }
+
+    /// Propose handing the group to another member (owner only)
+    /// The recipient must accept before anything changes
+    pub fn transfer_group_ownership(
+        ctx: Context<TransferGroupOwnership>,
+        group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::TransferGroupOwnership,
+        )?;
+
+        let new_owner = ctx.accounts.new_owner.key();
+        require_keys_neq!(
+            new_owner,
+            ctx.accounts.owner.key(),
+            GroupError::CannotTransferToSelf
+        );
+
+        let pending = &mut ctx.accounts.pending_transfer;
+        pending.group_id = group_id;
+        pending.current_owner = ctx.accounts.owner.key();
+        pending.new_owner = new_owner;
+        pending.proposed_at = Clock::get()?.unix_timestamp;
+        pending.bump = ctx.bumps.pending_transfer;
+
+        emit!(GroupOwnershipTransferProposed {
+            group_id,
+            current_owner: pending.current_owner,
+            new_owner,
+        });
+
+        msg!(
+            "Ownership of group '{}' proposed from {} to {}",
+            ctx.accounts.group_account.name,
+            pending.current_owner,
+            new_owner
+        );
+
+        Ok(())
+    }
+
+    /// Accept a proposed group ownership transfer
+    /// The owner and member roles are swapped in the same instruction, and
+    /// the previous owner's successor, if any, is cleared
+    pub fn accept_group_ownership(
+        ctx: Context<AcceptGroupOwnership>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::AcceptGroupOwnership,
+        )?;
+
+        swap_group_owner(
+            &mut ctx.accounts.group_account,
+            &mut ctx.accounts.owner_member_account,
+            &mut ctx.accounts.new_owner_member_account,
+            Clock::get()?.unix_timestamp,
+        );
+        close_group_succession(&ctx.accounts.succession, &ctx.accounts.succession_owner)?;
+
+        emit!(GroupOwnershipTransferred {
+            group_id: ctx.accounts.group_account.group_id,
+            previous_owner: ctx.accounts.current_owner.key(),
+            new_owner: ctx.accounts.new_owner.key(),
+        });
+
+        msg!(
+            "Group '{}' transferred from {} to {}",
+            ctx.accounts.group_account.name,
+            ctx.accounts.current_owner.key(),
+            ctx.accounts.new_owner.key()
+        );
+
+        // Pending transfer will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Cancel a proposed group ownership transfer (either party, or whoever
+    /// owns the group now, may cancel)
+    /// The rent lamports are returned to the proposing owner
+    pub fn cancel_group_ownership_transfer(
+        ctx: Context<CancelGroupOwnershipTransfer>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::CancelGroupOwnershipTransfer,
+        )?;
+
+        let pending = &ctx.accounts.pending_transfer;
+        emit!(GroupOwnershipTransferCancelled {
+            group_id: pending.group_id,
+            current_owner: pending.current_owner,
+            new_owner: pending.new_owner,
+            cancelled_by: ctx.accounts.signer.key(),
+        });
+
+        msg!(
+            "Group ownership transfer to {} cancelled by {}",
+            ctx.accounts.pending_transfer.new_owner,
+            ctx.accounts.signer.key()
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Name a member who may claim the group once the owner has been
+    /// inactive for `inactivity_period` seconds (owner only)
+    /// Setting a successor counts as owner activity
+    pub fn set_group_successor(
+        ctx: Context<SetGroupSuccessor>,
+        group_id: [u8; 32],
+        inactivity_period: i64,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::SetGroupSuccessor)?;
+
+        let successor = ctx.accounts.successor.key();
+        require_keys_neq!(
+            successor,
+            ctx.accounts.owner.key(),
+            GroupError::CannotTransferToSelf
+        );
+        require!(
+            (MIN_OWNER_INACTIVITY_PERIOD..=MAX_OWNER_INACTIVITY_PERIOD)
+                .contains(&inactivity_period),
+            GroupError::InvalidInactivityPeriod
+        );
+
+        let succession = &mut ctx.accounts.succession;
+        succession.group_id = group_id;
+        succession.owner = ctx.accounts.owner.key();
+        succession.successor = successor;
+        succession.inactivity_period = inactivity_period;
+        succession.owner_last_active_at = Clock::get()?.unix_timestamp;
+        succession.claim_started_at = 0;
+        succession.bump = ctx.bumps.succession;
+
+        emit!(GroupSuccessorSet {
+            group_id,
+            owner: succession.owner,
+            successor,
+            inactivity_period,
+        });
+
+        msg!(
+            "Successor {} named for group '{}' after {}s of owner inactivity",
+            successor,
+            ctx.accounts.group_account.name,
+            inactivity_period
+        );
+
+        Ok(())
+    }
+
+    /// Record that the owner is still active, pushing back succession and
+    /// cancelling any claim the successor has announced
+    pub fn confirm_owner_activity(
+        ctx: Context<ConfirmOwnerActivity>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::ConfirmOwnerActivity,
+        )?;
+
+        let succession = &mut ctx.accounts.succession;
+        succession.owner_last_active_at = Clock::get()?.unix_timestamp;
+        succession.claim_started_at = 0;
+
+        emit!(OwnerActivityConfirmed {
+            group_id: succession.group_id,
+            owner: succession.owner,
+            claimable_at: succession.owner_last_active_at + succession.inactivity_period,
+        });
+
+        msg!(
+            "Owner {} active (succession no earlier than {})",
+            succession.owner,
+            succession.owner_last_active_at + succession.inactivity_period
+        );
+
+        Ok(())
+    }
+
+    /// Remove the configured successor (current group owner only)
+    /// The rent lamports are returned to the owner who named the successor
+    pub fn clear_group_successor(
+        ctx: Context<ClearGroupSuccessor>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ClearGroupSuccessor)?;
+
+        emit!(GroupSuccessorCleared {
+            group_id: ctx.accounts.succession.group_id,
+            successor: ctx.accounts.succession.successor,
+            cleared_by: ctx.accounts.owner.key(),
+        });
+
+        msg!(
+            "Successor {} cleared by {}",
+            ctx.accounts.succession.successor,
+            ctx.accounts.owner.key()
+        );
+
+        // Account will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Announce a claim as the configured successor once the owner has been
+    /// inactive for the configured period
+    /// The owner can cancel it with confirm_owner_activity until
+    /// OWNERSHIP_CLAIM_NOTICE_PERIOD has passed
+    pub fn begin_ownership_claim(
+        ctx: Context<BeginOwnershipClaim>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::BeginOwnershipClaim)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let succession = &mut ctx.accounts.succession;
+        require!(
+            now >= succession.owner_last_active_at + succession.inactivity_period,
+            GroupError::OwnerStillActive
+        );
+        require!(
+            succession.claim_started_at == 0,
+            GroupError::OwnershipClaimAlreadyStarted
+        );
+        succession.claim_started_at = now;
+
+        emit!(GroupOwnershipClaimStarted {
+            group_id: succession.group_id,
+            owner: succession.owner,
+            successor: succession.successor,
+            claimable_at: now + OWNERSHIP_CLAIM_NOTICE_PERIOD,
+        });
+
+        msg!(
+            "Successor {} claims group '{}' (no earlier than {})",
+            succession.successor,
+            ctx.accounts.group_account.name,
+            now + OWNERSHIP_CLAIM_NOTICE_PERIOD
+        );
+
+        Ok(())
+    }
+
+    /// Claim ownership as the configured successor once an announced claim
+    /// has gone uncancelled for OWNERSHIP_CLAIM_NOTICE_PERIOD
+    /// Any transfer the inactive owner proposed is dropped
+    pub fn claim_group_ownership(
+        ctx: Context<ClaimGroupOwnership>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::ClaimGroupOwnership)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let succession = &ctx.accounts.succession;
+        require!(
+            succession.claim_started_at != 0
+                && now >= succession.claim_started_at + OWNERSHIP_CLAIM_NOTICE_PERIOD,
+            GroupError::OwnershipClaimNotReady
+        );
+
+        swap_group_owner(
+            &mut ctx.accounts.group_account,
+            &mut ctx.accounts.owner_member_account,
+            &mut ctx.accounts.successor_member_account,
+            now,
+        );
+        close_pending_group_transfer(
+            &ctx.accounts.pending_transfer,
+            &ctx.accounts.transfer_proposer,
+        )?;
+
+        emit!(GroupOwnershipClaimed {
+            group_id: ctx.accounts.group_account.group_id,
+            previous_owner: ctx.accounts.previous_owner.key(),
+            successor: ctx.accounts.successor.key(),
+        });
+
+        msg!(
+            "Group '{}' claimed by successor {} from inactive owner {}",
+            ctx.accounts.group_account.name,
+            ctx.accounts.successor.key(),
+            ctx.accounts.previous_owner.key()
+        );
+
+        // Succession will be closed automatically by Anchor's close constraint
+        Ok(())
+    }
+
+    /// Create a temporary invite link for a group
+    pub fn create_invite_link(
+        ctx: Context<CreateInviteLink>,
+        group_id: [u8; 32],
+        invite_code: String,
+        expires_at: i64,
+        max_uses: u16,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::CreateInviteLink)?;
+
+        let creator_member = &ctx.accounts.creator_member_account;
+        let invite_link = &mut ctx.accounts.invite_link_account;
+
+        // Permission check
+        let can_create_invite = creator_member.role == GroupRole::Owner
+            || creator_member.role == GroupRole::Admin
+            || creator_member.role == GroupRole::Moderator
+            || (ctx.accounts.group_account.allow_member_invites
+                && creator_member.permissions & PERM_INVITE_MEMBERS != 0);
+
+        require!(can_create_invite, GroupError::InsufficientPermissions);
+
+        let invite_code = canonical::invite_code(&invite_code)?;
+
+        // Initialize invite link
+        invite_link.group_id = group_id;
+        invite_link.invite_code = invite_code.clone();
+        invite_link.created_by = ctx.accounts.creator.key();
+        invite_link.expires_at = expires_at;
+        invite_link.max_uses = max_uses;
+        invite_link.use_count = 0;
+        invite_link.created_at = Clock::get()?.unix_timestamp;
+        invite_link.is_active = true;
+        invite_link.bump = ctx.bumps.invite_link_account;
+
+        emit!(InviteLinkCreated {
+            group_id,
+            invite_code: invite_code.clone(),
+            created_by: invite_link.created_by,
+            expires_at,
+            max_uses,
+        });
+
+        msg!(
+            "Invite link '{}' created for group by {}",
+            invite_code,
+            ctx.accounts.creator.key()
+        );
+
+        Ok(())
+    }
+
+    /// Revoke/deactivate an invite link
+    pub fn revoke_invite_link(
+        ctx: Context<RevokeInviteLink>,
+        _group_id: [u8; 32],
+        _invite_code: String,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RevokeInviteLink)?;
+
+        let revoker_member = &ctx.accounts.revoker_member_account;
+        let invite_link = &mut ctx.accounts.invite_link_account;
+
+        // Permission check - must be creator, moderator+, or owner
+        let can_revoke = invite_link.created_by == ctx.accounts.revoker.key()
+            || revoker_member.role == GroupRole::Owner
+            || revoker_member.role == GroupRole::Admin
+            || revoker_member.role == GroupRole::Moderator;
+
+        require!(can_revoke, GroupError::InsufficientPermissions);
+
+        invite_link.is_active = false;
+
+        emit!(InviteLinkRevoked {
+            group_id: invite_link.group_id,
+            invite_code: invite_link.invite_code.clone(),
+            revoked_by: ctx.accounts.revoker.key(),
+        });
+
+        msg!(
+            "Invite link '{}' revoked by {}",
+            invite_link.invite_code,
+            ctx.accounts.revoker.key()
+        );
+
+        Ok(())
+    }
+
+    /// Migrate a group account to the current layout
+    /// Groups created when the group key was stored in plaintext have it
+    /// replaced by its commitment; since it was public, the group should
+    /// rotate to a fresh key afterwards
+    pub fn migrate_group_key(ctx: Context<MigrateGroupKey>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::MigrateGroupKey)?;
+
+        let group_account = &ctx.accounts.group_account;
+        let old_len = group_account.data_len();
+        let new_len = 8 + GroupAccount::INIT_SPACE;
+
+        require!(old_len < new_len, GroupError::GroupAlreadyMigrated);
+
+        let shortfall = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(group_account.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: group_account.to_account_info(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        group_account.realloc(new_len, true)?;
+
+        // Fields appended since the group was created decode from the zero
+        // padding; on the plaintext layout the old group_encryption_key sits
+        // where key_commitment is now
+        let plaintext_key = old_len <= PLAINTEXT_KEY_GROUP_LEN;
+        let mut data = group_account.try_borrow_mut_data()?;
+        let mut group = GroupAccount::try_deserialize(&mut &data[..])?;
+        if plaintext_key {
+            group.key_commitment = hash(&group.key_commitment).to_bytes();
+        }
+        group.role_permissions = DEFAULT_ROLE_PERMISSIONS;
+        group.try_serialize(&mut &mut data[..])?;
+
+        emit!(GroupKeyMigrated {
+            group_id: group.group_id,
+            plaintext_key,
+        });
+
+        msg!("Group key migrated for group {}", group_account.key());
+
+        Ok(())
+    }
+
+    /// Start a new group key epoch (e.g., after a member leaves or is kicked)
+    /// Members whose key_epoch lags the group's hold a stale key until
+    /// rewrap_member_keys gives them the new one
+    pub fn begin_key_rotation(
+        ctx: Context<BeginKeyRotation>,
+        _group_id: [u8; 32],
+        new_key_commitment: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::BeginKeyRotation)?;
+
+        let group = &mut ctx.accounts.group_account;
+
+        group.key_epoch = group
+            .key_epoch
+            .checked_add(1)
+            .ok_or(GroupError::KeyEpochOverflow)?;
+        group.key_commitment = new_key_commitment;
+        group.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(GroupKeyRotationStarted {
+            group_id: group.group_id,
+            key_epoch: group.key_epoch,
+            key_commitment: new_key_commitment,
+        });
+
+        msg!("Group key rotation started (epoch {})", group.key_epoch);
+
+        Ok(())
+    }
+
+    /// Hand out the current epoch's group key, encrypted to each member
+    /// Member accounts are passed as remaining accounts in the order of
+    /// `keys`; call repeatedly to cover large groups
+    pub fn rewrap_member_keys<'info>(
+        ctx: Context<'_, '_, '_, 'info, RewrapMemberKeys<'info>>,
+        group_id: [u8; 32],
+        key_epoch: u32,
+        keys: Vec<RewrappedKey>,
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::RewrapMemberKeys)?;
+
+        require!(
+            key_epoch == ctx.accounts.group_account.key_epoch,
+            GroupError::StaleKeyEpoch
+        );
+        require!(
+            keys.len() == ctx.remaining_accounts.len(),
+            GroupError::RewrapAccountMismatch
+        );
+
+        let new_len = 8 + GroupMemberAccount::INIT_SPACE;
+        let rent = Rent::get()?;
+
+        for (key, member_info) in keys.iter().zip(ctx.remaining_accounts) {
+            let (member_pda, _) = Pubkey::find_program_address(
+                &[b"group:member", group_id.as_ref(), key.member.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(
+                member_info.key(),
+                member_pda,
+                GroupError::RewrapAccountMismatch
+            );
+            require_keys_eq!(
+                *member_info.owner,
+                crate::ID,
+                GroupError::RewrapAccountMismatch
+            );
+
+            // Members from older layouts are short
+            if member_info.data_len() < new_len {
+                let shortfall = rent
+                    .minimum_balance(new_len)
+                    .saturating_sub(member_info.lamports());
+                if shortfall > 0 {
+                    system_program::transfer(
+                        CpiContext::new(
+                            ctx.accounts.system_program.to_account_info(),
+                            system_program::Transfer {
+                                from: ctx.accounts.owner.to_account_info(),
+                                to: member_info.clone(),
+                            },
+                        ),
+                        shortfall,
+                    )?;
+                }
+                member_info.realloc(new_len, true)?;
+            }
+
+            let mut data = member_info.try_borrow_mut_data()?;
+            let mut member = GroupMemberAccount::try_deserialize(&mut &data[..])?;
+            member.encrypted_group_key = key.encrypted_group_key;
+            member.key_epoch = key_epoch;
+            member.try_serialize(&mut &mut data[..])?;
+        }
+
+        emit!(MemberKeysRewrapped {
+            group_id,
+            key_epoch,
+            members: keys.iter().map(|key| key.member).collect(),
+        });
+
+        msg!(
+            "Group key rewrapped for {} members (epoch {})",
+            keys.len(),
+            key_epoch
+        );
+
+        Ok(())
+    }
+
+    /// Dissolve a group (owner only)
+    /// The group stops accepting members, invites and codes, and its public
+    /// code is released. Its remaining accounts are then closed in batches by
+    /// close_dissolved_accounts, and the group itself by close_dissolved_group
+    pub fn dissolve_group(ctx: Context<DissolveGroup>, _group_id: [u8; 32]) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::DissolveGroup)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let group = &mut ctx.accounts.group_account;
+
+        // The owner paid for the code lookup in set_group_code
+        let lookup = ctx.accounts.group_code_lookup.to_account_info();
+        if !group.public_code.is_empty() && !lookup.data_is_empty() {
+            close_program_account(&lookup, &ctx.accounts.owner.to_account_info())?;
+        }
+        group.public_code = String::new();
+
+        group.dissolved_at = now;
+        group.updated_at = now;
+
+        emit!(GroupDissolved {
+            group_id: group.group_id,
+            owner: group.owner,
+            member_count: group.member_count,
+        });
+
+        msg!(
+            "Group '{}' dissolved by {} ({} members to close)",
+            group.name,
+            ctx.accounts.owner.key(),
+            group.member_count
+        );
+
+        Ok(())
+    }
+
+    /// Close accounts of a dissolved group, refunding each to whoever paid
+    /// for it. Anyone can crank this
+    /// remaining_accounts holds (account, rent recipient) pairs; members,
+    /// invitations, invite links, bans, join requests, pending transfers,
+    /// successions and role tables are accepted, so a large group is emptied
+    /// over several transactions
+    pub fn close_dissolved_accounts<'info>(
+        ctx: Context<'_, '_, '_, 'info, CloseDissolvedAccounts<'info>>,
+        group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(
+            &ctx.accounts.program_config,
+            Operation::CloseDissolvedAccounts,
+        )?;
+
+        // Once the group itself is closed, only its stragglers are left
+        let group_info = ctx.accounts.group_account.to_account_info();
+        let mut group = if group_info.data_is_empty() {
+            None
+        } else {
+            require_keys_eq!(
+                *group_info.owner,
+                crate::ID,
+                ErrorCode::AccountOwnedByWrongProgram
+            );
+            let data = group_info.try_borrow_data()?;
+            if data.starts_with(&GroupTombstoneAccount::DISCRIMINATOR) {
+                None
+            } else {
+                let group = GroupAccount::try_deserialize(&mut &data[..])?;
+                require!(group.dissolved_at != 0, GroupError::GroupNotDissolved);
+                Some(group)
+            }
+        };
+
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(
+            pairs.remainder().is_empty(),
+            GroupError::DissolveAccountMismatch
+        );
+
+        for pair in pairs {
+            let (account, recipient) = (&pair[0], &pair[1]);
+            require_keys_eq!(
+                *account.owner,
+                crate::ID,
+                GroupError::DissolveAccountMismatch
+            );
+
+            let (account_group_id, rent_payer) = {
+                let data = account.try_borrow_data()?;
+                let discriminator = data
+                    .get(..8)
+                    .ok_or(error!(GroupError::DissolveAccountMismatch))?;
+
+                if discriminator == GroupMemberAccount::DISCRIMINATOR {
+                    // Members from older layouts are short; pad them with zeros
+                    let mut padded = data.to_vec();
+                    padded.resize(8 + GroupMemberAccount::INIT_SPACE, 0);
+                    let member = GroupMemberAccount::try_deserialize(&mut &padded[..])?;
+                    if let Some(group) = group.as_mut() {
+                        group.member_count = group.member_count.saturating_sub(1);
+                    }
+                    (member.group_id, member_rent_payer(&member))
+                } else if discriminator == GroupInvitationAccount::DISCRIMINATOR {
+                    let invitation = GroupInvitationAccount::try_deserialize(&mut &data[..])?;
+                    (invitation.group_id, invitation.invited_by)
+                } else if discriminator == InviteLinkAccount::DISCRIMINATOR {
+                    let link = InviteLinkAccount::try_deserialize(&mut &data[..])?;
+                    (link.group_id, link.created_by)
+                } else if discriminator == BanAccount::DISCRIMINATOR {
+                    let ban = BanAccount::try_deserialize(&mut &data[..])?;
+                    (ban.group_id, ban.banned_by)
+                } else if discriminator == JoinRequestAccount::DISCRIMINATOR {
+                    let request = JoinRequestAccount::try_deserialize(&mut &data[..])?;
+                    (request.group_id, request.applicant)
+                } else if discriminator == PendingGroupTransferAccount::DISCRIMINATOR {
+                    let pending = PendingGroupTransferAccount::try_deserialize(&mut &data[..])?;
+                    (pending.group_id, pending.current_owner)
+                } else if discriminator == GroupSuccessionAccount::DISCRIMINATOR {
+                    let succession = GroupSuccessionAccount::try_deserialize(&mut &data[..])?;
+                    (succession.group_id, succession.owner)
+                } else if discriminator == GroupRolesAccount::DISCRIMINATOR {
+                    let roles = GroupRolesAccount::try_deserialize(&mut &data[..])?;
+                    (roles.group_id, roles.created_by)
+                } else {
+                    return err!(GroupError::DissolveAccountMismatch);
+                }
+            };
+
+            require!(
+                account_group_id == group_id,
+                GroupError::DissolveAccountMismatch
+            );
+            require_keys_eq!(
+                recipient.key(),
+                rent_payer,
+                GroupError::DissolveAccountMismatch
+            );
+
+            close_program_account(account, recipient)?;
+        }
+
+        emit!(DissolvedAccountsClosed {
+            group_id,
+            accounts_closed: (ctx.remaining_accounts.len() / 2) as u16,
+            member_count: group.as_ref().map(|group| group.member_count),
+        });
+
+        if let Some(group) = group {
+            msg!(
+                "Closed {} accounts of dissolved group ({} members left)",
+                ctx.remaining_accounts.len() / 2,
+                group.member_count
+            );
+            group.try_serialize(&mut &mut group_info.try_borrow_mut_data()?[..])?;
+        }
+
+        Ok(())
+    }
+
+    /// Close a dissolved group once every member account is gone
+    /// Anyone can crank this; the rent lamports are returned to the owner,
+    /// less what the tombstone left in the group's place needs
+    pub fn close_dissolved_group(
+        ctx: Context<CloseDissolvedGroup>,
+        _group_id: [u8; 32],
+    ) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::CloseDissolvedGroup)?;
+
+        let group_info = ctx.accounts.group_account.to_account_info();
+        require_keys_eq!(
+            *group_info.owner,
+            crate::ID,
+            ErrorCode::AccountOwnedByWrongProgram
+        );
+        let group = GroupAccount::try_deserialize(&mut &group_info.try_borrow_data()?[..])?;
+        require_keys_eq!(
+            group.owner,
+            ctx.accounts.owner.key(),
+            ErrorCode::ConstraintHasOne
+        );
+        require!(group.dissolved_at != 0, GroupError::GroupNotDissolved);
+        require!(group.member_count == 0, GroupError::GroupNotEmpty);
+
+        // The group is shrunk to a tombstone rather than closed, so its
+        // group_id can never be created again and inherit bans, invitations,
+        // join requests or invite links that were never reclaimed
+        let tombstone_len = 8 + GroupTombstoneAccount::INIT_SPACE;
+        let refund = group_info
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(tombstone_len));
+        **ctx.accounts.owner.try_borrow_mut_lamports()? += refund;
+        **group_info.try_borrow_mut_lamports()? -= refund;
+        group_info.realloc(tombstone_len, false)?;
+
+        GroupTombstoneAccount {
+            group_id: group.group_id,
+            closed_at: Clock::get()?.unix_timestamp,
+            bump: group.bump,
+        }
+        .try_serialize(&mut &mut group_info.try_borrow_mut_data()?[..])?;
+
+        emit!(DissolvedGroupClosed {
+            group_id: group.group_id,
+            owner: group.owner,
+        });
+
+        msg!("Dissolved group '{}' closed", group.name);
+
+        Ok(())
+    }
+
+    /// Lookup a group by its public code
+    pub fn lookup_group_by_code(ctx: Context<LookupGroupByCode>) -> Result<()> {
+        require_not_paused(&ctx.accounts.program_config, Operation::LookupGroupByCode)?;
+
+        // The account data is returned automatically by Anchor
+        // This instruction is mainly for on-chain verification
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Check that the instruction right before this one is an Ed25519 program
+/// verification of `signature` over `message` by `public_key`
+fn verify_ed25519_signature(
+    instructions_sysvar: &AccountInfo,
+    public_key: &[u8; 32],
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<()> {
+    let ix = get_instruction_relative(-1, instructions_sysvar)
+        .map_err(|_| error!(KeyError::InvalidPrekeySignature))?;
+    require_keys_eq!(
+        ix.program_id,
+        ed25519_program::ID,
+        KeyError::InvalidPrekeySignature
+    );
+
+    // Layout: signature count, padding, then one 14-byte offsets struct
+    let data = &ix.data;
+    require!(
+        data.len() >= 16 && data[0] == 1,
+        KeyError::InvalidPrekeySignature
+    );
+    let offset = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]);
+    let (signature_offset, signature_ix) = (offset(0) as usize, offset(1));
+    let (public_key_offset, public_key_ix) = (offset(2) as usize, offset(3));
+    let (message_offset, message_len, message_ix) =
+        (offset(4) as usize, offset(5) as usize, offset(6));
+
+    // Everything must live inside the Ed25519 instruction itself
+    require!(
+        signature_ix == u16::MAX && public_key_ix == u16::MAX && message_ix == u16::MAX,
+        KeyError::InvalidPrekeySignature
+    );
+    let field = |start: usize, len: usize| data.get(start..start + len);
+    require!(
+        field(public_key_offset, 32) == Some(&public_key[..])
+            && field(signature_offset, 64) == Some(&signature[..])
+            && field(message_offset, message_len) == Some(message),
+        KeyError::InvalidPrekeySignature
+    );
+
+    Ok(())
+}
+
+/// Close the current key history entry and append one for `key`, dropping
+/// the oldest entry once the history is full
+fn record_key_change(
+    history: &mut KeyHistoryAccount,
+    key: [u8; 32],
+    now: i64,
+    reason: KeyChangeReason,
+) {
+    if let Some(current) = history.entries.last_mut() {
+        current.valid_until = now;
+    }
+    if history.entries.len() == MAX_KEY_HISTORY {
+        history.entries.remove(0);
+    }
+    history.entries.push(KeyHistoryEntry {
+        key,
+        valid_from: now,
+        valid_until: 0,
+        reason,
+        compromised_at: 0,
+    });
+}
+
+fn require_not_paused(config: &ProgramConfigAccount, operation: Operation) -> Result<()> {
+    require!(
+        config.paused & (1u128 << operation as u8) == 0,
+        ConfigError::InstructionPaused
+    );
+    Ok(())
+}
+
+fn username_length(limits: &LengthLimits) -> RangeInclusive<usize> {
+    limits.min_username_len as usize..=limits.max_username_len as usize
+}
+
+/// Fee tiers must be in strictly increasing order of name length
+fn validate_fee_tiers(fee_tiers: &[FeeTier]) -> Result<()> {
+    require!(
+        fee_tiers.len() <= MAX_FEE_TIERS
+            && fee_tiers
+                .windows(2)
+                .all(|pair| pair[0].max_length < pair[1].max_length),
+        KeyError::InvalidFeeSchedule
+    );
+    Ok(())
+}
+
+/// Fee for registering a name of `length` characters: the first tier that
+/// covers it, or nothing for names longer than every tier
+fn registration_fee(fee_tiers: &[FeeTier], length: usize) -> u64 {
+    fee_tiers
+        .iter()
+        .find(|tier| length <= tier.max_length as usize)
+        .map_or(0, |tier| tier.fee)
+}
+
+/// Pay a registration (or renewal) fee into the treasury
+fn pay_registration_fee<'info>(
+    system_program: &Program<'info, System>,
+    owner: &Signer<'info>,
+    treasury: &Account<'info, TreasuryAccount>,
+    fee: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: owner.to_account_info(),
+                to: treasury.to_account_info(),
+            },
+        ),
+        fee,
+    )
+}
+
+/// End of a lease starting at `from` under the current terms (0 = never)
+fn lease_expiry(config: &RegistryConfigAccount, from: i64) -> i64 {
+    if config.lease_duration == 0 {
+        0
+    } else {
+        from + config.lease_duration
+    }
+}
+
+/// When a username's lease runs out (0 = never). Accounts still on a layout
+/// from before leases never expire: the bytes `expires_at` would be read
+/// from may be device ids left behind when the device list shrank
+fn lease_expires_at(user_account: &Account<UserAccount>) -> i64 {
+    if user_account.to_account_info().data_len() < 8 + UserAccount::INIT_SPACE {
+        0
+    } else {
+        user_account.expires_at
+    }
+}
+
+fn is_expired(user_account: &Account<UserAccount>) -> Result<bool> {
+    let expires_at = lease_expires_at(user_account);
+    Ok(expires_at != 0 && Clock::get()?.unix_timestamp >= expires_at)
+}
+
+fn is_compromised(history: &KeyHistoryAccount, key: &[u8; 32]) -> bool {
+    history
+        .entries
+        .iter()
+        .any(|entry| entry.key == *key && entry.compromised_at != 0)
+}
+
+/// Create the `[b"group:code", code]` lookup for a group, failing with
+/// PublicCodeTaken (rather than a system program error) if it exists
+fn init_group_code_lookup<'info>(
+    lookup: &UncheckedAccount<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    group_id: &[u8; 32],
+    public_code: &str,
+    bump: u8,
+) -> Result<()> {
+    require!(lookup.data_is_empty(), GroupError::PublicCodeTaken);
+
+    let space = 8 + GroupCodeLookupAccount::INIT_SPACE;
+    let signer_seeds: &[&[&[u8]]] = &[&[b"group:code", public_code.as_bytes(), &[bump]]];
+
+    // Anyone may have sent lamports to the address, so top up rather than
+    // create_account
+    let shortfall = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(lookup.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: lookup.to_account_info(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Allocate {
+                account_to_allocate: lookup.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Assign {
+                account_to_assign: lookup.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        &crate::ID,
+    )?;
+
+    let lookup_account = GroupCodeLookupAccount {
+        public_code: public_code.to_string(),
+        group_id: *group_id,
+        bump,
+    };
+    lookup_account.try_serialize(&mut &mut lookup.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+fn validate_group_name(name: &str, limits: &LengthLimits) -> Result<()> {
+    require!(
+        !name.is_empty() && name.len() <= limits.max_group_name_len as usize,
+        GroupError::InvalidGroupNameLength
+    );
+    Ok(())
+}
+
+fn validate_group_description(description: &str, limits: &LengthLimits) -> Result<()> {
+    require!(
+        description.len() <= limits.max_group_description_len as usize,
+        GroupError::InvalidGroupDescriptionLength
+    );
+    Ok(())
+}
+
+/// Groups can only be joined without an invitation if they are not
+/// invite-only and can be found (searchable or with a public code)
+fn require_open_to_join(group: &GroupAccount) -> Result<()> {
+    require!(
+        !group.invite_only && (group.is_searchable || !group.public_code.is_empty()),
+        GroupError::InviteRequired
+    );
+    Ok(())
+}
+
+/// Whether `member` may approve or reject join requests: moderators and
+/// above, or members allowed to invite when the group lets members invite
+fn can_decide_join_requests(group: &GroupAccount, member: &GroupMemberAccount) -> bool {
+    member.role == GroupRole::Owner
+        || member.role == GroupRole::Admin
+        || member.role == GroupRole::Moderator
+        || (group.allow_member_invites && member.permissions & PERM_INVITE_MEMBERS != 0)
+}
+
+/// Reject wallets with an unexpired ban record at their ban PDA
+fn require_not_banned(ban_record: &AccountInfo) -> Result<()> {
+    if ban_record.data_is_empty() {
+        return Ok(());
+    }
+    require_keys_eq!(*ban_record.owner, crate::ID, GroupError::MemberBanned);
+
+    let ban = BanAccount::try_deserialize(&mut &ban_record.try_borrow_data()?[..])?;
+    require!(
+        ban.expires_at != 0 && Clock::get()?.unix_timestamp >= ban.expires_at,
+        GroupError::MemberBanned
+    );
+    Ok(())
+}
+
+/// Whether `member_account`, if it exists, may revoke other members'
+/// invitations: holders of PERM_KICK_MEMBERS, or anyone ranking at least as
+/// high as a moderator under the group's custom roles
+fn can_revoke_invitations(member_account: &AccountInfo, group_roles: &AccountInfo) -> Result<bool> {
+    if member_account.data_is_empty() {
+        return Ok(false);
+    }
+    require_keys_eq!(
+        *member_account.owner,
+        crate::ID,
+        ErrorCode::AccountOwnedByWrongProgram
+    );
+
+    // Members from older layouts are short; pad them with zeros
+    let mut data = member_account.try_borrow_data()?.to_vec();
+    data.resize(8 + GroupMemberAccount::INIT_SPACE, 0);
+    let member = GroupMemberAccount::try_deserialize(&mut &data[..])?;
+    if member.permissions & PERM_KICK_MEMBERS != 0 {
+        return Ok(true);
+    }
+    let roles = load_group_roles(group_roles)?;
+    Ok(member_rank(&member, roles.as_ref()) >= role_to_rank(GroupRole::Moderator))
+}
+
+/// Close a username's pending transfer, if there is one, refunding whoever
+/// proposed it, so the name's next holder doesn't inherit it
+fn close_pending_transfer(pending_transfer: &AccountInfo, proposer: &AccountInfo) -> Result<()> {
+    if *pending_transfer.owner != crate::ID {
+        return Ok(());
+    }
+    let pending =
+        PendingTransferAccount::try_deserialize(&mut &pending_transfer.try_borrow_data()?[..])?;
+    require_keys_eq!(
+        proposer.key(),
+        pending.current_owner,
+        KeyError::TransferProposerMismatch
+    );
+    close_program_account(pending_transfer, proposer)
+}
+
+/// Close a group's pending ownership transfer, if there is one, refunding
+/// whoever proposed it, so the group's next owner can propose their own
+fn close_pending_group_transfer(
+    pending_transfer: &AccountInfo,
+    proposer: &AccountInfo,
+) -> Result<()> {
+    if *pending_transfer.owner != crate::ID {
+        return Ok(());
+    }
+    let pending = PendingGroupTransferAccount::try_deserialize(
+        &mut &pending_transfer.try_borrow_data()?[..],
+    )?;
+    require_keys_eq!(
+        proposer.key(),
+        pending.current_owner,
+        GroupError::TransferProposerMismatch
+    );
+    close_program_account(pending_transfer, proposer)
+}
+
+/// Close a group's succession, if there is one, refunding the owner who
+/// named the successor, so the group's next owner can name their own
+fn close_group_succession(succession: &AccountInfo, configured_by: &AccountInfo) -> Result<()> {
+    if *succession.owner != crate::ID {
+        return Ok(());
+    }
+    let succession_account =
+        GroupSuccessionAccount::try_deserialize(&mut &succession.try_borrow_data()?[..])?;
+    require_keys_eq!(
+        configured_by.key(),
+        succession_account.owner,
+        GroupError::SuccessionOwnerMismatch
+    );
+    close_program_account(succession, configured_by)
+}
+
+/// Wallet that paid for a membership and gets its rent back
+fn member_rent_payer(member: &GroupMemberAccount) -> Pubkey {
+    if member.rent_payer == Pubkey::default() {
+        member.member
+    } else {
+        member.rent_payer
+    }
+}
+
+/// Close a program-owned account by hand, moving its lamports to
+/// `destination`
+fn close_program_account(account: &AccountInfo, destination: &AccountInfo) -> Result<()> {
+    **destination.try_borrow_mut_lamports()? += account.lamports();
+    **account.try_borrow_mut_lamports()? = 0;
+    account.assign(&system_program::ID);
+    account.realloc(0, false)?;
+    Ok(())
+}
+
+/// Permissions a role starts with under the group's template
+fn role_permissions(template: &RolePermissions, role: GroupRole) -> u16 {
+    match role {
+        GroupRole::Owner => PERM_ALL,
+        GroupRole::Admin => template.admin,
+        GroupRole::Moderator => template.moderator,
+        GroupRole::Member => template.member,
+    }
+}
+
+/// Check that `updater` may grant or revoke `permissions` on `target`: only
+/// owners and admins can, only bits they hold themselves, and only on members
+/// ranked below them
+fn require_can_change_permissions(
+    updater: &GroupMemberAccount,
+    target: &GroupMemberAccount,
+    roles: Option<&GroupRolesAccount>,
+    permissions: u16,
+) -> Result<()> {
+    require!(
+        permissions != 0 && permissions & !PERM_ALL == 0,
+        GroupError::InvalidPermissions
+    );
+    require!(
+        updater.role == GroupRole::Owner || updater.role == GroupRole::Admin,
+        GroupError::InsufficientPermissions
+    );
+    // Owners hold every permission whatever their stored mask
+    let held = if updater.role == GroupRole::Owner {
+        PERM_ALL
+    } else {
+        updater.permissions
+    };
+    require!(
+        permissions & !held == 0,
+        GroupError::InsufficientPermissions
+    );
+    require!(
+        member_rank(updater, roles) > member_rank(target, roles),
+        GroupError::InsufficientPermissions
+    );
+    Ok(())
+}
+
+/// Hand the group to `new_owner`, demoting the previous owner to Admin
+fn swap_group_owner(
+    group: &mut GroupAccount,
+    owner_member: &mut GroupMemberAccount,
+    new_owner_member: &mut GroupMemberAccount,
+    now: i64,
+) {
+    group.owner = new_owner_member.member;
+    group.updated_at = now;
+
+    owner_member.role = GroupRole::Admin;
+    owner_member.role_id = 0;
+    owner_member.permissions = role_permissions(&group.role_permissions, GroupRole::Admin);
+
+    new_owner_member.role = GroupRole::Owner;
+    new_owner_member.role_id = 0;
+    new_owner_member.permissions = PERM_ALL;
+}
+
+/// Rank of a built-in role, on the same scale as custom role ranks and
+/// spaced out so custom roles can sit between them
+fn role_to_rank(role: GroupRole) -> u8 {
+    match role {
+        GroupRole::Member => 0,
+        GroupRole::Moderator => 64,
+        GroupRole::Admin => 128,
+        GroupRole::Owner => u8::MAX,
+    }
+}
+
+/// Check that `moderator` may mute `target`, by kick_member's rules:
+/// moderators and above (or custom roles with PERM_KICK_MEMBERS) can, on
+/// members ranked below them, and nobody can mute the owner
+fn require_can_moderate(
+    moderator: &GroupMemberAccount,
+    target: &GroupMemberAccount,
+    group_roles: &AccountInfo,
+) -> Result<()> {
+    require!(
+        moderator.role == GroupRole::Owner
+            || moderator.role == GroupRole::Admin
+            || moderator.role == GroupRole::Moderator
+            || moderator.permissions & PERM_KICK_MEMBERS != 0,
+        GroupError::InsufficientPermissions
+    );
+    require!(target.role != GroupRole::Owner, GroupError::CannotMuteOwner);
+    if moderator.role != GroupRole::Owner {
+        let roles = load_group_roles(group_roles)?;
+        require!(
+            member_rank(moderator, roles.as_ref()) > member_rank(target, roles.as_ref()),
+            GroupError::InsufficientPermissions
+        );
+    }
+    Ok(())
+}
+
+/// Load the group's custom role table, if the owner has created one
+fn load_group_roles(group_roles: &AccountInfo) -> Result<Option<GroupRolesAccount>> {
+    if group_roles.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(
+        *group_roles.owner,
+        crate::ID,
+        ErrorCode::AccountOwnedByWrongProgram
+    );
+    let roles = GroupRolesAccount::try_deserialize(&mut &group_roles.try_borrow_data()?[..])?;
+    Ok(Some(roles))
+}
+
+fn find_custom_role(roles: &GroupRolesAccount, role_id: u8) -> Option<&CustomRole> {
+    roles.roles.iter().find(|role| role.id == role_id)
+}
+
+/// A member's rank: their custom role's while it exists, otherwise their
+/// built-in role's. The owner always ranks highest
+fn member_rank(member: &GroupMemberAccount, roles: Option<&GroupRolesAccount>) -> u8 {
+    if member.role == GroupRole::Owner {
+        return role_to_rank(GroupRole::Owner);
+    }
+    roles
+        .and_then(|roles| find_custom_role(roles, member.role_id))
+        .map_or(role_to_rank(member.role), |role| role.rank)
+}
+
+fn validate_custom_role(name: &str, rank: u8, permissions: u16) -> Result<()> {
+    require!(
+        !name.is_empty() && name.len() <= MAX_ROLE_NAME_LEN,
+        GroupError::InvalidRoleName
+    );
+    require!(
+        rank < role_to_rank(GroupRole::Owner),
+        GroupError::InvalidRoleRank
+    );
+    require!(permissions & !PERM_ALL == 0, GroupError::InvalidPermissions);
+    Ok(())
+}
+
+// ============================================================================
+// Account Validation Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct RegisterUsername<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + UserAccount::INIT_SPACE,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + OwnerLookupAccount::INIT_SPACE,
+        seeds = [b"owner", owner.key().as_ref()],
+        bump
+    )]
+    pub owner_lookup: Account<'info, OwnerLookupAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + KeyHistoryAccount::INIT_SPACE,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub key_history: Account<'info, KeyHistoryAccount>,
+
+    #[account(
+        seeds = [b"registry_config"],
+        bump = registry_config.bump
+    )]
+    pub registry_config: Account<'info, RegistryConfigAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, TreasuryAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// The program authority, co-signing to assign a reserved name without a
+    /// fee
+    pub registry_authority: Option<Signer<'info>>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct LookupUsername<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        constraint = !is_expired(&user_account)? @ KeyError::UsernameExpired
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct ProposeTransfer<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + PendingTransferAccount::INIT_SPACE,
+        seeds = [b"transfer", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub pending_transfer: Account<'info, PendingTransferAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct AcceptTransfer<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        constraint = user_account.owner == pending_transfer.current_owner @ KeyError::StaleTransfer
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"transfer", canonical::normalize(&username).as_bytes()],
+        bump = pending_transfer.bump,
+        has_one = current_owner,
+        has_one = new_owner @ KeyError::NotTransferRecipient,
+        close = current_owner
+    )]
+    pub pending_transfer: Account<'info, PendingTransferAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"owner", current_owner.key().as_ref()],
+        bump = owner_lookup.bump,
+        close = current_owner
+    )]
+    pub owner_lookup: Account<'info, OwnerLookupAccount>,
+
+    #[account(
+        init,
+        payer = new_owner,
+        space = 8 + OwnerLookupAccount::INIT_SPACE,
+        seeds = [b"owner", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_owner_lookup: Account<'info, OwnerLookupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump = key_history.bump
+    )]
+    pub key_history: Account<'info, KeyHistoryAccount>,
+
+    /// CHECK: The proposing owner (validated via has_one on pending_transfer)
+    #[account(mut)]
+    pub current_owner: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub new_owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CancelTransfer<'info> {
+    #[account(
+        mut,
+        seeds = [b"transfer", canonical::normalize(&username).as_bytes()],
+        bump = pending_transfer.bump,
+        has_one = current_owner,
+        constraint = signer.key() == pending_transfer.current_owner
+            || signer.key() == pending_transfer.new_owner @ KeyError::NotTransferParty,
+        close = current_owner
+    )]
+    pub pending_transfer: Account<'info, PendingTransferAccount>,
+
+    /// CHECK: The proposing owner (validated via has_one on pending_transfer)
+    #[account(mut)]
+    pub current_owner: AccountInfo<'info>,
+
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CloseAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner,
+        close = owner  // Returns rent to owner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"owner", owner.key().as_ref()],
+        bump = owner_lookup.bump,
+        close = owner
+    )]
+    pub owner_lookup: Account<'info, OwnerLookupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump = key_history.bump,
+        close = owner
+    )]
+    pub key_history: Account<'info, KeyHistoryAccount>,
+
+    /// CHECK: Closed by hand if a transfer is pending
+    #[account(
+        mut,
+        seeds = [b"transfer", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub pending_transfer: UncheckedAccount<'info>,
+
+    /// CHECK: Whoever proposed the pending transfer, if any (gets its rent back)
+    #[account(mut)]
+    pub transfer_proposer: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct RenewUsername<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        seeds = [b"registry_config"],
+        bump = registry_config.bump
+    )]
+    pub registry_config: Account<'info, RegistryConfigAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, TreasuryAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct ReclaimExpiredUsername<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        close = owner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    /// CHECK: Closed by hand if it exists (usernames may predate reverse lookups)
+    #[account(
+        mut,
+        seeds = [b"owner", user_account.owner.as_ref()],
+        bump
+    )]
+    pub owner_lookup: UncheckedAccount<'info>,
+
+    /// CHECK: Closed by hand if it exists (usernames may predate key histories)
+    #[account(
+        mut,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub key_history: UncheckedAccount<'info>,
+
+    /// CHECK: Closed by hand if a transfer is pending
+    #[account(
+        mut,
+        seeds = [b"transfer", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub pending_transfer: UncheckedAccount<'info>,
+
+    /// CHECK: Whoever proposed the pending transfer, if any (gets its rent back)
+    #[account(mut)]
+    pub transfer_proposer: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"registry_config"],
+        bump = registry_config.bump
+    )]
+    pub registry_config: Account<'info, RegistryConfigAccount>,
+
+    /// CHECK: The lapsed owner, who gets the rent back
+    #[account(mut, address = user_account.owner)]
+    pub owner: UncheckedAccount<'info>,
+
+    pub reclaimer: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct MigrateUserAccount<'info> {
+    /// CHECK: Resized before being decoded; address and owner checked here
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump,
+        owner = crate::ID
+    )]
+    pub user_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct BackfillOwnerLookup<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + OwnerLookupAccount::INIT_SPACE,
+        seeds = [b"owner", owner.key().as_ref()],
+        bump
+    )]
+    pub owner_lookup: Account<'info, OwnerLookupAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct UpdateEncryptionKey<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump = key_history.bump
+    )]
+    pub key_history: Account<'info, KeyHistoryAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct RevokeEncryptionKey<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump = key_history.bump
+    )]
+    pub key_history: Account<'info, KeyHistoryAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct InitKeyHistory<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + KeyHistoryAccount::INIT_SPACE,
+        seeds = [b"key_history", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub key_history: Account<'info, KeyHistoryAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+// ============================================================================
+// Program Config Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+pub struct InitProgramConfig<'info> {
+    #[account(
+        init,
+        payer = upgrade_authority,
+        space = 8 + ProgramConfigAccount::INIT_SPACE,
+        seeds = [b"program_config"],
+        bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ ConfigError::NotProgramAuthority
+    )]
+    pub program: Program<'info, crate::program::KeyRegistry>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(upgrade_authority.key())
+            @ ConfigError::NotProgramAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(mut)]
+    pub upgrade_authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProgramConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = authority @ ConfigError::NotProgramAuthority
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptProgramAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        constraint = program_config.pending_authority == new_authority.key()
+            @ ConfigError::NotPendingAuthority
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+
+    pub new_authority: Signer<'info>,
+}
+
+// ============================================================================
+// Registry Config Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+pub struct InitRegistryConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + RegistryConfigAccount::INIT_SPACE,
+        seeds = [b"registry_config"],
+        bump
+    )]
+    pub registry_config: Account<'info, RegistryConfigAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TreasuryAccount::INIT_SPACE,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, TreasuryAccount>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = authority @ ConfigError::NotProgramAuthority
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRegistryConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry_config"],
+        bump = registry_config.bump
+    )]
+    pub registry_config: Account<'info, RegistryConfigAccount>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = authority @ ConfigError::NotProgramAuthority
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = authority @ ConfigError::NotProgramAuthority
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump
+    )]
+    pub treasury: Account<'info, TreasuryAccount>,
+
+    /// CHECK: Any wallet the authority sends the fees to
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+}
+
+// ============================================================================
+// Device Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(username: String, device_id: u32)]
+pub struct AddDevice<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + DeviceAccount::INIT_SPACE,
+        seeds = [b"device", canonical::normalize(&username).as_bytes(), &device_id.to_le_bytes()],
+        bump
+    )]
+    pub device_account: Account<'info, DeviceAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String, device_id: u32)]
+pub struct RotateDeviceKey<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"device", canonical::normalize(&username).as_bytes(), &device_id.to_le_bytes()],
+        bump = device_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub device_account: Account<'info, DeviceAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String, device_id: u32)]
+pub struct RevokeDevice<'info> {
+    #[account(
+        mut,
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"device", canonical::normalize(&username).as_bytes(), &device_id.to_le_bytes()],
+        bump = device_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub device_account: Account<'info, DeviceAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String, device_id: u32)]
+pub struct CloseDevice<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"device", canonical::normalize(&username).as_bytes(), &device_id.to_le_bytes()],
+        bump = device_account.bump,
+        constraint = authority.key() == device_account.owner
+            || authority.key() == user_account.owner @ KeyError::NotOwner,
+        close = device_owner
+    )]
+    pub device_account: Account<'info, DeviceAccount>,
+
+    /// CHECK: Wallet that added the device (receives the rent back)
+    #[account(mut, address = device_account.owner)]
+    pub device_owner: AccountInfo<'info>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+// ============================================================================
+// Prekey Bundle Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct PublishPrekeyBundle<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + PrekeyBundleAccount::INIT_SPACE,
+        seeds = [b"prekeys", canonical::normalize(&username).as_bytes()],
+        bump
+    )]
+    pub prekey_bundle: Account<'info, PrekeyBundleAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Instructions sysvar (used to find the Ed25519 verification)
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct UpdatePrekeyBundle<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        has_one = owner @ KeyError::NotOwner
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"prekeys", canonical::normalize(&username).as_bytes()],
+        bump = prekey_bundle.bump,
+        has_one = owner @ KeyError::StalePrekeyBundle
+    )]
+    pub prekey_bundle: Account<'info, PrekeyBundleAccount>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Instructions sysvar (used to find the Ed25519 verification)
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct ConsumeOneTimePrekey<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump,
+        constraint = !is_expired(&user_account)? @ KeyError::UsernameExpired
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"prekeys", canonical::normalize(&username).as_bytes()],
+        bump = prekey_bundle.bump,
+        constraint = prekey_bundle.owner == user_account.owner @ KeyError::StalePrekeyBundle
+    )]
+    pub prekey_bundle: Account<'info, PrekeyBundleAccount>,
+
+    pub consumer: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct ClosePrekeyBundle<'info> {
+    #[account(
+        seeds = [b"username", canonical::normalize(&username).as_bytes()],
+        bump = user_account.bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"prekeys", canonical::normalize(&username).as_bytes()],
+        bump = prekey_bundle.bump,
+        constraint = authority.key() == prekey_bundle.owner
+            || authority.key() == user_account.owner @ KeyError::NotOwner,
+        close = publisher
+    )]
+    pub prekey_bundle: Account<'info, PrekeyBundleAccount>,
+
+    /// CHECK: Wallet that published the bundle (receives the rent back)
+    #[account(mut, address = prekey_bundle.owner)]
+    pub publisher: AccountInfo<'info>,
+
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+// ============================================================================
+// Group Chat Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct CreateGroup<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GroupAccount::INIT_SPACE,
+        seeds = [b"group", group_id.as_ref()],
+        bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GroupMemberAccount::INIT_SPACE,
+        seeds = [b"group:member", group_id.as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub owner_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct UpdateGroupSettings<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), updater.key().as_ref()],
+        bump = updater_member_account.bump
+    )]
+    pub updater_member_account: Account<'info, GroupMemberAccount>,
+
+    pub updater: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32], public_code: String)]
+pub struct SetGroupCode<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner,
+        constraint = group_account.public_code.is_empty() @ GroupError::PublicCodeAlreadySet
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    /// CHECK: Lookup for the new code, created in the handler (validated via
+    /// PDA seeds)
+    #[account(
+        mut,
+        seeds = [b"group:code", canonical::normalize(&public_code).as_bytes()],
+        bump
+    )]
+    pub group_code_lookup: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32], new_public_code: String)]
+pub struct ChangeGroupCode<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner,
+        constraint = !group_account.public_code.is_empty() @ GroupError::NoPublicCode
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:code", canonical::normalize(&group_account.public_code).as_bytes()],
+        bump = old_group_code_lookup.bump,
+        constraint = old_group_code_lookup.group_id == group_id @ GroupError::NoPublicCode,
+        close = owner
+    )]
+    pub old_group_code_lookup: Account<'info, GroupCodeLookupAccount>,
+
+    /// CHECK: Lookup for the new code, created in the handler (validated via
+    /// PDA seeds)
+    #[account(
+        mut,
+        seeds = [b"group:code", canonical::normalize(&new_public_code).as_bytes()],
+        bump
+    )]
+    pub new_group_code_lookup: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ReleaseGroupCode<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner,
+        constraint = !group_account.public_code.is_empty() @ GroupError::NoPublicCode
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:code", canonical::normalize(&group_account.public_code).as_bytes()],
+        bump = group_code_lookup.bump,
+        constraint = group_code_lookup.group_id == group_id @ GroupError::NoPublicCode,
+        close = owner
+    )]
+    pub group_code_lookup: Account<'info, GroupCodeLookupAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct JoinGroup<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        init,
+        payer = new_member,
+        space = 8 + GroupMemberAccount::INIT_SPACE,
+        seeds = [b"group:member", group_id.as_ref(), new_member.key().as_ref()],
+        bump
+    )]
+    pub member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: Ban record for the joining wallet (must not be active)
+    #[account(
+        seeds = [b"group:ban", group_id.as_ref(), new_member.key().as_ref()],
+        bump
+    )]
+    pub ban_record: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub new_member: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32], invite_code: String)]
+pub struct JoinWithInviteLink<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:invite", group_id.as_ref(), canonical::normalize(&invite_code).as_bytes()],
+        bump = invite_link_account.bump
+    )]
+    pub invite_link_account: Account<'info, InviteLinkAccount>,
+
+    #[account(
+        init,
+        payer = new_member,
+        space = 8 + GroupMemberAccount::INIT_SPACE,
+        seeds = [b"group:member", group_id.as_ref(), new_member.key().as_ref()],
+        bump
+    )]
+    pub member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: Ban record for the joining wallet (must not be active)
+    #[account(
+        seeds = [b"group:ban", group_id.as_ref(), new_member.key().as_ref()],
+        bump
+    )]
+    pub ban_record: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub new_member: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct RequestToJoin<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.require_approval @ GroupError::ApprovalNotRequired
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        init,
+        payer = applicant,
+        space = 8 + JoinRequestAccount::INIT_SPACE,
+        seeds = [b"group:request", group_id.as_ref(), applicant.key().as_ref()],
+        bump
+    )]
+    pub join_request: Account<'info, JoinRequestAccount>,
+
+    /// CHECK: Ban record for the applicant (must not be active)
+    #[account(
+        seeds = [b"group:ban", group_id.as_ref(), applicant.key().as_ref()],
+        bump
+    )]
+    pub ban_record: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub applicant: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ApproveJoinRequest<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), approver.key().as_ref()],
+        bump = approver_member_account.bump
+    )]
+    pub approver_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:request", group_id.as_ref(), applicant.key().as_ref()],
+        bump = join_request.bump,
+        close = applicant
+    )]
+    pub join_request: Account<'info, JoinRequestAccount>,
+
+    #[account(
+        init,
+        payer = approver,
+        space = 8 + GroupMemberAccount::INIT_SPACE,
+        seeds = [b"group:member", group_id.as_ref(), applicant.key().as_ref()],
+        bump
+    )]
+    pub member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: Ban record for the applicant (must not be active)
+    #[account(
+        seeds = [b"group:ban", group_id.as_ref(), applicant.key().as_ref()],
+        bump
+    )]
+    pub ban_record: UncheckedAccount<'info>,
+
+    /// CHECK: The applicant (validated via PDA seeds, receives the rent back)
+    #[account(mut)]
+    pub applicant: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub approver: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct RejectJoinRequest<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), rejecter.key().as_ref()],
+        bump = rejecter_member_account.bump
+    )]
+    pub rejecter_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:request", group_id.as_ref(), applicant.key().as_ref()],
+        bump = join_request.bump,
+        close = applicant
+    )]
+    pub join_request: Account<'info, JoinRequestAccount>,
+
+    /// CHECK: The applicant (validated via PDA seeds, receives the rent back)
+    #[account(mut)]
+    pub applicant: AccountInfo<'info>,
+
+    pub rejecter: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct LeaveGroup<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), member.key().as_ref()],
+        bump = member_account.bump,
+        close = member
+    )]
+    pub member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(mut)]
+    pub member: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct InviteMember<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), inviter.key().as_ref()],
+        bump = inviter_member_account.bump
+    )]
+    pub inviter_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        init,
+        payer = inviter,
+        space = 8 + GroupInvitationAccount::INIT_SPACE,
+        seeds = [b"group:invitation", group_id.as_ref(), invited_user.key().as_ref()],
+        bump
+    )]
+    pub invitation: Account<'info, GroupInvitationAccount>,
+
+    /// CHECK: Ban record for the invited wallet (must not be active)
+    #[account(
+        seeds = [b"group:ban", group_id.as_ref(), invited_user.key().as_ref()],
+        bump
+    )]
+    pub ban_record: UncheckedAccount<'info>,
+
+    /// CHECK: The invited user's public key (validated via PDA seeds)
+    pub invited_user: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub inviter: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct AcceptInvitation<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:invitation", group_id.as_ref(), invited_user.key().as_ref()],
+        bump = invitation.bump,
+        has_one = invited_by @ GroupError::InvalidInvitation,
+        close = invited_by
+    )]
+    pub invitation: Account<'info, GroupInvitationAccount>,
+
+    #[account(
+        init,
+        payer = invited_user,
+        space = 8 + GroupMemberAccount::INIT_SPACE,
+        seeds = [b"group:member", group_id.as_ref(), invited_user.key().as_ref()],
+        bump
+    )]
+    pub member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: Ban record for the invited wallet (must not be active)
+    #[account(
+        seeds = [b"group:ban", group_id.as_ref(), invited_user.key().as_ref()],
+        bump
+    )]
+    pub ban_record: UncheckedAccount<'info>,
+
+    /// CHECK: The inviter (validated against the invitation, receives the rent back)
+    #[account(mut)]
+    pub invited_by: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub invited_user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct DeclineInvitation<'info> {
+    #[account(
+        mut,
+        seeds = [b"group:invitation", group_id.as_ref(), invited_user.key().as_ref()],
+        bump = invitation.bump,
+        has_one = invited_by @ GroupError::InvalidInvitation,
+        close = invited_by
+    )]
+    pub invitation: Account<'info, GroupInvitationAccount>,
+
+    /// CHECK: The inviter (validated against the invitation, receives the rent back)
+    #[account(mut)]
+    pub invited_by: AccountInfo<'info>,
+
+    pub invited_user: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct RevokeInvitation<'info> {
+    #[account(
+        mut,
+        seeds = [b"group:invitation", group_id.as_ref(), invited_user.key().as_ref()],
+        bump = invitation.bump,
+        has_one = invited_by @ GroupError::InvalidInvitation,
+        close = invited_by
+    )]
+    pub invitation: Account<'info, GroupInvitationAccount>,
+
+    /// CHECK: The revoker's membership, if any (only needed by moderators)
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), revoker.key().as_ref()],
+        bump
+    )]
+    pub revoker_member_account: UncheckedAccount<'info>,
+
+    /// CHECK: The group's custom role table, if any (validated via PDA seeds)
+    #[account(seeds = [b"group:roles", group_id.as_ref()], bump)]
+    pub group_roles: UncheckedAccount<'info>,
+
+    /// CHECK: The invited wallet (validated via PDA seeds)
+    pub invited_user: AccountInfo<'info>,
+
+    /// CHECK: The inviter (validated against the invitation, receives the rent back)
+    #[account(mut)]
+    pub invited_by: AccountInfo<'info>,
+
+    pub revoker: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct KickMember<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), kicker.key().as_ref()],
+        bump = kicker_member_account.bump
+    )]
+    pub kicker_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), kicked_user.key().as_ref()],
+        bump = kicked_member_account.bump,
+        close = rent_payer
+    )]
+    pub kicked_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: The kicked user's public key (validated via PDA seeds)
+    pub kicked_user: AccountInfo<'info>,
+
+    /// CHECK: Whoever paid for the kicked membership (receives the rent back)
+    #[account(
+        mut,
+        address = member_rent_payer(&kicked_member_account) @ GroupError::RentPayerMismatch
+    )]
+    pub rent_payer: AccountInfo<'info>,
+
+    /// CHECK: The group's custom role table, if any (validated via PDA seeds)
+    #[account(seeds = [b"group:roles", group_id.as_ref()], bump)]
+    pub group_roles: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub kicker: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ModerateMember<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), moderator.key().as_ref()],
+        bump = moderator_member_account.bump
+    )]
+    pub moderator_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), target_user.key().as_ref()],
+        bump = target_member_account.bump
+    )]
+    pub target_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: The target user's public key (validated via PDA seeds)
+    pub target_user: AccountInfo<'info>,
+
+    /// CHECK: The group's custom role table, if any (validated via PDA seeds)
+    #[account(seeds = [b"group:roles", group_id.as_ref()], bump)]
+    pub group_roles: UncheckedAccount<'info>,
+
+    pub moderator: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct SetNotificationMute<'info> {
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), member.key().as_ref()],
+        bump = member_account.bump
+    )]
+    pub member_account: Account<'info, GroupMemberAccount>,
+
+    pub member: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct BanMember<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), banner.key().as_ref()],
+        bump = banner_member_account.bump
+    )]
+    pub banner_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: The banned user's membership, if any (validated via PDA seeds,
+    /// decoded in the handler)
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), banned_user.key().as_ref()],
+        bump
+    )]
+    pub banned_member_account: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = banner,
+        space = 8 + BanAccount::INIT_SPACE,
+        seeds = [b"group:ban", group_id.as_ref(), banned_user.key().as_ref()],
+        bump
+    )]
+    pub ban_record: Account<'info, BanAccount>,
+
+    /// CHECK: The banned user's public key (validated via PDA seeds)
+    pub banned_user: AccountInfo<'info>,
+
+    /// CHECK: Whoever paid for the banned user's membership, if any (gets its
+    /// rent back)
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// CHECK: The group's custom role table, if any (validated via PDA seeds)
+    #[account(seeds = [b"group:roles", group_id.as_ref()], bump)]
+    pub group_roles: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub banner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct UnbanMember<'info> {
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), unbanner.key().as_ref()],
+        bump = unbanner_member_account.bump
+    )]
+    pub unbanner_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:ban", group_id.as_ref(), banned_user.key().as_ref()],
+        bump = ban_record.bump,
+        close = banned_by
+    )]
+    pub ban_record: Account<'info, BanAccount>,
+
+    /// CHECK: The banned user's public key (validated via PDA seeds)
+    pub banned_user: AccountInfo<'info>,
+
+    /// CHECK: Whoever placed the ban (receives the rent back)
+    #[account(mut, address = ban_record.banned_by)]
+    pub banned_by: AccountInfo<'info>,
+
+    pub unbanner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct UpdateMemberRole<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), updater.key().as_ref()],
+        bump = updater_member_account.bump
+    )]
+    pub updater_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), target_user.key().as_ref()],
+        bump = target_member_account.bump
+    )]
+    pub target_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: The target user's public key (validated via PDA seeds)
+    pub target_user: AccountInfo<'info>,
+
+    /// CHECK: The group's custom role table, if any (validated via PDA seeds)
+    #[account(seeds = [b"group:roles", group_id.as_ref()], bump)]
+    pub group_roles: UncheckedAccount<'info>,
+
+    pub updater: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct UpdateMemberPermissions<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), updater.key().as_ref()],
+        bump = updater_member_account.bump
+    )]
+    pub updater_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), target_user.key().as_ref()],
+        bump = target_member_account.bump
+    )]
+    pub target_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: The target user's public key (validated via PDA seeds)
+    pub target_user: AccountInfo<'info>,
+
+    /// CHECK: The group's custom role table, if any (validated via PDA seeds)
+    #[account(seeds = [b"group:roles", group_id.as_ref()], bump)]
+    pub group_roles: UncheckedAccount<'info>,
+
+    pub updater: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct SetRolePermissions<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct InitGroupRoles<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GroupRolesAccount::INIT_SPACE,
+        seeds = [b"group:roles", group_id.as_ref()],
+        bump
+    )]
+    pub group_roles: Account<'info, GroupRolesAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ManageGroupRoles<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:roles", group_id.as_ref()],
+        bump = group_roles.bump
+    )]
+    pub group_roles: Account<'info, GroupRolesAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct AssignGroupRole<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:roles", group_id.as_ref()],
+        bump = group_roles.bump
+    )]
+    pub group_roles: Account<'info, GroupRolesAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), updater.key().as_ref()],
+        bump = updater_member_account.bump
+    )]
+    pub updater_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), target_user.key().as_ref()],
+        bump = target_member_account.bump
+    )]
+    pub target_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: The target user's public key (validated via PDA seeds)
+    pub target_user: AccountInfo<'info>,
+
+    pub updater: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct TransferGroupOwnership<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    /// The recipient must already be a member
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), new_owner.key().as_ref()],
+        bump = new_owner_member_account.bump
+    )]
+    pub new_owner_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + PendingGroupTransferAccount::INIT_SPACE,
+        seeds = [b"group:transfer", group_id.as_ref()],
+        bump
+    )]
+    pub pending_transfer: Account<'info, PendingGroupTransferAccount>,
+
+    /// CHECK: The proposed owner's public key (validated via PDA seeds)
+    pub new_owner: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct AcceptGroupOwnership<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == pending_transfer.current_owner
+            @ GroupError::StaleOwnershipTransfer
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:transfer", group_id.as_ref()],
+        bump = pending_transfer.bump,
+        has_one = current_owner,
+        has_one = new_owner @ GroupError::NotTransferRecipient,
+        close = current_owner
+    )]
+    pub pending_transfer: Account<'info, PendingGroupTransferAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), current_owner.key().as_ref()],
+        bump = owner_member_account.bump
+    )]
+    pub owner_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), new_owner.key().as_ref()],
+        bump = new_owner_member_account.bump
+    )]
+    pub new_owner_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: Closed by hand if a successor is named
+    #[account(
+        mut,
+        seeds = [b"group:succession", group_id.as_ref()],
+        bump
+    )]
+    pub succession: UncheckedAccount<'info>,
+
+    /// CHECK: Owner who named the successor, if any (gets its rent back)
+    #[account(mut)]
+    pub succession_owner: UncheckedAccount<'info>,
+
+    /// CHECK: The proposing owner (validated via has_one on pending_transfer)
+    #[account(mut)]
+    pub current_owner: AccountInfo<'info>,
+
+    pub new_owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct CancelGroupOwnershipTransfer<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:transfer", group_id.as_ref()],
+        bump = pending_transfer.bump,
+        has_one = current_owner,
+        constraint = signer.key() == pending_transfer.current_owner
+            || signer.key() == pending_transfer.new_owner
+            || signer.key() == group_account.owner @ GroupError::NotTransferParty,
+        close = current_owner
+    )]
+    pub pending_transfer: Account<'info, PendingGroupTransferAccount>,
+
+    /// CHECK: The proposing owner (validated via has_one on pending_transfer)
+    #[account(mut)]
+    pub current_owner: AccountInfo<'info>,
+
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct SetGroupSuccessor<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    /// The successor must already be a member
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), successor.key().as_ref()],
+        bump = successor_member_account.bump
+    )]
+    pub successor_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GroupSuccessionAccount::INIT_SPACE,
+        seeds = [b"group:succession", group_id.as_ref()],
+        bump
+    )]
+    pub succession: Account<'info, GroupSuccessionAccount>,
+
+    /// CHECK: The successor's public key (validated via PDA seeds)
+    pub successor: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ConfirmOwnerActivity<'info> {
+    #[account(
+        mut,
+        seeds = [b"group:succession", group_id.as_ref()],
+        bump = succession.bump,
+        has_one = owner @ GroupError::NotGroupOwner
+    )]
+    pub succession: Account<'info, GroupSuccessionAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ClearGroupSuccessor<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:succession", group_id.as_ref()],
+        bump = succession.bump,
+        close = configured_by
+    )]
+    pub succession: Account<'info, GroupSuccessionAccount>,
+
+    /// CHECK: Owner who named the successor (receives the rent back)
+    #[account(mut, address = succession.owner)]
+    pub configured_by: AccountInfo<'info>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct BeginOwnershipClaim<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == succession.owner @ GroupError::StaleSuccession
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:succession", group_id.as_ref()],
+        bump = succession.bump,
+        has_one = successor @ GroupError::NotGroupSuccessor
+    )]
+    pub succession: Account<'info, GroupSuccessionAccount>,
+
+    pub successor: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct ClaimGroupOwnership<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == succession.owner @ GroupError::StaleSuccession
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:succession", group_id.as_ref()],
+        bump = succession.bump,
+        has_one = successor @ GroupError::NotGroupSuccessor,
+        close = previous_owner
+    )]
+    pub succession: Account<'info, GroupSuccessionAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), previous_owner.key().as_ref()],
+        bump = owner_member_account.bump
+    )]
+    pub owner_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:member", group_id.as_ref(), successor.key().as_ref()],
+        bump = successor_member_account.bump
+    )]
+    pub successor_member_account: Account<'info, GroupMemberAccount>,
+
+    /// CHECK: Closed by hand if a transfer is pending
+    #[account(
+        mut,
+        seeds = [b"group:transfer", group_id.as_ref()],
+        bump
+    )]
+    pub pending_transfer: UncheckedAccount<'info>,
+
+    /// CHECK: Whoever proposed the pending transfer, if any (gets its rent back)
+    #[account(mut)]
+    pub transfer_proposer: UncheckedAccount<'info>,
+
+    /// CHECK: The inactive owner (receives the rent back)
+    #[account(mut, address = succession.owner)]
+    pub previous_owner: AccountInfo<'info>,
+
+    pub successor: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32], invite_code: String)]
+pub struct CreateInviteLink<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), creator.key().as_ref()],
+        bump = creator_member_account.bump
+    )]
+    pub creator_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + InviteLinkAccount::INIT_SPACE,
+        seeds = [b"group:invite", group_id.as_ref(), canonical::normalize(&invite_code).as_bytes()],
+        bump
+    )]
+    pub invite_link_account: Account<'info, InviteLinkAccount>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32], invite_code: String)]
+pub struct RevokeInviteLink<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"group:member", group_id.as_ref(), revoker.key().as_ref()],
+        bump = revoker_member_account.bump
+    )]
+    pub revoker_member_account: Account<'info, GroupMemberAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"group:invite", group_id.as_ref(), canonical::normalize(&invite_code).as_bytes()],
+        bump = invite_link_account.bump
+    )]
+    pub invite_link_account: Account<'info, InviteLinkAccount>,
+
+    pub revoker: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct BeginKeyRotation<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct RewrapMemberKeys<'info> {
+    #[account(
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    /// Pays to grow member accounts created before key epochs
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct MigrateGroupKey<'info> {
+    /// CHECK: Resized before being decoded; address and owner checked here
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    pub group_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct DissolveGroup<'info> {
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump = group_account.bump,
+        constraint = group_account.owner == owner.key() @ GroupError::NotGroupOwner,
+        constraint = group_account.dissolved_at == 0 @ GroupError::GroupDissolved
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    /// CHECK: Lookup for the group's public code, if it has one (validated
+    /// via PDA seeds)
+    #[account(
+        mut,
+        seeds = [b"group:code", canonical::normalize(&group_account.public_code).as_bytes()],
+        bump
+    )]
+    pub group_code_lookup: UncheckedAccount<'info>,
 
     #[account(mut)]
-    pub new_member: Signer<'info>,
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct CloseDissolvedAccounts<'info> {
+    /// CHECK: The dissolved group, or nothing once it is closed (validated
+    /// via PDA seeds, decoded in the handler)
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump
+    )]
+    pub group_account: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: [u8; 32])]
+pub struct CloseDissolvedGroup<'info> {
+    /// CHECK: The dissolved group, replaced by a tombstone (validated via
+    /// PDA seeds, decoded in the handler)
+    #[account(
+        mut,
+        seeds = [b"group", group_id.as_ref()],
+        bump
+    )]
+    pub group_account: UncheckedAccount<'info>,
+
+    /// CHECK: The group owner (validated in the handler, receives the rent back)
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(public_code: String)]
+pub struct LookupGroupByCode<'info> {
+    #[account(
+        seeds = [b"group:code", canonical::normalize(&public_code).as_bytes()],
+        bump = group_code_lookup.bump
+    )]
+    pub group_code_lookup: Account<'info, GroupCodeLookupAccount>,
+
+    #[account(
+        seeds = [b"group", group_code_lookup.group_id.as_ref()],
+        bump = group_account.bump
+    )]
+    pub group_account: Account<'info, GroupAccount>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfigAccount>,
+}
+
+// ============================================================================
+// Account Structs
+// ============================================================================
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserAccount {
+    /// Owner's public key
+    pub owner: Pubkey,
+
+    /// The username (lowercase, 3-20 chars)
+    #[max_len(20)]
+    pub username: String,
+
+    /// Unix timestamp of registration
+    pub created_at: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+
+    // NEW: X25519 encryption public key (32 bytes)
+    pub encryption_key: [u8; 32],
+
+    /// Ids of the active devices (see DeviceAccount), bounded by MAX_DEVICES
+    #[max_len(MAX_DEVICES)]
+    pub devices: Vec<u32>,
+
+    /// When the lease runs out (0 = never); only meaningful once the account
+    /// is on the current layout
+    pub expires_at: i64,
+}
+
+/// Reverse lookup from a wallet to the username it owns
+#[account]
+#[derive(InitSpace)]
+pub struct OwnerLookupAccount {
+    /// Owner's public key
+    pub owner: Pubkey,
+
+    /// The username owned by this wallet (lowercase)
+    #[max_len(20)]
+    pub username: String,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// A username transfer awaiting acceptance by the recipient
+#[account]
+#[derive(InitSpace)]
+pub struct PendingTransferAccount {
+    /// The username being transferred (lowercase)
+    #[max_len(20)]
+    pub username: String,
+
+    /// Owner who proposed the transfer (receives the rent back)
+    pub current_owner: Pubkey,
+
+    /// Wallet that must sign to accept
+    pub new_owner: Pubkey,
+
+    /// Unix timestamp of the proposal
+    pub proposed_at: i64,
+
+    /// Earliest time the transfer can be accepted
+    pub unlocks_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// Every encryption key a username has published, oldest first
+#[account]
+#[derive(InitSpace)]
+pub struct KeyHistoryAccount {
+    /// The username this history belongs to (lowercase)
+    #[max_len(20)]
+    pub username: String,
+
+    /// Bounded by MAX_KEY_HISTORY; the oldest entry is dropped when full
+    #[max_len(MAX_KEY_HISTORY)]
+    pub entries: Vec<KeyHistoryEntry>,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct KeyHistoryEntry {
+    /// X25519 encryption public key
+    pub key: [u8; 32],
+
+    /// When the key was published
+    pub valid_from: i64,
+
+    /// When the key was replaced (0 = current key)
+    pub valid_until: i64,
+
+    /// Why the key was published
+    pub reason: KeyChangeReason,
+
+    /// When the key was compromised (0 = not compromised)
+    pub compromised_at: i64,
+}
+
+// ============================================================================
+// Program Config Accounts
+// ============================================================================
+
+/// Program-wide admin settings, checked by every instruction
+#[account]
+#[derive(InitSpace)]
+pub struct ProgramConfigAccount {
+    /// Can pause instructions, change limits and manage the registry config
+    pub authority: Pubkey,
+
+    /// Wallet the authority is being handed to (default = none)
+    pub pending_authority: Pubkey,
+
+    /// Paused instructions, one bit per `Operation`
+    pub paused: u128,
+
+    /// Tunable length limits
+    pub limits: LengthLimits,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct LengthLimits {
+    /// Username length bounds, within MAX_USERNAME_LEN
+    pub min_username_len: u8,
+    pub max_username_len: u8,
+
+    /// Longest group name, within MAX_GROUP_NAME_LEN
+    pub max_group_name_len: u16,
+
+    /// Longest group description, within MAX_GROUP_DESCRIPTION_LEN
+    pub max_group_description_len: u16,
+}
+
+// ============================================================================
+// Registry Config Accounts
+// ============================================================================
+
+/// Registry-wide settings for username registration, managed by the program
+/// authority
+#[account]
+#[derive(InitSpace)]
+pub struct RegistryConfigAccount {
+    /// Registration fees by name length, bounded by MAX_FEE_TIERS
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<FeeTier>,
+
+    /// Names (lowercase) only the authority can assign, bounded by
+    /// MAX_RESERVED_NAMES
+    #[max_len(MAX_RESERVED_NAMES, 20)]
+    pub reserved_names: Vec<String>,
+
+    /// How long registrations and renewals last (0 = names never expire)
+    pub lease_duration: i64,
+
+    /// How long an expired name stays with its owner before anyone can
+    /// reclaim it
+    pub grace_period: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct FeeTier {
+    /// Longest name this tier applies to
+    pub max_length: u8,
+
+    /// Fee in lamports, paid into the treasury
+    pub fee: u64,
+}
+
+/// Program-owned account collecting registration fees
+#[account]
+#[derive(InitSpace)]
+pub struct TreasuryAccount {
+    /// PDA bump
+    pub bump: u8,
+}
+
+// ============================================================================
+// Device Accounts
+// ============================================================================
+
+/// One device's encryption key under a username
+#[account]
+#[derive(InitSpace)]
+pub struct DeviceAccount {
+    /// Wallet that added the device (stale once the username changes hands)
+    pub owner: Pubkey,
+
+    /// The username this device belongs to (lowercase)
+    #[max_len(20)]
+    pub username: String,
+
+    /// Client-chosen device identifier
+    pub device_id: u32,
+
+    /// This device's X25519 encryption public key
+    pub encryption_key: [u8; 32],
+
+    /// Human readable label (e.g., "Pixel 8", "Web")
+    #[max_len(32)]
+    pub label: String,
+
+    /// Timestamps
+    pub created_at: i64,
+    pub last_rotated_at: i64,
+
+    /// Revoked devices must not be encrypted to
+    pub revoked: bool,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+// ============================================================================
+// Prekey Bundle Accounts
+// ============================================================================
+
+/// Public half of an X3DH prekey bundle for a username
+#[account]
+#[derive(InitSpace)]
+pub struct PrekeyBundleAccount {
+    /// Wallet that published the bundle (stale once the username changes hands)
+    pub owner: Pubkey,
+
+    /// The username this bundle belongs to (lowercase)
+    #[max_len(20)]
+    pub username: String,
+
+    /// Long-term Ed25519 identity key (signs the signed prekey)
+    pub identity_key: [u8; 32],
+
+    /// Medium-term X25519 prekey, signed by the identity key
+    pub signed_prekey: SignedPrekey,
+
+    /// When the signed prekey was last published
+    pub signed_prekey_updated_at: i64,
+
+    /// Ring of unused X25519 one-time prekeys, consumed oldest first
+    #[max_len(MAX_ONE_TIME_PREKEYS)]
+    pub one_time_prekeys: Vec<OneTimePrekey>,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct SignedPrekey {
+    /// Client-chosen identifier so peers can tell which prekey was used
+    pub key_id: u32,
+
+    /// X25519 public key
+    pub public_key: [u8; 32],
+
+    /// Ed25519 signature over `public_key` by the identity key
+    pub signature: [u8; 64],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct OneTimePrekey {
+    /// Client-chosen identifier so peers can tell which prekey was used
+    pub key_id: u32,
+
+    /// X25519 public key
+    pub public_key: [u8; 32],
+}
+
+// ============================================================================
+// Group Chat Accounts
+// ============================================================================
+
+#[account]
+#[derive(InitSpace)]
+pub struct GroupAccount {
+    /// Group owner/creator
+    pub owner: Pubkey,
+
+    /// Unique group identifier (32-byte hash)
+    pub group_id: [u8; 32],
+
+    /// Public code for joining (e.g., "keyapp-general")
+    /// Empty string if no public code assigned
+    #[max_len(20)]
+    pub public_code: String,
+
+    /// Group name (encrypted if private, plaintext if public)
+    #[max_len(MAX_GROUP_NAME_LEN)]
+    pub name: String,
+
+    /// Group description (encrypted if private, plaintext if public)
+    #[max_len(MAX_GROUP_DESCRIPTION_LEN)]
+    pub description: String,
+
+    /// Group avatar Arweave transaction ID
+    #[max_len(43)]
+    pub avatar_arweave_id: String,
+
+    /// Privacy settings
+    pub is_public: bool,
+    pub is_searchable: bool,
+    pub invite_only: bool,
 
-    pub system_program: Program<'info, System>,
+    /// Group settings
+    pub max_members: u16,
+    pub allow_member_invites: bool,
+    pub require_approval: bool,
+
+    /// Feature flags
+    pub enable_replies: bool,
+    pub enable_reactions: bool,
+    pub enable_read_receipts: bool,
+    pub enable_typing_indicators: bool,
+
+    /// SHA-256 commitment to the current group key
+    /// The key itself is only held by members, in encrypted_group_key
+    pub key_commitment: [u8; 32],
+
+    /// Member count (for quick lookup)
+    pub member_count: u16,
+
+    /// Timestamps
+    pub created_at: i64,
+    pub updated_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+
+    /// Incremented every time the group key changes
+    pub key_epoch: u32,
+
+    /// Dissolution timestamp (0 = active)
+    pub dissolved_at: i64,
+
+    /// Permissions a member gets on joining or changing role
+    pub role_permissions: RolePermissions,
 }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32])]
-pub struct LeaveGroup<'info> {
-    #[account(
-        mut,
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+/// Custom roles a group's owner defined on top of the built-in ones
+#[account]
+#[derive(InitSpace)]
+pub struct GroupRolesAccount {
+    /// The group these roles belong to
+    pub group_id: [u8; 32],
 
-    #[account(
-        mut,
-        seeds = [b"group:member", group_id.as_ref(), member.key().as_ref()],
-        bump = member_account.bump,
-        close = member
-    )]
-    pub member_account: Account<'info, GroupMemberAccount>,
+    /// Owner who created the table (receives the rent back)
+    pub created_by: Pubkey,
 
-    #[account(mut)]
-    pub member: Signer<'info>,
+    #[max_len(MAX_CUSTOM_ROLES)]
+    pub roles: Vec<CustomRole>,
 
-    pub system_program: Program<'info, System>,
+    /// Id for the next role; ids are never reused, so members still holding
+    /// a removed role don't pick up a new one
+    pub next_role_id: u8,
+
+    /// PDA bump
+    pub bump: u8,
 }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32])]
-pub struct InviteMember<'info> {
-    #[account(
-        mut,
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Debug)]
+pub struct CustomRole {
+    /// Referenced by GroupMemberAccount::role_id (never 0)
+    pub id: u8,
 
-    #[account(
-        seeds = [b"group:member", group_id.as_ref(), inviter.key().as_ref()],
-        bump = inviter_member_account.bump
-    )]
-    pub inviter_member_account: Account<'info, GroupMemberAccount>,
+    #[max_len(MAX_ROLE_NAME_LEN)]
+    pub name: String,
+
+    /// Position among the built-in roles' ranks (always below the owner)
+    pub rank: u8,
+
+    /// Permissions a member gets when given the role
+    pub permissions: u16,
+}
+
+/// Per-role permission template (owners always hold PERM_ALL)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct RolePermissions {
+    pub member: u16,
+    pub moderator: u16,
+    pub admin: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct GroupMemberAccount {
+    /// The group this membership belongs to
+    pub group_id: [u8; 32],
+
+    /// Member's public key
+    pub member: Pubkey,
+
+    /// Role in the group
+    pub role: GroupRole,
+
+    /// Permissions (bitflags)
+    pub permissions: u16,
+
+    /// Custom encryption key for this member (for key rotation)
+    /// Encrypted with member's X25519 public key
+    pub encrypted_group_key: [u8; 64],
+
+    /// Join timestamp
+    pub joined_at: i64,
+
+    /// Last read message timestamp (for read receipts)
+    pub last_read_at: i64,
+
+    /// Status flags
+    pub is_active: bool,
+    pub is_muted: bool,
+    pub is_banned: bool,
+
+    /// Invited by (for audit trail)
+    pub invited_by: Pubkey,
+
+    /// PDA bump
+    pub bump: u8,
+
+    /// Group key epoch of encrypted_group_key (stale if behind the group's)
+    pub key_epoch: u32,
+
+    /// Wallet that paid for this account (default = the member themselves)
+    pub rent_payer: Pubkey,
+
+    /// Custom role from the group's role table (0 = none, the built-in role
+    /// applies)
+    pub role_id: u8,
+
+    /// When a moderator mute ends (0 = until unmuted); only read while
+    /// is_muted is set
+    pub muted_until: i64,
+
+    /// The member muted the group's notifications for themselves
+    pub notifications_muted: bool,
+}
+
+/// A group ownership transfer awaiting acceptance by the recipient
+#[account]
+#[derive(InitSpace)]
+pub struct PendingGroupTransferAccount {
+    /// The group being transferred
+    pub group_id: [u8; 32],
+
+    /// Owner who proposed the transfer (receives the rent back)
+    pub current_owner: Pubkey,
+
+    /// Member who must sign to accept
+    pub new_owner: Pubkey,
+
+    /// Unix timestamp of the proposal
+    pub proposed_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// Successor who may claim a group whose owner has gone inactive
+#[account]
+#[derive(InitSpace)]
+pub struct GroupSuccessionAccount {
+    /// The group this succession applies to
+    pub group_id: [u8; 32],
+
+    /// Owner who named the successor (stale once the group changes hands)
+    pub owner: Pubkey,
+
+    /// Member allowed to claim ownership
+    pub successor: Pubkey,
+
+    /// Seconds of owner inactivity before the successor can claim
+    pub inactivity_period: i64,
+
+    /// Last time the owner confirmed activity
+    pub owner_last_active_at: i64,
+
+    /// When the successor announced a claim (0 = none)
+    pub claim_started_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// Changes for update_group_settings (`None` leaves a setting unchanged)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct GroupSettingsUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub is_public: Option<bool>,
+    pub is_searchable: Option<bool>,
+    pub invite_only: Option<bool>,
+    pub max_members: Option<u16>,
+    pub allow_member_invites: Option<bool>,
+    pub require_approval: Option<bool>,
+    pub enable_replies: Option<bool>,
+    pub enable_reactions: Option<bool>,
+    pub enable_read_receipts: Option<bool>,
+    pub enable_typing_indicators: Option<bool>,
+}
+
+/// A member's copy of the group key for rewrap_member_keys
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RewrappedKey {
+    pub member: Pubkey,
+    pub encrypted_group_key: [u8; 64],
+}
+
+/// Tombstone barring a wallet from a group; survives membership closure
+#[account]
+#[derive(InitSpace)]
+pub struct BanAccount {
+    /// The group the ban applies to
+    pub group_id: [u8; 32],
+
+    /// Banned wallet
+    pub user: Pubkey,
+
+    /// Moderator who placed the ban (receives the rent back on unban)
+    pub banned_by: Pubkey,
+
+    /// Ban timestamp
+    pub banned_at: i64,
+
+    /// Expiration timestamp (0 = permanent)
+    pub expires_at: i64,
+
+    /// Client-defined reason code
+    pub reason: u8,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// A pending request to join a group that requires approval
+#[account]
+#[derive(InitSpace)]
+pub struct JoinRequestAccount {
+    /// The group being requested
+    pub group_id: [u8; 32],
+
+    /// Wallet asking to join (receives the rent back)
+    pub applicant: Pubkey,
+
+    /// Request timestamp
+    pub requested_at: i64,
+
+    /// Applicant's X25519 public key, for wrapping the group key on approval
+    pub encryption_key: [u8; 32],
+
+    /// Intro note, encrypted by the applicant for the approvers (empty = none)
+    #[max_len(MAX_JOIN_NOTE_LEN)]
+    pub note: Vec<u8>,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// A direct invitation waiting for the invited wallet to accept or decline
+#[account]
+#[derive(InitSpace)]
+pub struct GroupInvitationAccount {
+    /// The group being joined
+    pub group_id: [u8; 32],
+
+    /// Wallet that was invited
+    pub invited_user: Pubkey,
+
+    /// Member who sent the invitation (receives the rent back)
+    pub invited_by: Pubkey,
+
+    /// Group key wrapped for the invited wallet
+    pub encrypted_group_key: [u8; 64],
+
+    /// Key epoch the group key was wrapped at
+    pub key_epoch: u32,
+
+    /// Invitation timestamp
+    pub created_at: i64,
+
+    /// Invitation can no longer be accepted from this time
+    pub expires_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct InviteLinkAccount {
+    /// The group this invite is for
+    pub group_id: [u8; 32],
+
+    /// Unique invite code (8-16 chars)
+    #[max_len(16)]
+    pub invite_code: String,
+
+    /// Creator of the invite link
+    pub created_by: Pubkey,
+
+    /// Expiration timestamp (0 = never expires)
+    pub expires_at: i64,
+
+    /// Maximum uses (0 = unlimited)
+    pub max_uses: u16,
+
+    /// Current use count
+    pub use_count: u16,
+
+    /// Creation timestamp
+    pub created_at: i64,
+
+    /// Is active (can be revoked)
+    pub is_active: bool,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+/// What is left of a group once close_dissolved_group has run; it holds the
+/// group PDA so the group_id can't be created again
+#[account]
+#[derive(InitSpace)]
+pub struct GroupTombstoneAccount {
+    /// The closed group's ID
+    pub group_id: [u8; 32],
+
+    /// When the group was closed
+    pub closed_at: i64,
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct GroupCodeLookupAccount {
+    /// Public code (e.g., "keyapp")
+    #[max_len(20)]
+    pub public_code: String,
+
+    /// The actual group_id this code points to
+    pub group_id: [u8; 32],
+
+    /// PDA bump
+    pub bump: u8,
+}
+
+// ============================================================================
+// Username Events
+// ============================================================================
+
+#[event]
+pub struct UsernameRegistered {
+    pub username: String,
+    pub owner: Pubkey,
+    pub encryption_key: [u8; 32],
+    /// Lamports paid into the treasury (0 for reserved names)
+    pub fee: u64,
+    /// 0 = never expires
+    pub expires_at: i64,
+}
 
-    #[account(
-        init,
-        payer = inviter,
-        space = 8 + GroupMemberAccount::INIT_SPACE,
-        seeds = [b"group:member", group_id.as_ref(), invited_user.key().as_ref()],
-        bump
-    )]
-    pub invited_member_account: Account<'info, GroupMemberAccount>,
+#[event]
+pub struct UsernameTransferProposed {
+    pub username: String,
+    pub current_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub unlocks_at: i64,
+}
 
-    /// CHECK: The invited user's public key (validated via PDA seeds)
-    pub invited_user: AccountInfo<'info>,
+#[event]
+pub struct UsernameTransferred {
+    pub username: String,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub encryption_key: [u8; 32],
+}
 
-    #[account(mut)]
-    pub inviter: Signer<'info>,
+#[event]
+pub struct UsernameTransferCancelled {
+    pub username: String,
+    pub current_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub cancelled_by: Pubkey,
+}
 
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct UsernameReleased {
+    pub username: String,
+    pub owner: Pubkey,
 }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32])]
-pub struct KickMember<'info> {
-    #[account(
-        mut,
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+#[event]
+pub struct UsernameRenewed {
+    pub username: String,
+    pub owner: Pubkey,
+    pub fee: u64,
+    /// 0 once leases are switched off
+    pub expires_at: i64,
+}
 
-    #[account(
-        seeds = [b"group:member", group_id.as_ref(), kicker.key().as_ref()],
-        bump = kicker_member_account.bump
-    )]
-    pub kicker_member_account: Account<'info, GroupMemberAccount>,
+#[event]
+pub struct UsernameReclaimed {
+    pub username: String,
+    pub previous_owner: Pubkey,
+    pub reclaimer: Pubkey,
+}
 
-    #[account(
-        mut,
-        seeds = [b"group:member", group_id.as_ref(), kicked_user.key().as_ref()],
-        bump = kicked_member_account.bump,
-        close = kicker
-    )]
-    pub kicked_member_account: Account<'info, GroupMemberAccount>,
+#[event]
+pub struct UserAccountMigrated {
+    pub username: String,
+}
 
-    /// CHECK: The kicked user's public key (validated via PDA seeds)
-    pub kicked_user: AccountInfo<'info>,
+#[event]
+pub struct OwnerLookupBackfilled {
+    pub username: String,
+    pub owner: Pubkey,
+}
 
-    #[account(mut)]
-    pub kicker: Signer<'info>,
+#[event]
+pub struct EncryptionKeyUpdated {
+    pub username: String,
+    pub owner: Pubkey,
+    pub encryption_key: [u8; 32],
+}
 
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct EncryptionKeyRevoked {
+    pub username: String,
+    pub key: [u8; 32],
+    pub compromised_at: i64,
+    /// The new current key, when the revoked key was the current one
+    pub replacement_key: Option<[u8; 32]>,
 }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32])]
-pub struct UpdateMemberRole<'info> {
-    #[account(
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+#[event]
+pub struct KeyHistoryInitialized {
+    pub username: String,
+    pub encryption_key: [u8; 32],
+}
 
-    #[account(
-        seeds = [b"group:member", group_id.as_ref(), updater.key().as_ref()],
-        bump = updater_member_account.bump
-    )]
-    pub updater_member_account: Account<'info, GroupMemberAccount>,
+// ============================================================================
+// Program Config Events
+// ============================================================================
 
-    #[account(
-        mut,
-        seeds = [b"group:member", group_id.as_ref(), target_user.key().as_ref()],
-        bump = target_member_account.bump
-    )]
-    pub target_member_account: Account<'info, GroupMemberAccount>,
+#[event]
+pub struct ProgramConfigInitialized {
+    pub authority: Pubkey,
+    pub limits: LengthLimits,
+}
 
-    /// CHECK: The target user's public key (validated via PDA seeds)
-    pub target_user: AccountInfo<'info>,
+#[event]
+pub struct ProgramAuthorityProposed {
+    pub authority: Pubkey,
+    /// Default pubkey = handover cancelled
+    pub pending_authority: Pubkey,
+}
 
-    pub updater: Signer<'info>,
+#[event]
+pub struct ProgramAuthorityAccepted {
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
 }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32], invite_code: String)]
-pub struct CreateInviteLink<'info> {
-    #[account(
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+#[event]
+pub struct OperationPauseUpdated {
+    pub operation: Operation,
+    pub paused: bool,
+}
 
-    #[account(
-        seeds = [b"group:member", group_id.as_ref(), creator.key().as_ref()],
-        bump = creator_member_account.bump
-    )]
-    pub creator_member_account: Account<'info, GroupMemberAccount>,
+#[event]
+pub struct LengthLimitsUpdated {
+    pub limits: LengthLimits,
+}
 
-    #[account(
-        init,
-        payer = creator,
-        space = 8 + InviteLinkAccount::INIT_SPACE,
-        seeds = [b"group:invite", group_id.as_ref(), invite_code.as_bytes()],
-        bump
-    )]
-    pub invite_link_account: Account<'info, InviteLinkAccount>,
+// ============================================================================
+// Registry Config Events
+// ============================================================================
 
-    #[account(mut)]
-    pub creator: Signer<'info>,
+#[event]
+pub struct RegistryConfigInitialized {
+    pub fee_tiers: Vec<FeeTier>,
+    pub grace_period: i64,
+}
 
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct FeeScheduleUpdated {
+    pub fee_tiers: Vec<FeeTier>,
 }
 
-#[derive(Accounts)]
-#[instruction(group_id: [u8; 32], invite_code: String)]
-pub struct RevokeInviteLink<'info> {
-    #[account(
-        seeds = [b"group", group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+#[event]
+pub struct LeaseTermsUpdated {
+    pub lease_duration: i64,
+    pub grace_period: i64,
+}
 
-    #[account(
-        seeds = [b"group:member", group_id.as_ref(), revoker.key().as_ref()],
-        bump = revoker_member_account.bump
-    )]
-    pub revoker_member_account: Account<'info, GroupMemberAccount>,
+#[event]
+pub struct ReservedNameAdded {
+    pub name: String,
+}
 
-    #[account(
-        mut,
-        seeds = [b"group:invite", group_id.as_ref(), invite_code.as_bytes()],
-        bump = invite_link_account.bump
-    )]
-    pub invite_link_account: Account<'info, InviteLinkAccount>,
+#[event]
+pub struct ReservedNameRemoved {
+    pub name: String,
+}
 
-    pub revoker: Signer<'info>,
+#[event]
+pub struct FeesWithdrawn {
+    pub amount: u64,
+    pub recipient: Pubkey,
 }
 
-#[derive(Accounts)]
-#[instruction(public_code: String)]
-pub struct LookupGroupByCode<'info> {
-    #[account(
-        seeds = [b"group:code", public_code.to_lowercase().as_bytes()],
-        bump = group_code_lookup.bump
-    )]
-    pub group_code_lookup: Account<'info, GroupCodeLookupAccount>,
+// ============================================================================
+// Device Events
+// ============================================================================
 
-    #[account(
-        seeds = [b"group", group_code_lookup.group_id.as_ref()],
-        bump = group_account.bump
-    )]
-    pub group_account: Account<'info, GroupAccount>,
+#[event]
+pub struct DeviceAdded {
+    pub username: String,
+    pub owner: Pubkey,
+    pub device_id: u32,
+    pub encryption_key: [u8; 32],
+    pub label: String,
+}
+
+#[event]
+pub struct DeviceKeyRotated {
+    pub username: String,
+    pub device_id: u32,
+    pub encryption_key: [u8; 32],
+}
+
+#[event]
+pub struct DeviceRevoked {
+    pub username: String,
+    pub device_id: u32,
+}
+
+#[event]
+pub struct DeviceClosed {
+    pub username: String,
+    pub device_id: u32,
+    pub closed_by: Pubkey,
 }
 
 // ============================================================================
-// Account Structs
+// Prekey Bundle Events
 // ============================================================================
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserAccount {
-    /// Owner's public key
+#[event]
+pub struct PrekeyBundlePublished {
+    pub username: String,
     pub owner: Pubkey,
+    pub identity_key: [u8; 32],
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekeys: u16,
+}
 
-    /// The username (lowercase, 3-20 chars)
-    #[max_len(20)]
+#[event]
+pub struct SignedPrekeyRotated {
     pub username: String,
+    pub signed_prekey: SignedPrekey,
+}
 
-    /// Unix timestamp of registration
-    pub created_at: i64,
+#[event]
+pub struct OneTimePrekeysAdded {
+    pub username: String,
+    pub added: u16,
+    pub available: u16,
+}
 
-    /// PDA bump seed
-    pub bump: u8,
+#[event]
+pub struct OneTimePrekeyConsumed {
+    pub username: String,
+    pub consumer: Pubkey,
+    /// None once the ring is empty
+    pub prekey: Option<OneTimePrekey>,
+}
 
-    // NEW: X25519 encryption public key (32 bytes)
-    pub encryption_key: [u8; 32],
+#[event]
+pub struct PrekeyBundleClosed {
+    pub username: String,
+    pub closed_by: Pubkey,
 }
 
 // ============================================================================
-// Group Chat Accounts
+// Group Chat Events
 // ============================================================================
 
-#[account]
-#[derive(InitSpace)]
-pub struct GroupAccount {
-    /// Group owner/creator
+#[event]
+pub struct GroupCreated {
+    pub group_id: [u8; 32],
     pub owner: Pubkey,
+    pub name: String,
+    pub description: String,
+    pub is_public: bool,
+    pub is_searchable: bool,
+    pub invite_only: bool,
+    pub max_members: u16,
+    pub allow_member_invites: bool,
+    pub key_commitment: [u8; 32],
+}
 
-    /// Unique group identifier (32-byte hash)
+#[event]
+pub struct GroupSettingsUpdated {
     pub group_id: [u8; 32],
+    pub updated_by: Pubkey,
+    pub settings: GroupSettingsUpdate,
+}
 
-    /// Public code for joining (e.g., "keyapp-general")
-    /// Empty string if no public code assigned
-    #[max_len(20)]
+#[event]
+pub struct GroupCodeSet {
+    pub group_id: [u8; 32],
     pub public_code: String,
+}
 
-    /// Group name (encrypted if private, plaintext if public)
-    #[max_len(100)]
-    pub name: String,
+#[event]
+pub struct GroupCodeChanged {
+    pub group_id: [u8; 32],
+    pub old_public_code: String,
+    pub new_public_code: String,
+}
 
-    /// Group description (encrypted if private, plaintext if public)
-    #[max_len(500)]
-    pub description: String,
+#[event]
+pub struct GroupCodeReleased {
+    pub group_id: [u8; 32],
+    pub public_code: String,
+}
 
-    /// Group avatar Arweave transaction ID
-    #[max_len(43)]
-    pub avatar_arweave_id: String,
+/// Emitted however the member got in; `invited_by` is the member itself,
+/// an invite link, an approver or an inviter, as on the member account
+#[event]
+pub struct MemberJoined {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub invited_by: Pubkey,
+    pub member_count: u16,
+}
+
+#[event]
+pub struct MemberInvited {
+    pub group_id: [u8; 32],
+    pub invited_user: Pubkey,
+    pub invited_by: Pubkey,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct InvitationDeclined {
+    pub group_id: [u8; 32],
+    pub invited_user: Pubkey,
+    pub invited_by: Pubkey,
+}
+
+#[event]
+pub struct InvitationRevoked {
+    pub group_id: [u8; 32],
+    pub invited_user: Pubkey,
+    pub invited_by: Pubkey,
+    pub revoked_by: Pubkey,
+}
+
+#[event]
+pub struct JoinRequested {
+    pub group_id: [u8; 32],
+    pub applicant: Pubkey,
+    pub encryption_key: [u8; 32],
+    pub note: Vec<u8>,
+}
+
+#[event]
+pub struct JoinRequestRejected {
+    pub group_id: [u8; 32],
+    pub applicant: Pubkey,
+    pub rejected_by: Pubkey,
+}
+
+#[event]
+pub struct MemberLeft {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub member_count: u16,
+}
 
-    /// Privacy settings
-    pub is_public: bool,
-    pub is_searchable: bool,
-    pub invite_only: bool,
+#[event]
+pub struct MemberKicked {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub kicked_by: Pubkey,
+    pub member_count: u16,
+}
 
-    /// Group settings
-    pub max_members: u16,
-    pub allow_member_invites: bool,
-    pub require_approval: bool,
+#[event]
+pub struct MemberMuted {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub muted_until: i64,
+    pub muted_by: Pubkey,
+}
 
-    /// Feature flags
-    pub enable_replies: bool,
-    pub enable_reactions: bool,
-    pub enable_read_receipts: bool,
-    pub enable_typing_indicators: bool,
+#[event]
+pub struct MemberUnmuted {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub unmuted_by: Pubkey,
+}
 
-    /// Encryption key for private groups (32 bytes, shared among members)
-    /// For public groups, this is zero/unused
-    pub group_encryption_key: [u8; 32],
+#[event]
+pub struct NotificationMuteSet {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub muted: bool,
+}
 
-    /// Member count (for quick lookup)
+#[event]
+pub struct MemberBanned {
+    pub group_id: [u8; 32],
+    pub user: Pubkey,
+    pub banned_by: Pubkey,
+    /// 0 = permanent
+    pub expires_at: i64,
+    pub reason: u8,
+    /// Whether a membership was removed along with the ban
+    pub removed_member: bool,
     pub member_count: u16,
+}
 
-    /// Timestamps
-    pub created_at: i64,
-    pub updated_at: i64,
+#[event]
+pub struct MemberUnbanned {
+    pub group_id: [u8; 32],
+    pub user: Pubkey,
+    pub unbanned_by: Pubkey,
+}
 
-    /// PDA bump
-    pub bump: u8,
+#[event]
+pub struct RoleChanged {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub old_role: GroupRole,
+    pub new_role: GroupRole,
+    pub permissions: u16,
+    pub changed_by: Pubkey,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct GroupMemberAccount {
-    /// The group this membership belongs to
+#[event]
+pub struct PermissionsGranted {
     pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub granted: u16,
+    pub permissions: u16,
+    pub granted_by: Pubkey,
+}
 
-    /// Member's public key
+#[event]
+pub struct PermissionsRevoked {
+    pub group_id: [u8; 32],
     pub member: Pubkey,
+    pub revoked: u16,
+    pub permissions: u16,
+    pub revoked_by: Pubkey,
+}
 
-    /// Role in the group
-    pub role: GroupRole,
+#[event]
+pub struct RolePermissionsUpdated {
+    pub group_id: [u8; 32],
+    pub role_permissions: RolePermissions,
+}
 
-    /// Permissions (bitflags)
+#[event]
+pub struct GroupRolesInitialized {
+    pub group_id: [u8; 32],
+}
+
+#[event]
+pub struct GroupRoleAdded {
+    pub group_id: [u8; 32],
+    pub role: CustomRole,
+}
+
+#[event]
+pub struct GroupRoleUpdated {
+    pub group_id: [u8; 32],
+    pub role: CustomRole,
+}
+
+#[event]
+pub struct GroupRoleRemoved {
+    pub group_id: [u8; 32],
+    pub role_id: u8,
+}
+
+#[event]
+pub struct GroupRoleAssigned {
+    pub group_id: [u8; 32],
+    pub member: Pubkey,
+    pub role_id: u8,
     pub permissions: u16,
+    pub assigned_by: Pubkey,
+}
 
-    /// Custom encryption key for this member (for key rotation)
-    /// Encrypted with member's X25519 public key
-    pub encrypted_group_key: [u8; 64],
+#[event]
+pub struct GroupOwnershipTransferProposed {
+    pub group_id: [u8; 32],
+    pub current_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
 
-    /// Join timestamp
-    pub joined_at: i64,
+#[event]
+pub struct GroupOwnershipTransferred {
+    pub group_id: [u8; 32],
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
 
-    /// Last read message timestamp (for read receipts)
-    pub last_read_at: i64,
+#[event]
+pub struct GroupOwnershipTransferCancelled {
+    pub group_id: [u8; 32],
+    pub current_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub cancelled_by: Pubkey,
+}
 
-    /// Status flags
-    pub is_active: bool,
-    pub is_muted: bool,
-    pub is_banned: bool,
+#[event]
+pub struct GroupSuccessorSet {
+    pub group_id: [u8; 32],
+    pub owner: Pubkey,
+    pub successor: Pubkey,
+    pub inactivity_period: i64,
+}
 
-    /// Invited by (for audit trail)
-    pub invited_by: Pubkey,
+#[event]
+pub struct GroupOwnershipClaimStarted {
+    pub group_id: [u8; 32],
+    pub owner: Pubkey,
+    pub successor: Pubkey,
+    /// Earliest time the successor can take the group
+    pub claimable_at: i64,
+}
 
-    /// PDA bump
-    pub bump: u8,
+#[event]
+pub struct OwnerActivityConfirmed {
+    pub group_id: [u8; 32],
+    pub owner: Pubkey,
+    /// Earliest time the successor can announce a claim
+    pub claimable_at: i64,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct InviteLinkAccount {
-    /// The group this invite is for
+#[event]
+pub struct GroupSuccessorCleared {
     pub group_id: [u8; 32],
+    pub successor: Pubkey,
+    pub cleared_by: Pubkey,
+}
 
-    /// Unique invite code (8-16 chars)
-    #[max_len(16)]
-    pub invite_code: String,
+#[event]
+pub struct GroupOwnershipClaimed {
+    pub group_id: [u8; 32],
+    pub previous_owner: Pubkey,
+    pub successor: Pubkey,
+}
 
-    /// Creator of the invite link
+#[event]
+pub struct InviteLinkCreated {
+    pub group_id: [u8; 32],
+    pub invite_code: String,
     pub created_by: Pubkey,
-
-    /// Expiration timestamp (0 = never expires)
     pub expires_at: i64,
-
-    /// Maximum uses (0 = unlimited)
     pub max_uses: u16,
+}
 
-    /// Current use count
-    pub use_count: u16,
+#[event]
+pub struct InviteLinkRevoked {
+    pub group_id: [u8; 32],
+    pub invite_code: String,
+    pub revoked_by: Pubkey,
+}
 
-    /// Creation timestamp
-    pub created_at: i64,
+#[event]
+pub struct GroupKeyMigrated {
+    pub group_id: [u8; 32],
+    /// Whether the group key was stored in plaintext before
+    pub plaintext_key: bool,
+}
 
-    /// Is active (can be revoked)
-    pub is_active: bool,
+#[event]
+pub struct GroupKeyRotationStarted {
+    pub group_id: [u8; 32],
+    pub key_epoch: u32,
+    pub key_commitment: [u8; 32],
+}
 
-    /// PDA bump
-    pub bump: u8,
+#[event]
+pub struct MemberKeysRewrapped {
+    pub group_id: [u8; 32],
+    pub key_epoch: u32,
+    pub members: Vec<Pubkey>,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct GroupCodeLookupAccount {
-    /// Public code (e.g., "keyapp")
-    #[max_len(20)]
-    pub public_code: String,
+#[event]
+pub struct GroupDissolved {
+    pub group_id: [u8; 32],
+    pub owner: Pubkey,
+    /// Member accounts left for close_dissolved_accounts
+    pub member_count: u16,
+}
 
-    /// The actual group_id this code points to
+#[event]
+pub struct DissolvedAccountsClosed {
     pub group_id: [u8; 32],
+    pub accounts_closed: u16,
+    /// None once the group itself has been closed
+    pub member_count: Option<u16>,
+}
 
-    /// PDA bump
-    pub bump: u8,
+#[event]
+pub struct DissolvedGroupClosed {
+    pub group_id: [u8; 32],
+    pub owner: Pubkey,
 }
 
 // ============================================================================
 // Enums
 // ============================================================================
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum KeyChangeReason {
+    Registered,  // Key supplied at registration
+    Rotated,     // Routine update by the owner
+    Transferred, // Key supplied by a new owner
+    Compromised, // Replacement for a revoked key
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
 pub enum GroupRole {
     Member,    // Basic member (default)
@@ -1051,6 +7516,153 @@ pub enum GroupRole {
     Owner,     // Full control (only one)
 }
 
+/// Instructions the program authority can pause, one bit each in
+/// `ProgramConfigAccount::paused`. New instructions are appended so existing
+/// bits keep their meaning
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operation {
+    RegisterUsername,
+    LookupUsername,
+    ProposeTransfer,
+    AcceptTransfer,
+    CancelTransfer,
+    CloseAccount,
+    RenewUsername,
+    ReclaimExpiredUsername,
+    MigrateUserAccount,
+    BackfillOwnerLookup,
+    UpdateEncryptionKey,
+    RevokeEncryptionKey,
+    InitKeyHistory,
+    AddDevice,
+    RotateDeviceKey,
+    RevokeDevice,
+    CloseDevice,
+    PublishPrekeyBundle,
+    RotateSignedPrekey,
+    AddOneTimePrekeys,
+    ConsumeOneTimePrekey,
+    ClosePrekeyBundle,
+    CreateGroup,
+    UpdateGroupSettings,
+    SetGroupCode,
+    ChangeGroupCode,
+    ReleaseGroupCode,
+    JoinGroup,
+    JoinWithInviteLink,
+    RequestToJoin,
+    ApproveJoinRequest,
+    LeaveGroup,
+    InviteMember,
+    KickMember,
+    BanMember,
+    UnbanMember,
+    UpdateMemberRole,
+    TransferGroupOwnership,
+    AcceptGroupOwnership,
+    CancelGroupOwnershipTransfer,
+    SetGroupSuccessor,
+    ConfirmOwnerActivity,
+    ClearGroupSuccessor,
+    ClaimGroupOwnership,
+    CreateInviteLink,
+    RevokeInviteLink,
+    MigrateGroupKey,
+    BeginKeyRotation,
+    RewrapMemberKeys,
+    DissolveGroup,
+    CloseDissolvedAccounts,
+    CloseDissolvedGroup,
+    LookupGroupByCode,
+    GrantPermissions,
+    RevokePermissions,
+    SetRolePermissions,
+    InitGroupRoles,
+    AddGroupRole,
+    UpdateGroupRole,
+    RemoveGroupRole,
+    AssignGroupRole,
+    MuteMember,
+    UnmuteMember,
+    SetNotificationMute,
+    RejectJoinRequest,
+    AcceptInvitation,
+    DeclineInvitation,
+    RevokeInvitation,
+    BeginOwnershipClaim,
+}
+
+// ============================================================================
+// Username Constants
+// ============================================================================
+
+/// Longest username the accounts have space for
+pub const MAX_USERNAME_LEN: usize = 20;
+
+/// Longest timelock an owner can put on a username transfer (30 days)
+pub const MAX_TRANSFER_TIMELOCK: i64 = 30 * 24 * 60 * 60;
+
+/// Size of the one-time prekey ring on a prekey bundle
+pub const MAX_ONE_TIME_PREKEYS: usize = 16;
+
+/// Entries kept in a username's key history
+pub const MAX_KEY_HISTORY: usize = 16;
+
+/// Active devices per username
+pub const MAX_DEVICES: usize = 8;
+
+/// Longest device label
+pub const MAX_DEVICE_LABEL_LEN: usize = 32;
+
+/// Length-based tiers in the registration fee schedule
+pub const MAX_FEE_TIERS: usize = 8;
+
+/// Names the registry config can reserve
+pub const MAX_RESERVED_NAMES: usize = 64;
+
+/// Shortest and longest username lease (30 days to 10 years)
+pub const MIN_LEASE_DURATION: i64 = 30 * 24 * 60 * 60;
+pub const MAX_LEASE_DURATION: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// Shortest and longest grace period after a lease runs out (7 to 180 days)
+pub const MIN_GRACE_PERIOD: i64 = 7 * 24 * 60 * 60;
+pub const MAX_GRACE_PERIOD: i64 = 180 * 24 * 60 * 60;
+
+// ============================================================================
+// Group Constants
+// ============================================================================
+
+/// Longest group name and description the group account has space for
+pub const MAX_GROUP_NAME_LEN: usize = 100;
+pub const MAX_GROUP_DESCRIPTION_LEN: usize = 500;
+
+/// Longest encrypted intro note on a join request
+pub const MAX_JOIN_NOTE_LEN: usize = 256;
+
+/// Shortest and longest time a direct invitation stays open (1 hour to 30 days)
+pub const MIN_INVITATION_TTL: i64 = 60 * 60;
+pub const MAX_INVITATION_TTL: i64 = 30 * 24 * 60 * 60;
+
+/// Most custom roles a group can define
+pub const MAX_CUSTOM_ROLES: usize = 16;
+/// Longest custom role name
+pub const MAX_ROLE_NAME_LEN: usize = 20;
+
+/// Size of a group account from when the group key was stored in plaintext,
+/// before key_epoch, dissolved_at and role_permissions were appended
+pub const PLAINTEXT_KEY_GROUP_LEN: usize =
+    8 + GroupAccount::INIT_SPACE - 4 - 8 - RolePermissions::INIT_SPACE;
+
+/// Shortest owner inactivity period before a successor can claim (7 days)
+pub const MIN_OWNER_INACTIVITY_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+/// Longest owner inactivity period before a successor can claim (2 years)
+pub const MAX_OWNER_INACTIVITY_PERIOD: i64 = 2 * 365 * 24 * 60 * 60;
+
+/// Notice the owner gets between a successor announcing a claim and taking
+/// the group (7 days)
+pub const OWNERSHIP_CLAIM_NOTICE_PERIOD: i64 = 7 * 24 * 60 * 60;
+
 // ============================================================================
 // Permission Constants
 // ============================================================================
@@ -1062,10 +7674,25 @@ pub const PERM_MANAGE_SETTINGS: u16 = 1 << 3;
 pub const PERM_DELETE_MESSAGES: u16 = 1 << 4;
 pub const PERM_PIN_MESSAGES: u16 = 1 << 5;
 pub const PERM_MANAGE_ROLES: u16 = 1 << 6;
+/// Every defined permission (the owner's set)
+pub const PERM_ALL: u16 = PERM_SEND_MESSAGES
+    | PERM_INVITE_MEMBERS
+    | PERM_KICK_MEMBERS
+    | PERM_MANAGE_SETTINGS
+    | PERM_DELETE_MESSAGES
+    | PERM_PIN_MESSAGES
+    | PERM_MANAGE_ROLES;
+
+/// Role template for new groups and groups migrated from older layouts
+pub const DEFAULT_ROLE_PERMISSIONS: RolePermissions = RolePermissions {
+    member: PERM_SEND_MESSAGES,
+    moderator: PERM_SEND_MESSAGES | PERM_INVITE_MEMBERS | PERM_KICK_MEMBERS,
+    admin: PERM_SEND_MESSAGES | PERM_INVITE_MEMBERS | PERM_KICK_MEMBERS | PERM_MANAGE_ROLES,
+};
 
 #[error_code]
 pub enum KeyError {
-    #[msg("Username must be 3-20 characters")]
+    #[msg("Username is shorter or longer than allowed")]
     InvalidUsernameLength,
 
     #[msg("Username can only contain letters, numbers, and underscores")]
@@ -1076,20 +7703,122 @@ pub enum KeyError {
 
     #[msg("Only the owner can perform this action")]
     NotOwner,
+
+    #[msg("Cannot transfer a username to its current owner")]
+    CannotTransferToSelf,
+
+    #[msg("Transfer timelock must be between 0 and 30 days")]
+    InvalidTransferTimelock,
+
+    #[msg("Transfer timelock has not elapsed yet")]
+    TransferLocked,
+
+    #[msg("Only the proposed recipient can accept this transfer")]
+    NotTransferRecipient,
+
+    #[msg("Only the owner or the recipient can cancel this transfer")]
+    NotTransferParty,
+
+    #[msg("Username owner changed since the transfer was proposed")]
+    StaleTransfer,
+
+    #[msg("Signed prekey is not signed by the identity key")]
+    InvalidPrekeySignature,
+
+    #[msg("Too many one-time prekeys (max 16)")]
+    TooManyOneTimePrekeys,
+
+    #[msg("Prekey bundle was published by a previous owner")]
+    StalePrekeyBundle,
+
+    #[msg("Username account already uses the current layout")]
+    AlreadyMigrated,
+
+    #[msg("Too many devices (max 8)")]
+    TooManyDevices,
+
+    #[msg("Device label must be at most 32 characters")]
+    InvalidDeviceLabelLength,
+
+    #[msg("Device has been revoked")]
+    DeviceRevoked,
+
+    #[msg("Revoke the device before closing it")]
+    DeviceStillActive,
+
+    #[msg("Key not found in the key history")]
+    KeyNotInHistory,
+
+    #[msg("Key has been marked compromised")]
+    KeyCompromised,
+
+    #[msg("Compromise time must be between the key's publication and now")]
+    InvalidCompromiseTime,
+
+    #[msg("Revoking the current key requires a replacement key")]
+    ReplacementKeyRequired,
+
+    #[msg("Username is reserved or too similar to a reserved name")]
+    ReservedUsername,
+
+    #[msg("Fee tiers must be in increasing order of name length (max 8)")]
+    InvalidFeeSchedule,
+
+    #[msg("Too many reserved names (max 64)")]
+    TooManyReservedNames,
+
+    #[msg("Name is already reserved")]
+    NameAlreadyReserved,
+
+    #[msg("Name is not reserved")]
+    NameNotReserved,
+
+    #[msg("Treasury balance is too low for this withdrawal")]
+    InsufficientTreasuryBalance,
+
+    #[msg("Leases must last 30 days to 10 years (or 0 for none), with a 7-180 day grace period")]
+    InvalidLeaseTerms,
+
+    #[msg("Username lease has expired")]
+    UsernameExpired,
+
+    #[msg("Username has no lease to renew")]
+    NoLease,
+
+    #[msg("Username lease and grace period have not run out")]
+    LeaseNotReclaimable,
+
+    #[msg("Pending transfer must be refunded to whoever proposed it")]
+    TransferProposerMismatch,
+}
+
+#[error_code]
+pub enum ConfigError {
+    #[msg("Only the program authority can perform this action")]
+    NotProgramAuthority,
+
+    #[msg("Only the proposed authority can accept the handover")]
+    NotPendingAuthority,
+
+    #[msg("This instruction is paused")]
+    InstructionPaused,
+
+    #[msg("Length limits are out of range")]
+    InvalidLengthLimits,
 }
 
 #[error_code]
 pub enum GroupError {
-    #[msg("Group name must be 1-100 characters")]
+    #[msg("Group name is empty or longer than allowed")]
     InvalidGroupNameLength,
 
-    #[msg("Group description must be 0-500 characters")]
+    #[msg("Group description is longer than allowed")]
     InvalidGroupDescriptionLength,
 
     #[msg("Public code must be 3-20 characters")]
     InvalidPublicCodeLength,
 
-    #[msg("Public code can only contain lowercase letters, numbers, and hyphens")]
+    #[msg("Public code can only contain letters, numbers, and hyphens")]
     InvalidPublicCodeCharacters,
 
     #[msg("Group is full (max members reached)")]
@@ -1145,4 +7874,130 @@ pub enum GroupError {
 
     #[msg("Invalid group ID")]
     InvalidGroupId,
+
+    #[msg("Group account already uses the current layout")]
+    GroupAlreadyMigrated,
+
+    #[msg("Key epoch does not match the group's current epoch")]
+    StaleKeyEpoch,
+
+    #[msg("Member accounts do not match the rewrapped keys")]
+    RewrapAccountMismatch,
+
+    #[msg("Group key epoch overflow")]
+    KeyEpochOverflow,
+
+    #[msg("Group can only be joined by invitation")]
+    InviteRequired,
+
+    #[msg("Group does not require approval; join directly")]
+    ApprovalNotRequired,
+
+    #[msg("Cannot ban yourself")]
+    CannotBanSelf,
+
+    #[msg("Cannot ban the group owner")]
+    CannotBanOwner,
+
+    #[msg("Ban expiry must be in the future (or 0 for permanent)")]
+    InvalidBanExpiry,
+
+    #[msg("Max members cannot be lower than the current member count")]
+    MaxMembersBelowMemberCount,
+
+    #[msg("Cannot hand the group to yourself")]
+    CannotTransferToSelf,
+
+    #[msg("Only the proposed owner can accept this transfer")]
+    NotTransferRecipient,
+
+    #[msg("Only the owner or the proposed owner can cancel this transfer")]
+    NotTransferParty,
+
+    #[msg("Group owner changed since this transfer was proposed")]
+    StaleOwnershipTransfer,
+
+    #[msg("Owner inactivity period must be between 7 days and 2 years")]
+    InvalidInactivityPeriod,
+
+    #[msg("Only the configured successor can claim the group")]
+    NotGroupSuccessor,
+
+    #[msg("Group owner changed since the successor was named")]
+    StaleSuccession,
+
+    #[msg("Owner has not been inactive long enough")]
+    OwnerStillActive,
+
+    #[msg("Group has been dissolved")]
+    GroupDissolved,
+
+    #[msg("Group has not been dissolved")]
+    GroupNotDissolved,
+
+    #[msg("Group still has members")]
+    GroupNotEmpty,
+
+    #[msg("Account does not belong to this group or refund recipient is wrong")]
+    DissolveAccountMismatch,
+
+    #[msg("Group already has a public code; use change_group_code")]
+    PublicCodeAlreadySet,
+
+    #[msg("Group has no public code")]
+    NoPublicCode,
+
+    #[msg("Public code is reserved or too similar to a reserved name")]
+    ReservedPublicCode,
+
+    #[msg("Permission mask is empty or has undefined bits")]
+    InvalidPermissions,
+
+    #[msg("Role name is empty or too long")]
+    InvalidRoleName,
+
+    #[msg("Custom roles must rank below the owner")]
+    InvalidRoleRank,
+
+    #[msg("Group has too many custom roles")]
+    TooManyRoles,
+
+    #[msg("Custom role not found")]
+    RoleNotFound,
+
+    #[msg("Cannot mute the group owner")]
+    CannotMuteOwner,
+
+    #[msg("Mute expiry must be in the future")]
+    InvalidMuteExpiry,
+
+    #[msg("Join request note is too long")]
+    JoinNoteTooLong,
+
+    #[msg("Invitation must stay open between 1 hour and 30 days")]
+    InvalidInvitationTtl,
+
+    #[msg("Invitation has expired")]
+    InvitationExpired,
+
+    #[msg("Inviter does not match the invitation")]
+    InvalidInvitation,
+
+    #[msg("Ownership claim already announced")]
+    OwnershipClaimAlreadyStarted,
+
+    #[msg("Ownership claim not announced or its notice period has not passed")]
+    OwnershipClaimNotReady,
+
+    #[msg("Join request holds no deposit for the member account")]
+    MissingJoinDeposit,
+
+    #[msg("Pending transfer must be refunded to whoever proposed it")]
+    TransferProposerMismatch,
+
+    #[msg("Succession must be refunded to the owner who named the successor")]
+    SuccessionOwnerMismatch,
+
+    #[msg("Membership must be refunded to whoever paid for it")]
+    RentPayerMismatch,
 }
@@ -0,0 +1,65 @@
+//! Tests for the canonical forms of usernames, group public codes and invite
+//! codes shared by every PDA derivation.
+
+use anchor_lang::error::Error;
+use key_registry::canonical::{self, is_reserved};
+use key_registry::{GroupError, KeyError};
+
+#[test]
+fn names_are_lowercased() {
+    assert_eq!(canonical::normalize("AliCe_1"), "alice_1");
+    assert_eq!(canonical::username("AliCe_1", 3..=20).unwrap(), "alice_1");
+    assert_eq!(canonical::public_code("Key-Fans").unwrap(), "key-fans");
+    assert_eq!(canonical::invite_code("JOINUS42").unwrap(), "joinus42");
+}
+
+#[test]
+fn reserved_names_and_lookalikes_are_reserved() {
+    for name in [
+        "admin", "adm1n", "4dmin", "supp0rt", "sys7em", "key_app", "k-e-y",
+    ] {
+        assert!(is_reserved(name), "{name} should be reserved");
+    }
+    for name in ["alice", "keyboard", "admins", "supporters", "bob_1"] {
+        assert!(!is_reserved(name), "{name} should be allowed");
+    }
+}
+
+#[test]
+fn homoglyph_sequences_collapse() {
+    // "rn" reads as "m", "vv" as "w"
+    assert!(is_reserved("rnod"));
+    assert!(is_reserved("rnoderator"));
+}
+
+#[test]
+fn validation_errors_are_reported_per_kind() {
+    assert_eq!(
+        canonical::username("ab", 3..=20).unwrap_err(),
+        Error::from(KeyError::InvalidUsernameLength)
+    );
+    assert_eq!(
+        canonical::username("ali-ce", 3..=20).unwrap_err(),
+        Error::from(KeyError::InvalidUsernameCharacters)
+    );
+    assert_eq!(
+        canonical::username("Support", 3..=20).unwrap_err(),
+        Error::from(KeyError::ReservedUsername)
+    );
+    assert_eq!(
+        canonical::public_code("key_fans").unwrap_err(),
+        Error::from(GroupError::InvalidPublicCodeCharacters)
+    );
+    assert_eq!(
+        canonical::public_code("Official").unwrap_err(),
+        Error::from(GroupError::ReservedPublicCode)
+    );
+    assert_eq!(
+        canonical::invite_code("short").unwrap_err(),
+        Error::from(GroupError::InvalidInviteCodeLength)
+    );
+    assert_eq!(
+        canonical::invite_code("join-us-42").unwrap_err(),
+        Error::from(GroupError::InvalidInviteCodeCharacters)
+    );
+}
@@ -0,0 +1,428 @@
+//! In-memory harness that dispatches instructions through `key_registry::entry`.
+//!
+//! Each account is laid out the way the runtime serializes it (original data
+//! length and key ahead of the data, realloc headroom behind it), so Anchor's
+//! `init`, `close` and `realloc` work without a validator. System program CPIs
+//! and the Clock/Rent sysvars are emulated by [`Stubs`], which also collects
+//! emitted events.
+
+#![allow(dead_code)]
+
+use std::cell::{Cell, RefCell};
+use std::sync::Once;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{
+    ProgramResult, MAX_PERMITTED_DATA_INCREASE, SUCCESS,
+};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::program_utils::limited_deserialize;
+use anchor_lang::solana_program::system_instruction::{SystemError, SystemInstruction};
+use anchor_lang::solana_program::sysvar::{
+    self,
+    instructions::{self, construct_instructions_data, store_current_index, BorrowedInstruction},
+};
+use anchor_lang::{
+    system_program, AccountDeserialize, AccountSerialize, Event, InstructionData, Space,
+};
+use key_registry::{instruction, LengthLimits, ProgramConfigAccount, TreasuryAccount, UserAccount};
+
+const NATIVE_LOADER_ID: &str = "NativeLoader1111111111111111111111111111111";
+
+const ORIGINAL_LEN_OFFSET: usize = 4;
+const KEY_OFFSET: usize = 8;
+const LEN_OFFSET: usize = KEY_OFFSET + 32;
+const DATA_OFFSET: usize = LEN_OFFSET + 8;
+
+thread_local! {
+    static NOW: Cell<i64> = const { Cell::new(1_700_000_000) };
+    static EVENTS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Set the `unix_timestamp` returned by `Clock::get()` on this thread.
+pub fn set_clock(unix_timestamp: i64) {
+    NOW.with(|now| now.set(unix_timestamp));
+}
+
+pub fn now() -> i64 {
+    NOW.with(Cell::get)
+}
+
+/// Events of type `T` emitted by the last successful [`run`] on this thread.
+pub fn events<T: Event>() -> Vec<T> {
+    EVENTS.with(|events| {
+        events
+            .borrow()
+            .iter()
+            .filter_map(|data| data.strip_prefix(&T::DISCRIMINATOR[..]))
+            .map(|mut data| T::deserialize(&mut data).unwrap())
+            .collect()
+    })
+}
+
+/// The authority of [`TestAccount::program_config`].
+pub fn program_authority() -> Pubkey {
+    Pubkey::new_from_array([0xAA; 32])
+}
+
+/// A PDA of the program under test.
+pub fn pda(seeds: &[&[u8]]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(seeds, &key_registry::ID)
+}
+
+/// `owner`'s "alice" username, with key `[7; 32]`, no devices and no lease.
+pub fn user(owner: Pubkey) -> UserAccount {
+    UserAccount {
+        owner,
+        username: "alice".to_string(),
+        created_at: 0,
+        bump: pda(&[b"username", b"alice"]).1,
+        encryption_key: [7u8; 32],
+        devices: vec![],
+        expires_at: 0,
+    }
+}
+
+/// `user` stored at its username PDA.
+pub fn user_account(user: &UserAccount) -> TestAccount {
+    TestAccount::program_account(pda(&[b"username", user.username.as_bytes()]).0, user)
+}
+
+/// The treasury registration fees are paid into.
+pub fn treasury() -> TestAccount {
+    let (key, bump) = pda(&[b"treasury"]);
+    TestAccount::program_account(key, &TreasuryAccount { bump })
+}
+
+/// Register `username` with key `[1; 32]`.
+pub fn register_username(username: &str) -> Vec<u8> {
+    instruction::RegisterUsername {
+        username: username.to_string(),
+        encryption_key: [1u8; 32],
+    }
+    .data()
+}
+
+/// Look up the "alice" username.
+pub fn lookup_username() -> Vec<u8> {
+    instruction::LookupUsername {
+        _username: "alice".to_string(),
+    }
+    .data()
+}
+
+#[derive(Clone)]
+pub struct TestAccount {
+    region: Vec<u64>,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub executable: bool,
+}
+
+impl TestAccount {
+    pub fn new(key: Pubkey, owner: Pubkey, lamports: u64, data: &[u8]) -> Self {
+        let mut account = Self {
+            region: vec![],
+            owner,
+            lamports,
+            is_signer: false,
+            is_writable: true,
+            executable: false,
+        };
+        account.reserve(data.len());
+        let bytes = account.bytes_mut();
+        bytes[KEY_OFFSET..LEN_OFFSET].copy_from_slice(key.as_ref());
+        bytes[LEN_OFFSET..DATA_OFFSET].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes[DATA_OFFSET..DATA_OFFSET + data.len()].copy_from_slice(data);
+        account
+    }
+
+    /// A funded system wallet that signs the transaction.
+    pub fn signer(key: Pubkey) -> Self {
+        Self {
+            is_signer: true,
+            ..Self::wallet(key)
+        }
+    }
+
+    /// A funded system wallet that does not sign.
+    pub fn wallet(key: Pubkey) -> Self {
+        Self::new(key, system_program::ID, 10_000_000_000, &[])
+    }
+
+    /// An address with no lamports or data, ready to be `init`ed.
+    pub fn uninit(key: Pubkey) -> Self {
+        Self::new(key, system_program::ID, 0, &[])
+    }
+
+    /// A program-owned account holding `account`, padded to its full space.
+    pub fn program_account<T: AccountSerialize + Space>(key: Pubkey, account: &T) -> Self {
+        let mut data = vec![];
+        account.try_serialize(&mut data).unwrap();
+        data.resize(8 + T::INIT_SPACE, 0);
+        Self::new(key, key_registry::ID, 10_000_000, &data)
+    }
+
+    /// The program config, with nothing paused and the default limits.
+    pub fn program_config() -> Self {
+        Self::program_config_with(|_| {})
+    }
+
+    /// The program config after `edit`, e.g. to pause an instruction.
+    pub fn program_config_with(edit: impl FnOnce(&mut ProgramConfigAccount)) -> Self {
+        let (key, bump) = Pubkey::find_program_address(&[b"program_config"], &key_registry::ID);
+        let mut config = ProgramConfigAccount {
+            authority: program_authority(),
+            pending_authority: Pubkey::default(),
+            paused: 0,
+            limits: LengthLimits {
+                min_username_len: 3,
+                max_username_len: 20,
+                max_group_name_len: 100,
+                max_group_description_len: 500,
+            },
+            bump,
+        };
+        edit(&mut config);
+        Self::program_account(key, &config)
+    }
+
+    /// Stands in for an optional account that is not provided.
+    pub fn none() -> Self {
+        Self {
+            is_writable: false,
+            ..Self::new(key_registry::ID, system_program::ID, 0, &[])
+        }
+    }
+
+    pub fn system_program() -> Self {
+        Self {
+            is_writable: false,
+            executable: true,
+            ..Self::new(
+                system_program::ID,
+                NATIVE_LOADER_ID.parse().unwrap(),
+                1,
+                &[],
+            )
+        }
+    }
+
+    /// The instructions sysvar for a transaction made of `instructions`
+    /// (program id and data), currently executing the one at `current`.
+    pub fn instructions_sysvar(instructions: &[(Pubkey, Vec<u8>)], current: u16) -> Self {
+        let borrowed: Vec<BorrowedInstruction> = instructions
+            .iter()
+            .map(|(program_id, data)| BorrowedInstruction {
+                program_id,
+                accounts: vec![],
+                data,
+            })
+            .collect();
+        let mut data = construct_instructions_data(&borrowed);
+        store_current_index(&mut data, current);
+        Self {
+            is_writable: false,
+            ..Self::new(instructions::ID, sysvar::ID, 1, &data)
+        }
+    }
+
+    pub fn key(&self) -> Pubkey {
+        Pubkey::try_from(&self.bytes()[KEY_OFFSET..LEN_OFFSET]).unwrap()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.bytes()[DATA_OFFSET..DATA_OFFSET + self.data_len()]
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let len = self.data_len();
+        &mut self.bytes_mut()[DATA_OFFSET..DATA_OFFSET + len]
+    }
+
+    pub fn decode<T: AccountDeserialize>(&self) -> T {
+        T::try_deserialize(&mut self.data()).unwrap()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lamports == 0 && self.owner == system_program::ID && self.data().is_empty()
+    }
+
+    fn data_len(&self) -> usize {
+        let len = &self.bytes()[LEN_OFFSET..DATA_OFFSET];
+        u64::from_le_bytes(len.try_into().unwrap()) as usize
+    }
+
+    /// Record the current length as the original one and make sure the
+    /// runtime's realloc headroom is available behind it.
+    fn reserve(&mut self, len: usize) {
+        let bytes = DATA_OFFSET + len + MAX_PERMITTED_DATA_INCREASE;
+        self.region.resize(bytes.div_ceil(8), 0);
+        self.bytes_mut()[ORIGINAL_LEN_OFFSET..KEY_OFFSET]
+            .copy_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: a `[u64]` is always a valid `[u8]` of eight times the length.
+        unsafe { std::slice::from_raw_parts(self.region.as_ptr().cast(), self.region.len() * 8) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: see `bytes`.
+        unsafe {
+            std::slice::from_raw_parts_mut(self.region.as_mut_ptr().cast(), self.region.len() * 8)
+        }
+    }
+
+    fn info(&mut self) -> AccountInfo<'_> {
+        let len = self.data_len();
+        self.reserve(len);
+        let base: *mut u8 = self.region.as_mut_ptr().cast();
+        // SAFETY: the key and data live inside `region`, which is not touched
+        // again until the returned `AccountInfo` is dropped.
+        let (key, data) = unsafe {
+            (
+                &*(base.add(KEY_OFFSET) as *const Pubkey),
+                std::slice::from_raw_parts_mut(base.add(DATA_OFFSET), len),
+            )
+        };
+        AccountInfo::new(
+            key,
+            self.is_signer,
+            self.is_writable,
+            &mut self.lamports,
+            data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+/// Run a single instruction against `accounts`, in order. Like a failed
+/// transaction, an error leaves the accounts untouched.
+pub fn run(accounts: &mut [TestAccount], data: Vec<u8>) -> std::result::Result<(), ProgramError> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(Stubs));
+    });
+
+    let snapshot = accounts.to_vec();
+    EVENTS.with(|events| events.borrow_mut().clear());
+    let result = {
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+        key_registry::entry(&key_registry::ID, &infos, &data)
+    };
+    if result.is_err() {
+        accounts.clone_from_slice(&snapshot);
+        EVENTS.with(|events| events.borrow_mut().clear());
+    }
+    result
+}
+
+pub fn anchor_err(code: anchor_lang::error::ErrorCode) -> ProgramError {
+    ProgramError::Custom(code.into())
+}
+
+pub fn program_err(code: impl Into<u32>) -> ProgramError {
+    ProgramError::Custom(code.into())
+}
+
+pub fn account_in_use() -> ProgramError {
+    ProgramError::Custom(SystemError::AccountAlreadyInUse as u32)
+}
+
+/// Syscall stubs covering what the program needs from the runtime.
+struct Stubs;
+
+impl Stubs {
+    fn move_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+        let remaining = from
+            .lamports()
+            .checked_sub(lamports)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        **from.try_borrow_mut_lamports()? = remaining;
+        **to.try_borrow_mut_lamports()? += lamports;
+        Ok(())
+    }
+
+    fn allocate(account: &AccountInfo, space: u64) -> ProgramResult {
+        if *account.owner != system_program::ID || !account.data_is_empty() {
+            return Err(account_in_use());
+        }
+        account.realloc(space as usize, true)
+    }
+}
+
+impl SyscallStubs for Stubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        assert_eq!(
+            instruction.program_id,
+            system_program::ID,
+            "only system program CPIs are emulated"
+        );
+        let account = |index: usize| {
+            let key = instruction.accounts[index].pubkey;
+            account_infos
+                .iter()
+                .find(|info| *info.key == key)
+                .expect("CPI account missing from account infos")
+        };
+
+        match limited_deserialize(&instruction.data, 1024)
+            .map_err(|_| ProgramError::InvalidInstructionData)?
+        {
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                let to = account(1);
+                if to.lamports() != 0 {
+                    return Err(ProgramError::Custom(
+                        SystemError::AccountAlreadyInUse as u32,
+                    ));
+                }
+                Self::move_lamports(account(0), to, lamports)?;
+                Self::allocate(to, space)?;
+                to.assign(&owner);
+            }
+            SystemInstruction::Transfer { lamports } => {
+                Self::move_lamports(account(0), account(1), lamports)?
+            }
+            SystemInstruction::Allocate { space } => Self::allocate(account(0), space)?,
+            SystemInstruction::Assign { owner } => account(0).assign(&owner),
+            other => panic!("unsupported system instruction {other:?}"),
+        }
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            unix_timestamp: now(),
+            ..Clock::default()
+        };
+        // SAFETY: the caller passes a pointer to a `Clock`.
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        EVENTS.with(|events| events.borrow_mut().push(fields.concat()));
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // SAFETY: the caller passes a pointer to a `Rent`.
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+}
@@ -0,0 +1,218 @@
+//! Tests for per-device encryption keys under a username and the migration
+//! of username accounts created before the device list existed.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountSerialize, InstructionData, Space};
+use common::{pda, program_err, run, user, user_account, TestAccount};
+use key_registry::{instruction, DeviceAccount, KeyError, UserAccount, ID, MAX_DEVICES};
+
+fn device_pda(device_id: u32) -> (Pubkey, u8) {
+    pda(&[b"device", b"alice", &device_id.to_le_bytes()])
+}
+
+fn device_account(owner: Pubkey, device_id: u32, revoked: bool) -> TestAccount {
+    let (key, bump) = device_pda(device_id);
+    TestAccount::program_account(
+        key,
+        &DeviceAccount {
+            owner,
+            username: "alice".to_string(),
+            device_id,
+            encryption_key: [device_id as u8; 32],
+            label: "Phone".to_string(),
+            created_at: 0,
+            last_rotated_at: 0,
+            revoked,
+            bump,
+        },
+    )
+}
+
+fn add_device(device_id: u32) -> Vec<u8> {
+    instruction::AddDevice {
+        _username: "alice".to_string(),
+        device_id,
+        encryption_key: [device_id as u8; 32],
+        label: "Laptop".to_string(),
+    }
+    .data()
+}
+
+fn close_device(device_id: u32) -> Vec<u8> {
+    instruction::CloseDevice {
+        _username: "alice".to_string(),
+        device_id,
+    }
+    .data()
+}
+
+#[test]
+fn add_rotate_and_revoke_device() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&user(alice)),
+        TestAccount::uninit(device_pda(3).0),
+        TestAccount::signer(alice),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(&mut accounts, add_device(3)).unwrap();
+    assert_eq!(accounts[0].decode::<UserAccount>().devices, vec![3]);
+    let device: DeviceAccount = accounts[1].decode();
+    assert_eq!(device.owner, alice);
+    assert_eq!(device.encryption_key, [3u8; 32]);
+    assert_eq!(device.label, "Laptop");
+
+    let mut accounts = vec![
+        accounts[0].clone(),
+        accounts[1].clone(),
+        TestAccount::signer(alice),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::RotateDeviceKey {
+            _username: "alice".to_string(),
+            device_id: 3,
+            new_encryption_key: [9u8; 32],
+        }
+        .data(),
+    )
+    .unwrap();
+    assert_eq!(
+        accounts[1].decode::<DeviceAccount>().encryption_key,
+        [9u8; 32]
+    );
+
+    run(
+        &mut accounts,
+        instruction::RevokeDevice {
+            _username: "alice".to_string(),
+            device_id: 3,
+        }
+        .data(),
+    )
+    .unwrap();
+    assert!(accounts[0].decode::<UserAccount>().devices.is_empty());
+    assert!(accounts[1].decode::<DeviceAccount>().revoked);
+}
+
+#[test]
+fn device_list_is_bounded() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&UserAccount {
+            devices: (0..MAX_DEVICES as u32).collect(),
+            ..user(alice)
+        }),
+        TestAccount::uninit(device_pda(100).0),
+        TestAccount::signer(alice),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    assert_eq!(
+        run(&mut accounts, add_device(100)),
+        Err(program_err(KeyError::TooManyDevices))
+    );
+}
+
+#[test]
+fn non_owner_cannot_add_device() {
+    let alice = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&user(alice)),
+        TestAccount::uninit(device_pda(1).0),
+        TestAccount::signer(mallory),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    assert_eq!(
+        run(&mut accounts, add_device(1)),
+        Err(program_err(KeyError::NotOwner))
+    );
+}
+
+#[test]
+fn only_revoked_devices_can_be_closed() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&UserAccount {
+            devices: vec![1],
+            ..user(alice)
+        }),
+        device_account(alice, 1, false),
+        TestAccount::wallet(alice),
+        TestAccount::signer(alice),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, close_device(1)),
+        Err(program_err(KeyError::DeviceStillActive))
+    );
+
+    accounts[1] = device_account(alice, 1, true);
+    run(&mut accounts, close_device(1)).unwrap();
+    assert!(accounts[1].is_closed());
+}
+
+#[test]
+fn new_owner_can_close_stale_device_refunding_previous_owner() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    // The username now belongs to Bob, but the device is still Alice's
+    let mut accounts = vec![
+        user_account(&user(bob)),
+        device_account(alice, 1, false),
+        TestAccount::wallet(alice),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ];
+    let lamports = accounts[2].lamports;
+
+    run(&mut accounts, close_device(1)).unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].lamports > lamports);
+}
+
+#[test]
+fn legacy_user_account_is_migrated() {
+    let alice = Pubkey::new_unique();
+    let payer = Pubkey::new_unique();
+    // Accounts registered before the device list end at the encryption key
+    let mut data = vec![];
+    user(alice).try_serialize(&mut data).unwrap();
+    data.truncate(data.len() - 4 - 8);
+    let mut accounts = vec![
+        TestAccount::new(pda(&[b"username", b"alice"]).0, ID, 1_000_000, &data),
+        TestAccount::signer(payer),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    let migrate = instruction::MigrateUserAccount {
+        _username: "alice".to_string(),
+    }
+    .data();
+
+    run(&mut accounts, migrate.clone()).unwrap();
+
+    assert_eq!(accounts[0].data().len(), 8 + UserAccount::INIT_SPACE);
+    assert_eq!(
+        accounts[0].lamports,
+        Rent::default().minimum_balance(8 + UserAccount::INIT_SPACE)
+    );
+    let migrated: UserAccount = accounts[0].decode();
+    assert_eq!(migrated.owner, alice);
+    assert!(migrated.devices.is_empty());
+
+    assert_eq!(
+        run(&mut accounts, migrate),
+        Err(program_err(KeyError::AlreadyMigrated))
+    );
+}
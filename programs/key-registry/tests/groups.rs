@@ -0,0 +1,2831 @@
+//! Tests for group chats: creation, membership and the group key, which is
+//! only ever stored on chain as a commitment and per-member encrypted copies.
+
+mod common;
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::{AccountSerialize, InstructionData, Space};
+use common::{account_in_use, anchor_err, events, now, program_err, run, set_clock, TestAccount};
+use key_registry::{
+    instruction, BanAccount, CustomRole, GroupAccount, GroupCodeLookupAccount, GroupError,
+    GroupInvitationAccount, GroupKeyMigrated, GroupMemberAccount, GroupOwnershipClaimStarted,
+    GroupOwnershipTransferred, GroupRole, GroupRolesAccount, GroupSettingsUpdate,
+    GroupSuccessionAccount, GroupTombstoneAccount, InvitationDeclined, InvitationRevoked,
+    InviteLinkAccount, JoinRequestAccount, MemberBanned, MemberInvited, MemberJoined, MemberMuted,
+    PendingGroupTransferAccount, PermissionsGranted, RewrappedKey, RolePermissions,
+    DEFAULT_ROLE_PERMISSIONS, ID, MAX_INVITATION_TTL, MAX_JOIN_NOTE_LEN, MIN_INVITATION_TTL,
+    MIN_OWNER_INACTIVITY_PERIOD, OWNERSHIP_CLAIM_NOTICE_PERIOD, PERM_ALL, PERM_DELETE_MESSAGES,
+    PERM_INVITE_MEMBERS, PERM_KICK_MEMBERS, PERM_MANAGE_SETTINGS, PERM_PIN_MESSAGES,
+    PERM_SEND_MESSAGES, PLAINTEXT_KEY_GROUP_LEN,
+};
+
+const GROUP_ID: [u8; 32] = [42u8; 32];
+
+fn group_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group", GROUP_ID.as_ref()], &ID)
+}
+
+fn member_pda(user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:member", GROUP_ID.as_ref(), user.as_ref()], &ID)
+}
+
+fn ban_pda(user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:ban", GROUP_ID.as_ref(), user.as_ref()], &ID)
+}
+
+fn request_pda(user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:request", GROUP_ID.as_ref(), user.as_ref()], &ID)
+}
+
+fn invitation_pda(user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"group:invitation", GROUP_ID.as_ref(), user.as_ref()],
+        &ID,
+    )
+}
+
+fn code_pda(code: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:code", code.as_bytes()], &ID)
+}
+
+fn transfer_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:transfer", GROUP_ID.as_ref()], &ID)
+}
+
+fn succession_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:succession", GROUP_ID.as_ref()], &ID)
+}
+
+fn roles_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"group:roles", GROUP_ID.as_ref()], &ID)
+}
+
+fn invite_pda(code: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"group:invite",
+            GROUP_ID.as_ref(),
+            code.to_lowercase().as_bytes(),
+        ],
+        &ID,
+    )
+}
+
+fn group(owner: Pubkey) -> GroupAccount {
+    GroupAccount {
+        owner,
+        group_id: GROUP_ID,
+        public_code: String::new(),
+        name: "Friends".to_string(),
+        description: String::new(),
+        avatar_arweave_id: String::new(),
+        is_public: false,
+        is_searchable: false,
+        invite_only: false,
+        max_members: 0,
+        allow_member_invites: false,
+        require_approval: false,
+        enable_replies: true,
+        enable_reactions: true,
+        enable_read_receipts: true,
+        enable_typing_indicators: true,
+        key_commitment: [3u8; 32],
+        member_count: 1,
+        created_at: 0,
+        updated_at: 0,
+        bump: group_pda().1,
+        key_epoch: 0,
+        dissolved_at: 0,
+        role_permissions: DEFAULT_ROLE_PERMISSIONS,
+    }
+}
+
+fn member(user: Pubkey, role: GroupRole) -> GroupMemberAccount {
+    GroupMemberAccount {
+        group_id: GROUP_ID,
+        member: user,
+        role,
+        permissions: PERM_SEND_MESSAGES,
+        encrypted_group_key: [4u8; 64],
+        joined_at: 0,
+        last_read_at: 0,
+        is_active: true,
+        is_muted: false,
+        is_banned: false,
+        invited_by: user,
+        bump: member_pda(&user).1,
+        key_epoch: 0,
+        rent_payer: user,
+        role_id: 0,
+        muted_until: 0,
+        notifications_muted: false,
+    }
+}
+
+fn group_account(group: &GroupAccount) -> TestAccount {
+    TestAccount::program_account(group_pda().0, group)
+}
+
+fn member_account(member: &GroupMemberAccount) -> TestAccount {
+    TestAccount::program_account(member_pda(&member.member).0, member)
+}
+
+/// A group anyone can find and join.
+fn open_group(owner: Pubkey) -> GroupAccount {
+    GroupAccount {
+        is_searchable: true,
+        ..group(owner)
+    }
+}
+
+fn ban(user: Pubkey, banned_by: Pubkey, expires_at: i64) -> BanAccount {
+    BanAccount {
+        group_id: GROUP_ID,
+        user,
+        banned_by,
+        banned_at: 0,
+        expires_at,
+        reason: 1,
+        bump: ban_pda(&user).1,
+    }
+}
+
+/// A permanent ban on `user`.
+fn ban_record(user: &Pubkey) -> TestAccount {
+    TestAccount::program_account(ban_pda(user).0, &ban(*user, Pubkey::new_unique(), 0))
+}
+
+fn ban_accounts(
+    group: &GroupAccount,
+    banner: &GroupMemberAccount,
+    user: Pubkey,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        member_account(banner),
+        TestAccount::uninit(member_pda(&user).0),
+        TestAccount::uninit(ban_pda(&user).0),
+        TestAccount::wallet(user),
+        TestAccount::wallet(user),
+        TestAccount::uninit(roles_pda().0),
+        TestAccount::signer(banner.member),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn ban_member(expires_at: i64) -> Vec<u8> {
+    instruction::BanMember {
+        group_id: GROUP_ID,
+        expires_at,
+        reason: 1,
+    }
+    .data()
+}
+
+fn join_accounts(group: &GroupAccount, user: Pubkey) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        TestAccount::uninit(member_pda(&user).0),
+        TestAccount::uninit(ban_pda(&user).0),
+        TestAccount::signer(user),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn join() -> Vec<u8> {
+    instruction::JoinGroup {
+        group_id: GROUP_ID,
+        encrypted_group_key: [5u8; 64],
+    }
+    .data()
+}
+
+fn invite_link(created_by: Pubkey, expires_at: i64, max_uses: u16) -> InviteLinkAccount {
+    InviteLinkAccount {
+        group_id: GROUP_ID,
+        invite_code: "joinus42".to_string(),
+        created_by,
+        expires_at,
+        max_uses,
+        use_count: 0,
+        created_at: 0,
+        is_active: true,
+        bump: invite_pda("JOINUS42").1,
+    }
+}
+
+fn redeem_accounts(
+    group: &GroupAccount,
+    link: &InviteLinkAccount,
+    user: Pubkey,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        TestAccount::program_account(invite_pda(&link.invite_code).0, link),
+        TestAccount::uninit(member_pda(&user).0),
+        TestAccount::uninit(ban_pda(&user).0),
+        TestAccount::signer(user),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn redeem() -> Vec<u8> {
+    instruction::JoinWithInviteLink {
+        group_id: GROUP_ID,
+        _invite_code: "JOINUS42".to_string(),
+        encrypted_group_key: [5u8; 64],
+    }
+    .data()
+}
+
+fn begin_key_rotation(commitment: [u8; 32]) -> Vec<u8> {
+    instruction::BeginKeyRotation {
+        _group_id: GROUP_ID,
+        new_key_commitment: commitment,
+    }
+    .data()
+}
+
+fn rewrap(key_epoch: u32, members: &[Pubkey]) -> Vec<u8> {
+    instruction::RewrapMemberKeys {
+        group_id: GROUP_ID,
+        key_epoch,
+        keys: members
+            .iter()
+            .map(|&member| RewrappedKey {
+                member,
+                encrypted_group_key: [key_epoch as u8; 64],
+            })
+            .collect(),
+    }
+    .data()
+}
+
+#[test]
+fn create_group_stores_only_key_commitment() {
+    let owner = Pubkey::new_unique();
+    let group_key = [8u8; 32];
+    let commitment = hash(&group_key).to_bytes();
+    let mut accounts = vec![
+        TestAccount::uninit(group_pda().0),
+        TestAccount::uninit(member_pda(&owner).0),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(
+        &mut accounts,
+        instruction::CreateGroup {
+            group_id: GROUP_ID,
+            name: "Friends".to_string(),
+            description: String::new(),
+            is_public: false,
+            is_searchable: false,
+            invite_only: true,
+            max_members: 10,
+            allow_member_invites: false,
+            key_commitment: commitment,
+            owner_encrypted_group_key: [6u8; 64],
+        }
+        .data(),
+    )
+    .unwrap();
+
+    let created: GroupAccount = accounts[0].decode();
+    assert_eq!(created.key_commitment, commitment);
+    assert_eq!(created.key_epoch, 0);
+    assert!(!accounts[0]
+        .data()
+        .windows(group_key.len())
+        .any(|window| window == group_key));
+    let owner_member: GroupMemberAccount = accounts[1].decode();
+    assert_eq!(owner_member.role, GroupRole::Owner);
+    assert_eq!(owner_member.encrypted_group_key, [6u8; 64]);
+}
+
+#[test]
+fn legacy_group_key_is_replaced_by_commitment() {
+    let owner = Pubkey::new_unique();
+    let plaintext_key = [8u8; 32];
+    // Groups created before the migration hold the raw key and end at `bump`
+    let mut data = vec![];
+    GroupAccount {
+        key_commitment: plaintext_key,
+        ..group(owner)
+    }
+    .try_serialize(&mut data)
+    .unwrap();
+    data.resize(PLAINTEXT_KEY_GROUP_LEN, 0);
+    let mut accounts = vec![
+        TestAccount::new(group_pda().0, ID, 1_000_000, &data),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    let migrate = instruction::MigrateGroupKey {
+        _group_id: GROUP_ID,
+    }
+    .data();
+
+    run(&mut accounts, migrate.clone()).unwrap();
+
+    assert_eq!(accounts[0].data().len(), 8 + GroupAccount::INIT_SPACE);
+    let migrated: GroupAccount = accounts[0].decode();
+    assert_eq!(migrated.key_commitment, hash(&plaintext_key).to_bytes());
+    assert_eq!(migrated.key_epoch, 0);
+    assert_eq!(migrated.name, "Friends");
+    assert_eq!(migrated.role_permissions, DEFAULT_ROLE_PERMISSIONS);
+    assert!(events::<GroupKeyMigrated>()[0].plaintext_key);
+    assert!(!accounts[0]
+        .data()
+        .windows(plaintext_key.len())
+        .any(|window| window == plaintext_key));
+
+    assert_eq!(
+        run(&mut accounts, migrate),
+        Err(program_err(GroupError::GroupAlreadyMigrated))
+    );
+}
+
+#[test]
+fn group_from_before_role_templates_keeps_its_key() {
+    let owner = Pubkey::new_unique();
+    let mut data = vec![];
+    GroupAccount {
+        key_epoch: 3,
+        ..group(owner)
+    }
+    .try_serialize(&mut data)
+    .unwrap();
+    // Drop the template, keeping the space the older layout reserved
+    data.truncate(data.len() - RolePermissions::INIT_SPACE);
+    data.resize(
+        8 + GroupAccount::INIT_SPACE - RolePermissions::INIT_SPACE,
+        0,
+    );
+    let mut accounts = vec![
+        TestAccount::new(group_pda().0, ID, 1_000_000, &data),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(
+        &mut accounts,
+        instruction::MigrateGroupKey {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+
+    let migrated: GroupAccount = accounts[0].decode();
+    assert_eq!(migrated.key_commitment, [3u8; 32]);
+    assert_eq!(migrated.key_epoch, 3);
+    assert_eq!(migrated.role_permissions, DEFAULT_ROLE_PERMISSIONS);
+    assert!(!events::<GroupKeyMigrated>()[0].plaintext_key);
+}
+
+#[test]
+fn key_rotation_marks_members_stale_until_rewrapped() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        TestAccount::signer(owner),
+        TestAccount::program_config(),
+    ];
+
+    run(&mut accounts, begin_key_rotation([9u8; 32])).unwrap();
+
+    let rotated: GroupAccount = accounts[0].decode();
+    assert_eq!(rotated.key_epoch, 1);
+    assert_eq!(rotated.key_commitment, [9u8; 32]);
+
+    // Rewrap in two batches
+    let mut accounts = [
+        accounts[0].clone(),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+        member_account(&member(owner, GroupRole::Owner)),
+        member_account(&member(bob, GroupRole::Member)),
+        member_account(&member(carol, GroupRole::Member)),
+    ];
+    let (batch, rest) = accounts.split_at_mut(6);
+    run(batch, rewrap(1, &[owner, bob])).unwrap();
+
+    for account in &batch[4..] {
+        let rewrapped: GroupMemberAccount = account.decode();
+        assert_eq!(rewrapped.key_epoch, 1);
+        assert_eq!(rewrapped.encrypted_group_key, [1u8; 64]);
+    }
+    // Carol is detectably stale
+    assert!(rest[0].decode::<GroupMemberAccount>().key_epoch < rotated.key_epoch);
+}
+
+#[test]
+fn only_owner_can_rotate_group_key() {
+    let owner = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        TestAccount::signer(mallory),
+        TestAccount::program_config(),
+    ];
+
+    assert_eq!(
+        run(&mut accounts, begin_key_rotation([9u8; 32])),
+        Err(program_err(GroupError::NotGroupOwner))
+    );
+}
+
+#[test]
+fn rewrap_for_previous_epoch_is_rejected() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&GroupAccount {
+            key_epoch: 2,
+            ..group(owner)
+        }),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+        member_account(&member(owner, GroupRole::Owner)),
+    ];
+
+    assert_eq!(
+        run(&mut accounts, rewrap(1, &[owner])),
+        Err(program_err(GroupError::StaleKeyEpoch))
+    );
+}
+
+#[test]
+fn rewrap_rejects_accounts_not_matching_keys() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+        member_account(&member(mallory, GroupRole::Member)),
+    ];
+
+    assert_eq!(
+        run(&mut accounts, rewrap(0, &[bob])),
+        Err(program_err(GroupError::RewrapAccountMismatch))
+    );
+}
+
+#[test]
+fn rewrap_grows_legacy_member_accounts() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    // Members from before key epochs end at `bump`
+    let mut data = vec![];
+    member(bob, GroupRole::Member)
+        .try_serialize(&mut data)
+        .unwrap();
+    data.truncate(8 + GroupMemberAccount::INIT_SPACE - 4 - 32 - 1 - 8 - 1);
+    let mut accounts = vec![
+        group_account(&GroupAccount {
+            key_epoch: 1,
+            ..group(owner)
+        }),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+        TestAccount::new(member_pda(&bob).0, ID, 1_000_000, &data),
+    ];
+
+    run(&mut accounts, rewrap(1, &[bob])).unwrap();
+
+    assert_eq!(accounts[4].data().len(), 8 + GroupMemberAccount::INIT_SPACE);
+    let rewrapped: GroupMemberAccount = accounts[4].decode();
+    assert_eq!(rewrapped.key_epoch, 1);
+    assert_eq!(rewrapped.member, bob);
+}
+
+#[test]
+fn anyone_can_join_open_group() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = join_accounts(&open_group(owner), bob);
+
+    run(&mut accounts, join()).unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 2);
+    let joined: GroupMemberAccount = accounts[1].decode();
+    assert_eq!(joined.member, bob);
+    assert!(joined.is_active);
+
+    let [event] = &events::<MemberJoined>()[..] else {
+        panic!("expected one MemberJoined event");
+    };
+    assert_eq!(event.group_id, GROUP_ID);
+    assert_eq!(event.member, bob);
+    assert_eq!(event.invited_by, bob);
+    assert_eq!(event.member_count, 2);
+}
+
+#[test]
+fn join_requires_invitation_for_closed_groups() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+
+    for closed in [
+        GroupAccount {
+            invite_only: true,
+            ..open_group(owner)
+        },
+        // Neither searchable nor reachable by a public code
+        group(owner),
+    ] {
+        let mut accounts = join_accounts(&closed, bob);
+        assert_eq!(
+            run(&mut accounts, join()),
+            Err(program_err(GroupError::InviteRequired))
+        );
+    }
+
+    // A public code makes the group reachable
+    let mut accounts = join_accounts(
+        &GroupAccount {
+            public_code: "friends".to_string(),
+            ..group(owner)
+        },
+        bob,
+    );
+    run(&mut accounts, join()).unwrap();
+}
+
+#[test]
+fn banned_wallet_cannot_join() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = join_accounts(&open_group(owner), bob);
+    accounts[2] = ban_record(&bob);
+
+    assert_eq!(
+        run(&mut accounts, join()),
+        Err(program_err(GroupError::MemberBanned))
+    );
+}
+
+fn join_request(applicant: Pubkey) -> TestAccount {
+    let (key, bump) = request_pda(&applicant);
+    TestAccount::program_account(
+        key,
+        &JoinRequestAccount {
+            group_id: GROUP_ID,
+            applicant,
+            requested_at: 0,
+            bump,
+            encryption_key: [9u8; 32],
+            note: vec![],
+        },
+    )
+}
+
+fn request_to_join(note: Vec<u8>) -> Vec<u8> {
+    instruction::RequestToJoin {
+        group_id: GROUP_ID,
+        encryption_key: [9u8; 32],
+        note,
+    }
+    .data()
+}
+
+#[test]
+fn approval_group_join_goes_through_request() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let approval = GroupAccount {
+        require_approval: true,
+        ..open_group(owner)
+    };
+
+    let mut accounts = join_accounts(&approval, bob);
+    assert_eq!(
+        run(&mut accounts, join()),
+        Err(program_err(GroupError::RequiresApproval))
+    );
+
+    let mut accounts = vec![
+        group_account(&approval),
+        TestAccount::uninit(request_pda(&bob).0),
+        TestAccount::uninit(ban_pda(&bob).0),
+        TestAccount::signer(bob),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(
+            &mut accounts,
+            request_to_join(vec![1; MAX_JOIN_NOTE_LEN + 1])
+        ),
+        Err(program_err(GroupError::JoinNoteTooLong))
+    );
+    run(&mut accounts, request_to_join(vec![1, 2, 3])).unwrap();
+    let request = accounts[1].clone();
+    let pending: JoinRequestAccount = request.decode();
+    assert_eq!(pending.encryption_key, [9u8; 32]);
+    assert_eq!(pending.note, vec![1, 2, 3]);
+    // The applicant puts down the rent of their future member account
+    let deposit = request.lamports;
+
+    let mut accounts = vec![
+        group_account(&approval),
+        member_account(&member(owner, GroupRole::Owner)),
+        request,
+        TestAccount::uninit(member_pda(&bob).0),
+        TestAccount::uninit(ban_pda(&bob).0),
+        TestAccount::wallet(bob),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    let (lamports, approver_lamports) = (accounts[5].lamports, accounts[6].lamports);
+    run(
+        &mut accounts,
+        instruction::ApproveJoinRequest {
+            group_id: GROUP_ID,
+            encrypted_group_key: [5u8; 64],
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 2);
+    assert!(accounts[2].is_closed());
+    assert_eq!(accounts[6].lamports, approver_lamports);
+    assert_eq!(
+        accounts[5].lamports + accounts[3].lamports,
+        lamports + deposit
+    );
+    let approved: GroupMemberAccount = accounts[3].decode();
+    assert_eq!(approved.member, bob);
+    assert_eq!(approved.invited_by, owner);
+    assert_eq!(approved.rent_payer, bob);
+    assert_eq!(approved.encrypted_group_key, [5u8; 64]);
+}
+
+#[test]
+fn prefunded_member_address_does_not_block_approval() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut prefunded = TestAccount::uninit(member_pda(&bob).0);
+    prefunded.lamports = 50_000_000;
+    let mut accounts = vec![
+        group_account(&GroupAccount {
+            require_approval: true,
+            ..open_group(owner)
+        }),
+        member_account(&member(owner, GroupRole::Owner)),
+        join_request(bob),
+        prefunded,
+        TestAccount::uninit(ban_pda(&bob).0),
+        TestAccount::wallet(bob),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    let approver_lamports = accounts[6].lamports;
+
+    run(
+        &mut accounts,
+        instruction::ApproveJoinRequest {
+            group_id: GROUP_ID,
+            encrypted_group_key: [5u8; 64],
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert_eq!(accounts[3].decode::<GroupMemberAccount>().member, bob);
+    // The approver fronted nothing here, and is paid no more than the rent
+    assert_eq!(
+        accounts[6].lamports - approver_lamports,
+        Rent::default().minimum_balance(8 + GroupMemberAccount::INIT_SPACE)
+    );
+}
+
+#[test]
+fn plain_member_cannot_approve_requests() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&GroupAccount {
+            require_approval: true,
+            ..open_group(owner)
+        }),
+        member_account(&member(carol, GroupRole::Member)),
+        join_request(bob),
+        TestAccount::uninit(member_pda(&bob).0),
+        TestAccount::uninit(ban_pda(&bob).0),
+        TestAccount::wallet(bob),
+        TestAccount::signer(carol),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::ApproveJoinRequest {
+                group_id: GROUP_ID,
+                encrypted_group_key: [5u8; 64],
+            }
+            .data()
+        ),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+}
+
+#[test]
+fn rejected_request_refunds_applicant() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let approval = GroupAccount {
+        require_approval: true,
+        ..open_group(owner)
+    };
+    let carol = member(Pubkey::new_unique(), GroupRole::Member);
+    let reject = instruction::RejectJoinRequest { group_id: GROUP_ID }.data();
+    let mut accounts = vec![
+        group_account(&approval),
+        member_account(&carol),
+        join_request(bob),
+        TestAccount::wallet(bob),
+        TestAccount::signer(carol.member),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, reject.clone()),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    let moderator = member(Pubkey::new_unique(), GroupRole::Moderator);
+    accounts[1] = member_account(&moderator);
+    accounts[4] = TestAccount::signer(moderator.member);
+    let lamports = accounts[3].lamports;
+    run(&mut accounts, reject).unwrap();
+
+    assert!(accounts[2].is_closed());
+    assert!(accounts[3].lamports > lamports);
+    // Rejection doesn't admit anyone
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 1);
+}
+
+#[test]
+fn invite_link_admits_to_invite_only_group() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let closed = GroupAccount {
+        invite_only: true,
+        ..group(owner)
+    };
+    let mut accounts = redeem_accounts(&closed, &invite_link(owner, 0, 0), bob);
+
+    run(&mut accounts, redeem()).unwrap();
+
+    let link: InviteLinkAccount = accounts[1].decode();
+    assert_eq!(link.use_count, 1);
+    assert!(link.is_active);
+    let joined: GroupMemberAccount = accounts[2].decode();
+    assert_eq!(joined.member, bob);
+    assert_eq!(joined.invited_by, invite_pda("JOINUS42").0);
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 2);
+}
+
+#[test]
+fn invite_link_deactivates_on_last_use() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let mut accounts = redeem_accounts(&group(owner), &invite_link(owner, 0, 1), bob);
+
+    run(&mut accounts, redeem()).unwrap();
+
+    let link: InviteLinkAccount = accounts[1].decode();
+    assert_eq!(link.use_count, 1);
+    assert!(!link.is_active);
+
+    let mut accounts = redeem_accounts(&group(owner), &link, carol);
+    assert_eq!(
+        run(&mut accounts, redeem()),
+        Err(program_err(GroupError::InvalidInviteLink))
+    );
+}
+
+#[test]
+fn unusable_invite_links_are_rejected() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+
+    for (link, err) in [
+        (invite_link(owner, now(), 0), GroupError::InvalidInviteLink),
+        (
+            InviteLinkAccount {
+                is_active: false,
+                ..invite_link(owner, 0, 0)
+            },
+            GroupError::InvalidInviteLink,
+        ),
+        (
+            InviteLinkAccount {
+                use_count: 3,
+                ..invite_link(owner, 0, 3)
+            },
+            GroupError::InviteLinkExhausted,
+        ),
+    ] {
+        let mut accounts = redeem_accounts(&group(owner), &link, bob);
+        assert_eq!(run(&mut accounts, redeem()), Err(program_err(err)));
+    }
+}
+
+#[test]
+fn banned_wallet_cannot_redeem_invite_link() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = redeem_accounts(&group(owner), &invite_link(owner, 0, 0), bob);
+    accounts[3] = ban_record(&bob);
+
+    assert_eq!(
+        run(&mut accounts, redeem()),
+        Err(program_err(GroupError::MemberBanned))
+    );
+}
+
+#[test]
+fn ban_removes_membership_and_blocks_rejoining() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let group = GroupAccount {
+        member_count: 2,
+        ..open_group(owner)
+    };
+    let mut accounts = ban_accounts(&group, &member(owner, GroupRole::Owner), bob);
+    accounts[2] = member_account(&member(bob, GroupRole::Member));
+    let (owner_lamports, bob_lamports) = (accounts[7].lamports, accounts[5].lamports);
+
+    // The membership's rent goes back to whoever paid it, not the banner
+    let mut misdirected = accounts.clone();
+    misdirected[5] = TestAccount::wallet(owner);
+    assert_eq!(
+        run(&mut misdirected, ban_member(0)),
+        Err(program_err(GroupError::RentPayerMismatch))
+    );
+
+    run(&mut accounts, ban_member(0)).unwrap();
+
+    assert!(accounts[2].is_closed());
+    assert!(accounts[5].lamports > bob_lamports);
+    assert!(accounts[7].lamports < owner_lamports);
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 1);
+    let ban: BanAccount = accounts[3].decode();
+    assert_eq!(ban.user, bob);
+    assert_eq!(ban.banned_by, owner);
+    assert_eq!(ban.banned_at, now());
+    assert_eq!(ban.expires_at, 0);
+    let event = &events::<MemberBanned>()[0];
+    assert_eq!((event.user, event.banned_by), (bob, owner));
+    assert!(event.removed_member);
+    assert_eq!(event.member_count, 1);
+
+    let mut join_accounts = join_accounts(&open_group(owner), bob);
+    join_accounts[2] = accounts[3].clone();
+    assert_eq!(
+        run(&mut join_accounts, join()),
+        Err(program_err(GroupError::MemberBanned))
+    );
+}
+
+#[test]
+fn wallet_can_be_banned_without_membership() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = ban_accounts(&open_group(owner), &member(owner, GroupRole::Owner), bob);
+
+    run(&mut accounts, ban_member(0)).unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 1);
+    assert_eq!(accounts[3].decode::<BanAccount>().user, bob);
+}
+
+#[test]
+fn ban_requires_higher_rank() {
+    let owner = Pubkey::new_unique();
+    let moderator = Pubkey::new_unique();
+    let admin = Pubkey::new_unique();
+    let mut accounts = ban_accounts(
+        &open_group(owner),
+        &member(moderator, GroupRole::Moderator),
+        admin,
+    );
+    accounts[2] = member_account(&member(admin, GroupRole::Admin));
+    assert_eq!(
+        run(&mut accounts, ban_member(0)),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    let mut accounts = ban_accounts(&open_group(owner), &member(admin, GroupRole::Member), owner);
+    assert_eq!(
+        run(&mut accounts, ban_member(0)),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    let mut accounts = ban_accounts(&open_group(owner), &member(admin, GroupRole::Admin), owner);
+    accounts[2] = member_account(&member(owner, GroupRole::Owner));
+    assert_eq!(
+        run(&mut accounts, ban_member(0)),
+        Err(program_err(GroupError::CannotBanOwner))
+    );
+}
+
+#[test]
+fn ban_expiry_must_be_in_the_future() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = ban_accounts(&open_group(owner), &member(owner, GroupRole::Owner), bob);
+
+    assert_eq!(
+        run(&mut accounts, ban_member(now())),
+        Err(program_err(GroupError::InvalidBanExpiry))
+    );
+}
+
+#[test]
+fn expired_ban_no_longer_blocks_joining() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = join_accounts(&open_group(owner), bob);
+    let expires_at = now() + 60;
+    accounts[2] = TestAccount::program_account(ban_pda(&bob).0, &ban(bob, owner, expires_at));
+
+    assert_eq!(
+        run(&mut accounts, join()),
+        Err(program_err(GroupError::MemberBanned))
+    );
+
+    set_clock(expires_at);
+    run(&mut accounts, join()).unwrap();
+}
+
+#[test]
+fn unban_refunds_whoever_placed_the_ban() {
+    let owner = Pubkey::new_unique();
+    let moderator = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        member_account(&member(owner, GroupRole::Owner)),
+        TestAccount::program_account(ban_pda(&bob).0, &ban(bob, moderator, 0)),
+        TestAccount::wallet(bob),
+        TestAccount::wallet(moderator),
+        TestAccount::signer(owner),
+        TestAccount::program_config(),
+    ];
+    let lamports = accounts[3].lamports;
+
+    run(
+        &mut accounts,
+        instruction::UnbanMember {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert!(accounts[3].lamports > lamports);
+}
+
+#[test]
+fn custom_role_moderator_can_lift_own_ban() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let bot = GroupMemberAccount {
+        role_id: 1,
+        permissions: PERM_KICK_MEMBERS,
+        ..member(Pubkey::new_unique(), GroupRole::Member)
+    };
+    let mut accounts = ban_accounts(&group(owner), &bot, bob);
+    run(&mut accounts, ban_member(0)).unwrap();
+
+    let mut accounts = vec![
+        member_account(&bot),
+        accounts[3].clone(),
+        TestAccount::wallet(bob),
+        TestAccount::wallet(bot.member),
+        TestAccount::signer(bot.member),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::UnbanMember {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+    assert!(accounts[1].is_closed());
+}
+
+fn invite(ttl: i64) -> Vec<u8> {
+    instruction::InviteMember {
+        group_id: GROUP_ID,
+        encrypted_group_key: [5u8; 64],
+        ttl,
+    }
+    .data()
+}
+
+fn invite_accounts(
+    group: &GroupAccount,
+    inviter: &GroupMemberAccount,
+    user: Pubkey,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        member_account(inviter),
+        TestAccount::uninit(invitation_pda(&user).0),
+        TestAccount::uninit(ban_pda(&user).0),
+        TestAccount::wallet(user),
+        TestAccount::signer(inviter.member),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn invitation(invited_user: Pubkey, invited_by: Pubkey, expires_at: i64) -> TestAccount {
+    let (key, bump) = invitation_pda(&invited_user);
+    TestAccount::program_account(
+        key,
+        &GroupInvitationAccount {
+            group_id: GROUP_ID,
+            invited_user,
+            invited_by,
+            encrypted_group_key: [5u8; 64],
+            key_epoch: 0,
+            created_at: 0,
+            expires_at,
+            bump,
+        },
+    )
+}
+
+fn accept_accounts(
+    group: &GroupAccount,
+    invitation: TestAccount,
+    user: Pubkey,
+    inviter: Pubkey,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        invitation,
+        TestAccount::uninit(member_pda(&user).0),
+        TestAccount::uninit(ban_pda(&user).0),
+        TestAccount::wallet(inviter),
+        TestAccount::signer(user),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn accept() -> Vec<u8> {
+    instruction::AcceptInvitation { group_id: GROUP_ID }.data()
+}
+
+#[test]
+fn invitation_admits_only_once_accepted() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = invite_accounts(&group(owner), &member(owner, GroupRole::Owner), bob);
+
+    for ttl in [MIN_INVITATION_TTL - 1, MAX_INVITATION_TTL + 1] {
+        assert_eq!(
+            run(&mut accounts, invite(ttl)),
+            Err(program_err(GroupError::InvalidInvitationTtl))
+        );
+    }
+    run(&mut accounts, invite(MIN_INVITATION_TTL)).unwrap();
+
+    // Inviting alone admits no one
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 1);
+    let pending: GroupInvitationAccount = accounts[2].decode();
+    assert_eq!(pending.invited_by, owner);
+    assert_eq!(pending.expires_at, now() + MIN_INVITATION_TTL);
+    assert_eq!(events::<MemberInvited>()[0].invited_user, bob);
+
+    let mut accounts = accept_accounts(&group(owner), accounts[2].clone(), bob, owner);
+    let lamports = accounts[4].lamports;
+    run(&mut accounts, accept()).unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 2);
+    assert!(accounts[1].is_closed());
+    assert!(accounts[4].lamports > lamports);
+    let joined: GroupMemberAccount = accounts[2].decode();
+    assert_eq!(joined.member, bob);
+    assert_eq!(joined.invited_by, owner);
+    assert_eq!(joined.rent_payer, bob);
+    assert_eq!(joined.encrypted_group_key, [5u8; 64]);
+}
+
+#[test]
+fn expired_invitation_cannot_be_accepted() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = accept_accounts(&group(owner), invitation(bob, owner, now()), bob, owner);
+
+    assert_eq!(
+        run(&mut accounts, accept()),
+        Err(program_err(GroupError::InvitationExpired))
+    );
+}
+
+#[test]
+fn declined_invitation_refunds_inviter() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let decline = instruction::DeclineInvitation { group_id: GROUP_ID }.data();
+    let mut accounts = vec![
+        invitation(bob, owner, now()),
+        TestAccount::wallet(Pubkey::new_unique()),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, decline.clone()),
+        Err(program_err(GroupError::InvalidInvitation))
+    );
+
+    // Expired invitations can still be declined to recover the rent
+    accounts[1] = TestAccount::wallet(owner);
+    let lamports = accounts[1].lamports;
+    run(&mut accounts, decline).unwrap();
+
+    assert!(accounts[0].is_closed());
+    assert!(accounts[1].lamports > lamports);
+    assert_eq!(events::<InvitationDeclined>()[0].invited_by, owner);
+}
+
+#[test]
+fn expired_invitation_can_be_cleared_and_reissued() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = invite_accounts(&group(owner), &member(owner, GroupRole::Owner), bob);
+    run(&mut accounts, invite(MIN_INVITATION_TTL)).unwrap();
+    // A pending invitation blocks inviting the same wallet again
+    assert!(run(&mut accounts, invite(MIN_INVITATION_TTL)).is_err());
+
+    let stranger = Pubkey::new_unique();
+    let mut revoke_accounts = vec![
+        accounts[2].clone(),
+        TestAccount::uninit(member_pda(&stranger).0),
+        TestAccount::uninit(roles_pda().0),
+        TestAccount::wallet(bob),
+        TestAccount::wallet(owner),
+        TestAccount::signer(stranger),
+        TestAccount::program_config(),
+    ];
+    let revoke = instruction::RevokeInvitation { group_id: GROUP_ID }.data();
+    assert_eq!(
+        run(&mut revoke_accounts, revoke.clone()),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    // Once expired, anyone can clear it, refunding the inviter
+    set_clock(now() + MIN_INVITATION_TTL);
+    let lamports = revoke_accounts[4].lamports;
+    run(&mut revoke_accounts, revoke).unwrap();
+    assert!(revoke_accounts[0].is_closed());
+    assert!(revoke_accounts[4].lamports > lamports);
+    assert_eq!(events::<InvitationRevoked>()[0].revoked_by, stranger);
+
+    accounts[2] = revoke_accounts[0].clone();
+    run(&mut accounts, invite(MIN_INVITATION_TTL)).unwrap();
+    assert_eq!(
+        accounts[2].decode::<GroupInvitationAccount>().expires_at,
+        now() + MIN_INVITATION_TTL
+    );
+}
+
+#[test]
+fn moderator_can_revoke_pending_invitation() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let moderator = member(Pubkey::new_unique(), GroupRole::Moderator);
+    let mut accounts = revoke_invitation_accounts(owner, bob, &moderator, roles_account(vec![]));
+
+    run(&mut accounts, revoke_invitation()).unwrap();
+    assert!(accounts[0].is_closed());
+}
+
+fn revoke_invitation_accounts(
+    inviter: Pubkey,
+    invited: Pubkey,
+    revoker: &GroupMemberAccount,
+    roles: TestAccount,
+) -> Vec<TestAccount> {
+    vec![
+        invitation(invited, inviter, now() + MIN_INVITATION_TTL),
+        member_account(revoker),
+        roles,
+        TestAccount::wallet(invited),
+        TestAccount::wallet(inviter),
+        TestAccount::signer(revoker.member),
+        TestAccount::program_config(),
+    ]
+}
+
+fn revoke_invitation() -> Vec<u8> {
+    instruction::RevokeInvitation { group_id: GROUP_ID }.data()
+}
+
+#[test]
+fn custom_roles_revoke_invitations_by_permission_or_rank() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let custom = |role_id, permissions| GroupMemberAccount {
+        role_id,
+        permissions,
+        ..member(Pubkey::new_unique(), GroupRole::Member)
+    };
+    let roles = || {
+        roles_account(vec![
+            custom_role(1, 10, PERM_INVITE_MEMBERS),
+            custom_role(2, 100, PERM_INVITE_MEMBERS),
+        ])
+    };
+
+    // A low-ranked inviter can't clear other members' invitations
+    let mut accounts =
+        revoke_invitation_accounts(owner, bob, &custom(1, PERM_INVITE_MEMBERS), roles());
+    assert_eq!(
+        run(&mut accounts, revoke_invitation()),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    // Ranking above moderators is enough, as is PERM_KICK_MEMBERS
+    let mut accounts =
+        revoke_invitation_accounts(owner, bob, &custom(2, PERM_INVITE_MEMBERS), roles());
+    run(&mut accounts, revoke_invitation()).unwrap();
+    let mut accounts =
+        revoke_invitation_accounts(owner, bob, &custom(1, PERM_KICK_MEMBERS), roles());
+    run(&mut accounts, revoke_invitation()).unwrap();
+}
+
+#[test]
+fn banned_wallet_cannot_be_invited() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = invite_accounts(&group(owner), &member(owner, GroupRole::Owner), bob);
+    accounts[3] = ban_record(&bob);
+
+    assert_eq!(
+        run(&mut accounts, invite(MIN_INVITATION_TTL)),
+        Err(program_err(GroupError::MemberBanned))
+    );
+}
+
+fn update_settings(settings: GroupSettingsUpdate) -> Vec<u8> {
+    instruction::UpdateGroupSettings {
+        _group_id: GROUP_ID,
+        settings,
+    }
+    .data()
+}
+
+fn settings_accounts(group: &GroupAccount, updater: &GroupMemberAccount) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        member_account(updater),
+        TestAccount::signer(updater.member),
+        TestAccount::program_config(),
+    ]
+}
+
+#[test]
+fn settings_update_changes_only_given_fields() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = settings_accounts(&group(owner), &member(owner, GroupRole::Owner));
+
+    run(
+        &mut accounts,
+        update_settings(GroupSettingsUpdate {
+            name: Some("Family".to_string()),
+            require_approval: Some(true),
+            enable_reactions: Some(false),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+
+    let updated: GroupAccount = accounts[0].decode();
+    assert_eq!(updated.name, "Family");
+    assert!(updated.require_approval);
+    assert!(!updated.enable_reactions);
+    assert!(updated.enable_replies);
+    assert_eq!(updated.description, group(owner).description);
+    assert_eq!(updated.updated_at, now());
+}
+
+#[test]
+fn settings_update_requires_manage_settings() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let settings = GroupSettingsUpdate {
+        is_searchable: Some(true),
+        ..Default::default()
+    };
+
+    let mut accounts = settings_accounts(&group(owner), &member(bob, GroupRole::Moderator));
+    assert_eq!(
+        run(&mut accounts, update_settings(settings.clone())),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    let manager = GroupMemberAccount {
+        permissions: PERM_SEND_MESSAGES | PERM_MANAGE_SETTINGS,
+        ..member(bob, GroupRole::Member)
+    };
+    let mut accounts = settings_accounts(&group(owner), &manager);
+    run(&mut accounts, update_settings(settings)).unwrap();
+    assert!(accounts[0].decode::<GroupAccount>().is_searchable);
+}
+
+#[test]
+fn settings_update_validates_fields() {
+    let owner = Pubkey::new_unique();
+    let group = GroupAccount {
+        member_count: 5,
+        ..group(owner)
+    };
+    let mut accounts = settings_accounts(&group, &member(owner, GroupRole::Admin));
+
+    for (settings, error) in [
+        (
+            GroupSettingsUpdate {
+                name: Some(String::new()),
+                ..Default::default()
+            },
+            GroupError::InvalidGroupNameLength,
+        ),
+        (
+            GroupSettingsUpdate {
+                description: Some("x".repeat(501)),
+                ..Default::default()
+            },
+            GroupError::InvalidGroupDescriptionLength,
+        ),
+        (
+            GroupSettingsUpdate {
+                max_members: Some(4),
+                ..Default::default()
+            },
+            GroupError::MaxMembersBelowMemberCount,
+        ),
+    ] {
+        assert_eq!(
+            run(&mut accounts, update_settings(settings)),
+            Err(program_err(error))
+        );
+    }
+
+    run(
+        &mut accounts,
+        update_settings(GroupSettingsUpdate {
+            max_members: Some(5),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+    assert_eq!(accounts[0].decode::<GroupAccount>().max_members, 5);
+}
+
+fn permission_accounts(
+    group: &GroupAccount,
+    updater: &GroupMemberAccount,
+    target: &GroupMemberAccount,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        member_account(updater),
+        member_account(target),
+        TestAccount::wallet(target.member),
+        TestAccount::uninit(roles_pda().0),
+        TestAccount::signer(updater.member),
+        TestAccount::program_config(),
+    ]
+}
+
+fn grant(permissions: u16) -> Vec<u8> {
+    instruction::GrantPermissions {
+        _group_id: GROUP_ID,
+        permissions,
+    }
+    .data()
+}
+
+fn set_role_permissions(role_permissions: RolePermissions) -> Vec<u8> {
+    instruction::SetRolePermissions {
+        _group_id: GROUP_ID,
+        role_permissions,
+    }
+    .data()
+}
+
+#[test]
+fn single_permissions_are_granted_and_revoked_without_role_change() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = permission_accounts(
+        &group(owner),
+        &member(owner, GroupRole::Owner),
+        &member(bob, GroupRole::Member),
+    );
+
+    run(&mut accounts, grant(PERM_PIN_MESSAGES)).unwrap();
+    let granted: GroupMemberAccount = accounts[2].decode();
+    assert_eq!(granted.role, GroupRole::Member);
+    assert_eq!(granted.permissions, PERM_SEND_MESSAGES | PERM_PIN_MESSAGES);
+    let event = &events::<PermissionsGranted>()[0];
+    assert_eq!((event.member, event.granted), (bob, PERM_PIN_MESSAGES));
+    assert_eq!(event.granted_by, owner);
+
+    run(
+        &mut accounts,
+        instruction::RevokePermissions {
+            _group_id: GROUP_ID,
+            permissions: PERM_SEND_MESSAGES,
+        }
+        .data(),
+    )
+    .unwrap();
+    let revoked: GroupMemberAccount = accounts[2].decode();
+    assert_eq!(revoked.role, GroupRole::Member);
+    assert_eq!(revoked.permissions, PERM_PIN_MESSAGES);
+}
+
+#[test]
+fn permission_grants_are_bounded_by_the_granter() {
+    let owner = Pubkey::new_unique();
+    let admin = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let granter = GroupMemberAccount {
+        permissions: DEFAULT_ROLE_PERMISSIONS.admin,
+        ..member(admin, GroupRole::Admin)
+    };
+    let mut accounts =
+        permission_accounts(&group(owner), &granter, &member(bob, GroupRole::Member));
+
+    for (permissions, error) in [
+        (0, GroupError::InvalidPermissions),
+        (1 << 10, GroupError::InvalidPermissions),
+        // Admins don't hold PERM_DELETE_MESSAGES by default
+        (PERM_DELETE_MESSAGES, GroupError::InsufficientPermissions),
+    ] {
+        assert_eq!(
+            run(&mut accounts, grant(permissions)),
+            Err(program_err(error))
+        );
+    }
+    run(&mut accounts, grant(PERM_KICK_MEMBERS)).unwrap();
+
+    // Equal rank is not enough, and plain members can't grant at all
+    let mut accounts = permission_accounts(
+        &group(owner),
+        &granter,
+        &member(Pubkey::new_unique(), GroupRole::Admin),
+    );
+    assert_eq!(
+        run(&mut accounts, grant(PERM_SEND_MESSAGES)),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+    let mut accounts = permission_accounts(
+        &group(owner),
+        &member(bob, GroupRole::Member),
+        &member(Pubkey::new_unique(), GroupRole::Member),
+    );
+    assert_eq!(
+        run(&mut accounts, grant(PERM_SEND_MESSAGES)),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+}
+
+#[test]
+fn role_template_applies_on_join_and_role_change() {
+    let owner = Pubkey::new_unique();
+    let template = RolePermissions {
+        member: PERM_SEND_MESSAGES | PERM_PIN_MESSAGES,
+        moderator: PERM_SEND_MESSAGES | PERM_DELETE_MESSAGES,
+        ..DEFAULT_ROLE_PERMISSIONS
+    };
+    let mut accounts = vec![
+        group_account(&open_group(owner)),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, set_role_permissions(template)),
+        Err(program_err(GroupError::NotGroupOwner))
+    );
+
+    accounts[1] = TestAccount::signer(owner);
+    assert_eq!(
+        run(
+            &mut accounts,
+            set_role_permissions(RolePermissions {
+                admin: PERM_ALL | 1 << 15,
+                ..template
+            })
+        ),
+        Err(program_err(GroupError::InvalidPermissions))
+    );
+    run(&mut accounts, set_role_permissions(template)).unwrap();
+    let group: GroupAccount = accounts[0].decode();
+    assert_eq!(group.role_permissions, template);
+
+    let bob = Pubkey::new_unique();
+    let mut accounts = join_accounts(&group, bob);
+    run(&mut accounts, join()).unwrap();
+    let joined: GroupMemberAccount = accounts[1].decode();
+    assert_eq!(joined.permissions, template.member);
+
+    let mut accounts = permission_accounts(&group, &member(owner, GroupRole::Owner), &joined);
+    run(
+        &mut accounts,
+        instruction::UpdateMemberRole {
+            _group_id: GROUP_ID,
+            new_role: GroupRole::Moderator,
+        }
+        .data(),
+    )
+    .unwrap();
+    assert_eq!(
+        accounts[2].decode::<GroupMemberAccount>().permissions,
+        template.moderator
+    );
+}
+
+fn custom_role(id: u8, rank: u8, permissions: u16) -> CustomRole {
+    CustomRole {
+        id,
+        name: format!("Role {id}"),
+        rank,
+        permissions,
+    }
+}
+
+fn roles_account(roles: Vec<CustomRole>) -> TestAccount {
+    TestAccount::program_account(
+        roles_pda().0,
+        &GroupRolesAccount {
+            group_id: GROUP_ID,
+            created_by: Pubkey::new_unique(),
+            next_role_id: roles.len() as u8 + 1,
+            roles,
+            bump: roles_pda().1,
+        },
+    )
+}
+
+fn add_role(name: &str, rank: u8, permissions: u16) -> Vec<u8> {
+    instruction::AddGroupRole {
+        group_id: GROUP_ID,
+        name: name.to_string(),
+        rank,
+        permissions,
+    }
+    .data()
+}
+
+fn assign_role(role_id: u8) -> Vec<u8> {
+    instruction::AssignGroupRole {
+        _group_id: GROUP_ID,
+        role_id,
+    }
+    .data()
+}
+
+fn kick_accounts(
+    group: &GroupAccount,
+    kicker: &GroupMemberAccount,
+    kicked: &GroupMemberAccount,
+    roles: TestAccount,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        member_account(kicker),
+        member_account(kicked),
+        TestAccount::wallet(kicked.member),
+        TestAccount::wallet(kicked.rent_payer),
+        roles,
+        TestAccount::signer(kicker.member),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn kick() -> Vec<u8> {
+    instruction::KickMember {
+        _group_id: GROUP_ID,
+    }
+    .data()
+}
+
+#[test]
+fn owner_defines_custom_roles() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        TestAccount::uninit(roles_pda().0),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::InitGroupRoles { group_id: GROUP_ID }.data(),
+    )
+    .unwrap();
+
+    let mut accounts = vec![
+        accounts[0].clone(),
+        accounts[1].clone(),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, add_role("Announcer", 100, PERM_PIN_MESSAGES)),
+        Err(program_err(GroupError::NotGroupOwner))
+    );
+
+    accounts[2] = TestAccount::signer(owner);
+    for (data, error) in [
+        (add_role("", 100, 0), GroupError::InvalidRoleName),
+        (
+            add_role("A very long role name", 100, 0),
+            GroupError::InvalidRoleName,
+        ),
+        (
+            add_role("Announcer", u8::MAX, 0),
+            GroupError::InvalidRoleRank,
+        ),
+        (
+            add_role("Announcer", 100, 1 << 12),
+            GroupError::InvalidPermissions,
+        ),
+    ] {
+        assert_eq!(run(&mut accounts, data), Err(program_err(error)));
+    }
+
+    run(&mut accounts, add_role("Announcer", 100, PERM_PIN_MESSAGES)).unwrap();
+    run(&mut accounts, add_role("Bot", 10, PERM_SEND_MESSAGES)).unwrap();
+    run(
+        &mut accounts,
+        instruction::RemoveGroupRole {
+            group_id: GROUP_ID,
+            role_id: 1,
+        }
+        .data(),
+    )
+    .unwrap();
+    run(&mut accounts, add_role("Verified", 5, PERM_SEND_MESSAGES)).unwrap();
+
+    // Role ids are never reused
+    let roles: GroupRolesAccount = accounts[1].decode();
+    let ids: Vec<u8> = roles.roles.iter().map(|role| role.id).collect();
+    assert_eq!(ids, vec![2, 3]);
+    assert_eq!(roles.roles[1].name, "Verified");
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::UpdateGroupRole {
+                group_id: GROUP_ID,
+                role_id: 1,
+                name: "Announcer".to_string(),
+                rank: 100,
+                permissions: 0,
+            }
+            .data()
+        ),
+        Err(program_err(GroupError::RoleNotFound))
+    );
+}
+
+#[test]
+fn kick_refunds_membership_to_its_payer() {
+    let owner = member(Pubkey::new_unique(), GroupRole::Owner);
+    let applicant = Pubkey::new_unique();
+    let bob = GroupMemberAccount {
+        rent_payer: applicant,
+        ..member(Pubkey::new_unique(), GroupRole::Member)
+    };
+    let mut accounts = kick_accounts(&group(owner.member), &owner, &bob, roles_account(vec![]));
+    let lamports = accounts[4].lamports;
+
+    let mut misdirected = accounts.clone();
+    misdirected[4] = TestAccount::wallet(owner.member);
+    assert_eq!(
+        run(&mut misdirected, kick()),
+        Err(program_err(GroupError::RentPayerMismatch))
+    );
+
+    run(&mut accounts, kick()).unwrap();
+    assert!(accounts[2].is_closed());
+    assert!(accounts[4].lamports > lamports);
+}
+
+#[test]
+fn kick_follows_custom_role_ranks() {
+    let owner = Pubkey::new_unique();
+    let moderator = member(Pubkey::new_unique(), GroupRole::Moderator);
+    // A custom role ranked between moderators and admins, allowed to kick
+    let bot = GroupMemberAccount {
+        role_id: 1,
+        permissions: PERM_KICK_MEMBERS,
+        ..member(Pubkey::new_unique(), GroupRole::Member)
+    };
+    let roles = || roles_account(vec![custom_role(1, 100, PERM_KICK_MEMBERS)]);
+
+    let mut accounts = kick_accounts(&group(owner), &moderator, &bot, roles());
+    assert_eq!(
+        run(&mut accounts, kick()),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    let mut accounts = kick_accounts(&group(owner), &bot, &moderator, roles());
+    run(&mut accounts, kick()).unwrap();
+    assert!(accounts[2].is_closed());
+
+    // Once the role is gone its holders rank as their built-in role again
+    let mut accounts = kick_accounts(&group(owner), &moderator, &bot, roles_account(vec![]));
+    run(&mut accounts, kick()).unwrap();
+}
+
+#[test]
+fn assigning_custom_role_sets_its_permissions() {
+    let owner = Pubkey::new_unique();
+    let admin = member(Pubkey::new_unique(), GroupRole::Admin);
+    let bob = member(Pubkey::new_unique(), GroupRole::Member);
+    let announcer = custom_role(1, 100, PERM_SEND_MESSAGES | PERM_PIN_MESSAGES);
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        roles_account(vec![announcer.clone(), custom_role(2, 200, PERM_ALL)]),
+        member_account(&admin),
+        member_account(&bob),
+        TestAccount::wallet(bob.member),
+        TestAccount::signer(admin.member),
+        TestAccount::program_config(),
+    ];
+
+    for (role_id, error) in [
+        (3, GroupError::RoleNotFound),
+        // Admins rank 128, so they can't hand out a role ranked above them
+        (2, GroupError::InsufficientPermissions),
+    ] {
+        assert_eq!(
+            run(&mut accounts, assign_role(role_id)),
+            Err(program_err(error))
+        );
+    }
+
+    run(&mut accounts, assign_role(1)).unwrap();
+    let assigned: GroupMemberAccount = accounts[3].decode();
+    assert_eq!(assigned.role, GroupRole::Member);
+    assert_eq!(assigned.role_id, 1);
+    assert_eq!(assigned.permissions, announcer.permissions);
+
+    run(&mut accounts, assign_role(0)).unwrap();
+    let cleared: GroupMemberAccount = accounts[3].decode();
+    assert_eq!(cleared.role_id, 0);
+    assert_eq!(cleared.permissions, DEFAULT_ROLE_PERMISSIONS.member);
+}
+
+#[test]
+fn role_change_requires_higher_configured_rank() {
+    let owner = Pubkey::new_unique();
+    let admin = member(Pubkey::new_unique(), GroupRole::Admin);
+    // Ranked above admins, though only a member by built-in role
+    let lead = GroupMemberAccount {
+        role_id: 1,
+        ..member(Pubkey::new_unique(), GroupRole::Member)
+    };
+    let update_role = instruction::UpdateMemberRole {
+        _group_id: GROUP_ID,
+        new_role: GroupRole::Moderator,
+    }
+    .data();
+
+    let mut accounts = permission_accounts(&group(owner), &admin, &lead);
+    accounts[4] = roles_account(vec![custom_role(1, 200, PERM_SEND_MESSAGES)]);
+    assert_eq!(
+        run(&mut accounts, update_role.clone()),
+        Err(program_err(GroupError::InsufficientPermissions))
+    );
+
+    let mut accounts = permission_accounts(&group(owner), &member(owner, GroupRole::Owner), &lead);
+    accounts[4] = roles_account(vec![custom_role(1, 200, PERM_SEND_MESSAGES)]);
+    run(&mut accounts, update_role).unwrap();
+    // Setting a built-in role drops the custom one
+    let updated: GroupMemberAccount = accounts[2].decode();
+    assert_eq!((updated.role, updated.role_id), (GroupRole::Moderator, 0));
+}
+
+#[test]
+fn no_one_can_promote_to_owner() {
+    let owner = Pubkey::new_unique();
+    let bob = member(Pubkey::new_unique(), GroupRole::Member);
+    let promote = instruction::UpdateMemberRole {
+        _group_id: GROUP_ID,
+        new_role: GroupRole::Owner,
+    }
+    .data();
+
+    for updater in [
+        member(Pubkey::new_unique(), GroupRole::Admin),
+        member(owner, GroupRole::Owner),
+    ] {
+        let mut accounts = permission_accounts(&group(owner), &updater, &bob);
+        assert_eq!(
+            run(&mut accounts, promote.clone()),
+            Err(program_err(GroupError::CannotChangeOwnerRole))
+        );
+    }
+}
+
+fn moderate_accounts(
+    group: &GroupAccount,
+    moderator: &GroupMemberAccount,
+    target: &GroupMemberAccount,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(group),
+        member_account(moderator),
+        member_account(target),
+        TestAccount::wallet(target.member),
+        TestAccount::uninit(roles_pda().0),
+        TestAccount::signer(moderator.member),
+        TestAccount::program_config(),
+    ]
+}
+
+fn mute(muted_until: i64) -> Vec<u8> {
+    instruction::MuteMember {
+        _group_id: GROUP_ID,
+        muted_until,
+    }
+    .data()
+}
+
+#[test]
+fn moderator_mutes_until_expiry_or_unmute() {
+    let owner = Pubkey::new_unique();
+    let moderator = member(Pubkey::new_unique(), GroupRole::Moderator);
+    let bob = member(Pubkey::new_unique(), GroupRole::Member);
+    let mut accounts = moderate_accounts(&group(owner), &moderator, &bob);
+
+    assert_eq!(
+        run(&mut accounts, mute(now())),
+        Err(program_err(GroupError::InvalidMuteExpiry))
+    );
+    run(&mut accounts, mute(now() + 3600)).unwrap();
+    let muted: GroupMemberAccount = accounts[2].decode();
+    assert!(muted.is_muted);
+    assert_eq!(muted.muted_until, now() + 3600);
+    let event = &events::<MemberMuted>()[0];
+    assert_eq!(
+        (event.member, event.muted_by),
+        (bob.member, moderator.member)
+    );
+
+    run(
+        &mut accounts,
+        instruction::UnmuteMember {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+    let unmuted: GroupMemberAccount = accounts[2].decode();
+    assert!(!unmuted.is_muted);
+    assert_eq!(unmuted.muted_until, 0);
+}
+
+#[test]
+fn mute_requires_higher_rank() {
+    let owner = Pubkey::new_unique();
+    let moderator = member(Pubkey::new_unique(), GroupRole::Moderator);
+    let bob = member(Pubkey::new_unique(), GroupRole::Member);
+
+    for (muter, target, error) in [
+        (&bob, &moderator, GroupError::InsufficientPermissions),
+        (
+            &moderator,
+            &member(Pubkey::new_unique(), GroupRole::Moderator),
+            GroupError::InsufficientPermissions,
+        ),
+        (
+            &member(Pubkey::new_unique(), GroupRole::Admin),
+            &member(owner, GroupRole::Owner),
+            GroupError::CannotMuteOwner,
+        ),
+    ] {
+        let mut accounts = moderate_accounts(&group(owner), muter, target);
+        assert_eq!(run(&mut accounts, mute(0)), Err(program_err(error)));
+    }
+}
+
+#[test]
+fn members_mute_notifications_for_themselves() {
+    let bob = member(Pubkey::new_unique(), GroupRole::Member);
+    let set_mute = |muted| {
+        instruction::SetNotificationMute {
+            _group_id: GROUP_ID,
+            muted,
+        }
+        .data()
+    };
+    let mut accounts = vec![
+        member_account(&bob),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, set_mute(true)),
+        Err(anchor_err(ErrorCode::ConstraintSeeds))
+    );
+
+    accounts[1] = TestAccount::signer(bob.member);
+    run(&mut accounts, set_mute(true)).unwrap();
+    let member: GroupMemberAccount = accounts[0].decode();
+    assert!(member.notifications_muted);
+    // Muting notifications doesn't stop the member from sending
+    assert!(!member.is_muted);
+}
+
+fn pending_transfer(current_owner: Pubkey, new_owner: Pubkey) -> TestAccount {
+    TestAccount::program_account(
+        transfer_pda().0,
+        &PendingGroupTransferAccount {
+            group_id: GROUP_ID,
+            current_owner,
+            new_owner,
+            proposed_at: 0,
+            bump: transfer_pda().1,
+        },
+    )
+}
+
+fn accept_ownership_accounts(
+    group_owner: Pubkey,
+    proposer: Pubkey,
+    bob: Pubkey,
+) -> Vec<TestAccount> {
+    vec![
+        group_account(&group(group_owner)),
+        pending_transfer(proposer, bob),
+        member_account(&member(proposer, GroupRole::Owner)),
+        member_account(&member(bob, GroupRole::Member)),
+        TestAccount::uninit(succession_pda().0),
+        TestAccount::wallet(proposer),
+        TestAccount::wallet(proposer),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ]
+}
+
+fn accept_ownership() -> Vec<u8> {
+    instruction::AcceptGroupOwnership {
+        _group_id: GROUP_ID,
+    }
+    .data()
+}
+
+fn succession(owner: Pubkey, successor: Pubkey) -> TestAccount {
+    TestAccount::program_account(
+        succession_pda().0,
+        &GroupSuccessionAccount {
+            group_id: GROUP_ID,
+            owner,
+            successor,
+            inactivity_period: MIN_OWNER_INACTIVITY_PERIOD,
+            owner_last_active_at: now(),
+            claim_started_at: 0,
+            bump: succession_pda().1,
+        },
+    )
+}
+
+fn claim_accounts(group_owner: Pubkey, named_by: Pubkey, bob: Pubkey) -> Vec<TestAccount> {
+    vec![
+        group_account(&group(group_owner)),
+        succession(named_by, bob),
+        member_account(&member(named_by, GroupRole::Owner)),
+        member_account(&member(bob, GroupRole::Member)),
+        TestAccount::uninit(transfer_pda().0),
+        TestAccount::wallet(named_by),
+        TestAccount::wallet(named_by),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ]
+}
+
+/// Announce the claim with the accounts of `claim_accounts`.
+fn begin_claim(accounts: &mut [TestAccount]) -> std::result::Result<(), ProgramError> {
+    let mut begin = vec![
+        accounts[0].clone(),
+        accounts[1].clone(),
+        accounts[7].clone(),
+        accounts[8].clone(),
+    ];
+    let result = run(
+        &mut begin,
+        instruction::BeginOwnershipClaim {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    );
+    accounts[1] = begin[1].clone();
+    result
+}
+
+fn claim_ownership() -> Vec<u8> {
+    instruction::ClaimGroupOwnership {
+        _group_id: GROUP_ID,
+    }
+    .data()
+}
+
+#[test]
+fn ownership_transfer_swaps_roles_on_acceptance() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        member_account(&member(bob, GroupRole::Member)),
+        TestAccount::uninit(transfer_pda().0),
+        TestAccount::wallet(bob),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::TransferGroupOwnership { group_id: GROUP_ID }.data(),
+    )
+    .unwrap();
+    let pending: PendingGroupTransferAccount = accounts[2].decode();
+    assert_eq!(pending.current_owner, owner);
+    assert_eq!(pending.new_owner, bob);
+    // Nothing changes until Bob accepts
+    assert_eq!(accounts[0].decode::<GroupAccount>().owner, owner);
+
+    let mut accounts = vec![
+        accounts[0].clone(),
+        accounts[2].clone(),
+        member_account(&member(owner, GroupRole::Owner)),
+        accounts[1].clone(),
+        TestAccount::uninit(succession_pda().0),
+        TestAccount::wallet(owner),
+        TestAccount::wallet(owner),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ];
+    run(&mut accounts, accept_ownership()).unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().owner, bob);
+    assert!(accounts[1].is_closed());
+    assert_eq!(
+        accounts[2].decode::<GroupMemberAccount>().role,
+        GroupRole::Admin
+    );
+    let new_owner: GroupMemberAccount = accounts[3].decode();
+    assert_eq!(new_owner.role, GroupRole::Owner);
+    assert_eq!(new_owner.permissions, PERM_ALL);
+    let event = &events::<GroupOwnershipTransferred>()[0];
+    assert_eq!((event.previous_owner, event.new_owner), (owner, bob));
+}
+
+#[test]
+fn accepted_transfer_clears_previous_owners_successor() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let mut accounts = accept_ownership_accounts(owner, owner, bob);
+    accounts[4] = succession(owner, carol);
+    let lamports = accounts[5].lamports;
+
+    let mut misdirected = accounts.clone();
+    misdirected[5] = TestAccount::wallet(bob);
+    assert_eq!(
+        run(&mut misdirected, accept_ownership()),
+        Err(program_err(GroupError::SuccessionOwnerMismatch))
+    );
+
+    run(&mut accounts, accept_ownership()).unwrap();
+    assert!(accounts[4].is_closed());
+    assert!(accounts[5].lamports > lamports);
+
+    // Bob can name a successor straight away
+    let mut accounts = vec![
+        accounts[0].clone(),
+        member_account(&member(carol, GroupRole::Member)),
+        accounts[4].clone(),
+        TestAccount::wallet(carol),
+        TestAccount::signer(bob),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::SetGroupSuccessor {
+            group_id: GROUP_ID,
+            inactivity_period: MIN_OWNER_INACTIVITY_PERIOD,
+        }
+        .data(),
+    )
+    .unwrap();
+    assert_eq!(accounts[2].decode::<GroupSuccessionAccount>().owner, bob);
+}
+
+#[test]
+fn ownership_transfer_requires_the_recipient() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+    let mut accounts = accept_ownership_accounts(owner, owner, bob);
+    accounts[3] = member_account(&member(mallory, GroupRole::Member));
+    accounts[7] = TestAccount::signer(mallory);
+
+    assert_eq!(
+        run(&mut accounts, accept_ownership()),
+        Err(program_err(GroupError::NotTransferRecipient))
+    );
+}
+
+#[test]
+fn ownership_transfer_is_stale_once_group_changes_hands() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = accept_ownership_accounts(Pubkey::new_unique(), owner, bob);
+
+    assert_eq!(
+        run(&mut accounts, accept_ownership()),
+        Err(program_err(GroupError::StaleOwnershipTransfer))
+    );
+}
+
+#[test]
+fn recipient_can_decline_ownership_transfer() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        pending_transfer(owner, bob),
+        TestAccount::wallet(owner),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ];
+    let lamports = accounts[2].lamports;
+
+    run(&mut accounts, cancel_ownership_transfer()).unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].lamports > lamports);
+}
+
+fn cancel_ownership_transfer() -> Vec<u8> {
+    instruction::CancelGroupOwnershipTransfer {
+        _group_id: GROUP_ID,
+    }
+    .data()
+}
+
+#[test]
+fn current_owner_can_cancel_a_stale_transfer() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(carol)),
+        pending_transfer(owner, bob),
+        TestAccount::wallet(owner),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, cancel_ownership_transfer()),
+        Err(program_err(GroupError::NotTransferParty))
+    );
+
+    accounts[3] = TestAccount::signer(carol);
+    run(&mut accounts, cancel_ownership_transfer()).unwrap();
+    assert!(accounts[1].is_closed());
+}
+
+#[test]
+fn successor_claims_after_owner_inactivity() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = claim_accounts(owner, owner, bob);
+
+    assert_eq!(
+        begin_claim(&mut accounts),
+        Err(program_err(GroupError::OwnerStillActive))
+    );
+
+    set_clock(now() + MIN_OWNER_INACTIVITY_PERIOD);
+    begin_claim(&mut accounts).unwrap();
+    assert_eq!(
+        events::<GroupOwnershipClaimStarted>()[0].claimable_at,
+        now() + OWNERSHIP_CLAIM_NOTICE_PERIOD
+    );
+    assert_eq!(
+        begin_claim(&mut accounts),
+        Err(program_err(GroupError::OwnershipClaimAlreadyStarted))
+    );
+    // The owner gets a notice period before the group changes hands
+    assert_eq!(
+        run(&mut accounts, claim_ownership()),
+        Err(program_err(GroupError::OwnershipClaimNotReady))
+    );
+
+    set_clock(now() + OWNERSHIP_CLAIM_NOTICE_PERIOD);
+    run(&mut accounts, claim_ownership()).unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().owner, bob);
+    assert!(accounts[1].is_closed());
+    assert_eq!(
+        accounts[2].decode::<GroupMemberAccount>().role,
+        GroupRole::Admin
+    );
+    assert_eq!(
+        accounts[3].decode::<GroupMemberAccount>().role,
+        GroupRole::Owner
+    );
+}
+
+#[test]
+fn claim_drops_transfer_proposed_by_inactive_owner() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let mut accounts = claim_accounts(owner, owner, bob);
+    accounts[4] = pending_transfer(owner, carol);
+    let lamports = accounts[5].lamports;
+
+    set_clock(now() + MIN_OWNER_INACTIVITY_PERIOD);
+    begin_claim(&mut accounts).unwrap();
+    set_clock(now() + OWNERSHIP_CLAIM_NOTICE_PERIOD);
+
+    // The transfer's rent only goes back to the owner who proposed it
+    let mut misdirected = accounts.clone();
+    misdirected[5] = TestAccount::wallet(bob);
+    assert_eq!(
+        run(&mut misdirected, claim_ownership()),
+        Err(program_err(GroupError::TransferProposerMismatch))
+    );
+
+    run(&mut accounts, claim_ownership()).unwrap();
+    assert!(accounts[4].is_closed());
+    assert!(accounts[5].lamports > lamports);
+
+    // Bob can hand the group on in turn
+    let mut accounts = vec![
+        accounts[0].clone(),
+        member_account(&member(carol, GroupRole::Member)),
+        accounts[4].clone(),
+        TestAccount::wallet(carol),
+        TestAccount::signer(bob),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::TransferGroupOwnership { group_id: GROUP_ID }.data(),
+    )
+    .unwrap();
+    let pending: PendingGroupTransferAccount = accounts[2].decode();
+    assert_eq!((pending.current_owner, pending.new_owner), (bob, carol));
+}
+
+#[test]
+fn owner_activity_cancels_announced_claim() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = claim_accounts(owner, owner, bob);
+
+    set_clock(now() + MIN_OWNER_INACTIVITY_PERIOD);
+    begin_claim(&mut accounts).unwrap();
+    let mut confirm = vec![
+        accounts[1].clone(),
+        TestAccount::signer(owner),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut confirm,
+        instruction::ConfirmOwnerActivity {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+    accounts[1] = confirm[0].clone();
+
+    assert_eq!(
+        begin_claim(&mut accounts),
+        Err(program_err(GroupError::OwnerStillActive))
+    );
+    set_clock(now() + OWNERSHIP_CLAIM_NOTICE_PERIOD);
+    assert_eq!(
+        run(&mut accounts, claim_ownership()),
+        Err(program_err(GroupError::OwnershipClaimNotReady))
+    );
+}
+
+#[test]
+fn succession_is_stale_once_group_changes_hands() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = claim_accounts(Pubkey::new_unique(), owner, bob);
+
+    set_clock(now() + MIN_OWNER_INACTIVITY_PERIOD);
+    assert_eq!(
+        begin_claim(&mut accounts),
+        Err(program_err(GroupError::StaleSuccession))
+    );
+    assert_eq!(
+        run(&mut accounts, claim_ownership()),
+        Err(program_err(GroupError::StaleSuccession))
+    );
+}
+
+#[test]
+fn dissolved_group_cannot_change_hands() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = claim_accounts(owner, owner, bob);
+    set_clock(now() + MIN_OWNER_INACTIVITY_PERIOD);
+    begin_claim(&mut accounts).unwrap();
+    set_clock(now() + OWNERSHIP_CLAIM_NOTICE_PERIOD);
+
+    accounts[0] = group_account(&dissolved_group(owner, 2));
+    assert_eq!(
+        run(&mut accounts, claim_ownership()),
+        Err(program_err(GroupError::GroupDissolved))
+    );
+
+    let mut accounts = accept_ownership_accounts(owner, owner, bob);
+    accounts[0] = group_account(&dissolved_group(owner, 2));
+    assert_eq!(
+        run(&mut accounts, accept_ownership()),
+        Err(program_err(GroupError::GroupDissolved))
+    );
+}
+
+#[test]
+fn successor_inactivity_period_is_bounded() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        member_account(&member(bob, GroupRole::Member)),
+        TestAccount::uninit(succession_pda().0),
+        TestAccount::wallet(bob),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    let set_successor = |inactivity_period| {
+        instruction::SetGroupSuccessor {
+            group_id: GROUP_ID,
+            inactivity_period,
+        }
+        .data()
+    };
+
+    assert_eq!(
+        run(
+            &mut accounts,
+            set_successor(MIN_OWNER_INACTIVITY_PERIOD - 1)
+        ),
+        Err(program_err(GroupError::InvalidInactivityPeriod))
+    );
+
+    run(&mut accounts, set_successor(MIN_OWNER_INACTIVITY_PERIOD)).unwrap();
+    let succession: GroupSuccessionAccount = accounts[2].decode();
+    assert_eq!(succession.successor, bob);
+    assert_eq!(succession.owner_last_active_at, now());
+}
+
+/// A dissolved group with `member_count` members still to close.
+fn dissolved_group(owner: Pubkey, member_count: u16) -> GroupAccount {
+    GroupAccount {
+        dissolved_at: now(),
+        member_count,
+        ..group(owner)
+    }
+}
+
+fn close_dissolved(accounts: &mut [TestAccount]) -> std::result::Result<(), ProgramError> {
+    run(
+        accounts,
+        instruction::CloseDissolvedAccounts { group_id: GROUP_ID }.data(),
+    )
+}
+
+#[test]
+fn dissolving_releases_public_code_and_stops_joining() {
+    let owner = Pubkey::new_unique();
+    let group = GroupAccount {
+        public_code: "friends".to_string(),
+        ..open_group(owner)
+    };
+    let mut accounts = vec![
+        group_account(&group),
+        TestAccount::program_account(
+            code_pda("friends").0,
+            &GroupCodeLookupAccount {
+                public_code: "friends".to_string(),
+                group_id: GROUP_ID,
+                bump: code_pda("friends").1,
+            },
+        ),
+        TestAccount::signer(owner),
+        TestAccount::program_config(),
+    ];
+    let lamports = accounts[2].lamports;
+
+    run(
+        &mut accounts,
+        instruction::DissolveGroup {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].lamports > lamports);
+    let dissolved: GroupAccount = accounts[0].decode();
+    assert_eq!(dissolved.dissolved_at, now());
+    assert!(dissolved.public_code.is_empty());
+
+    let bob = Pubkey::new_unique();
+    let mut join_accounts = join_accounts(&dissolved, bob);
+    assert_eq!(
+        run(&mut join_accounts, join()),
+        Err(program_err(GroupError::GroupDissolved))
+    );
+}
+
+#[test]
+fn dissolved_group_accounts_are_refunded_to_their_payers() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+    let invited = GroupMemberAccount {
+        rent_payer: owner,
+        ..member(bob, GroupRole::Member)
+    };
+    let mut accounts = vec![
+        group_account(&dissolved_group(owner, 2)),
+        TestAccount::program_config(),
+        member_account(&member(owner, GroupRole::Owner)),
+        TestAccount::wallet(owner),
+        member_account(&invited),
+        TestAccount::wallet(owner),
+        TestAccount::program_account(invite_pda("JOINUS42").0, &invite_link(bob, 0, 0)),
+        TestAccount::wallet(bob),
+        TestAccount::program_account(ban_pda(&carol).0, &ban(carol, bob, 0)),
+        TestAccount::wallet(bob),
+        invitation(carol, bob, now()),
+        TestAccount::wallet(bob),
+    ];
+    let (owner_lamports, bob_lamports) = (accounts[3].lamports, accounts[7].lamports);
+
+    close_dissolved(&mut accounts).unwrap();
+
+    for closed in [2, 4, 6, 8, 10] {
+        assert!(accounts[closed].is_closed());
+    }
+    assert!(accounts[3].lamports + accounts[5].lamports > 2 * owner_lamports);
+    assert!(accounts[7].lamports + accounts[9].lamports + accounts[11].lamports > 3 * bob_lamports);
+    assert_eq!(accounts[0].decode::<GroupAccount>().member_count, 0);
+
+    let mut accounts = vec![
+        accounts[0].clone(),
+        TestAccount::wallet(owner),
+        TestAccount::program_config(),
+    ];
+    let (group_lamports, owner_lamports) = (accounts[0].lamports, accounts[1].lamports);
+    run(
+        &mut accounts,
+        instruction::CloseDissolvedGroup {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+    let tombstone: GroupTombstoneAccount = accounts[0].decode();
+    assert_eq!(tombstone.group_id, GROUP_ID);
+    assert!(accounts[0].lamports < group_lamports);
+    assert_eq!(
+        accounts[0].lamports + accounts[1].lamports,
+        group_lamports + owner_lamports
+    );
+}
+
+#[test]
+fn closed_group_id_cannot_be_created_again() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&dissolved_group(owner, 0)),
+        TestAccount::wallet(owner),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::CloseDissolvedGroup {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+
+    // Stragglers can still be reclaimed once the group is gone
+    let bob = Pubkey::new_unique();
+    let mut stragglers = vec![
+        accounts[0].clone(),
+        TestAccount::program_config(),
+        TestAccount::program_account(ban_pda(&bob).0, &ban(bob, owner, 0)),
+        TestAccount::wallet(owner),
+    ];
+    close_dissolved(&mut stragglers).unwrap();
+    assert!(stragglers[2].is_closed());
+
+    let mut accounts = vec![
+        accounts[0].clone(),
+        TestAccount::uninit(member_pda(&owner).0),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::CreateGroup {
+                group_id: GROUP_ID,
+                name: "Friends".to_string(),
+                description: String::new(),
+                is_public: false,
+                is_searchable: false,
+                invite_only: true,
+                max_members: 10,
+                allow_member_invites: false,
+                key_commitment: [1u8; 32],
+                owner_encrypted_group_key: [6u8; 64],
+            }
+            .data()
+        ),
+        Err(account_in_use())
+    );
+}
+
+#[test]
+fn dissolved_accounts_go_back_to_their_payer_only() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&dissolved_group(owner, 2)),
+        TestAccount::program_config(),
+        member_account(&member(bob, GroupRole::Member)),
+        TestAccount::wallet(Pubkey::new_unique()),
+    ];
+    assert_eq!(
+        close_dissolved(&mut accounts),
+        Err(program_err(GroupError::DissolveAccountMismatch))
+    );
+
+    // Accounts of another group are out of reach
+    let other = GroupMemberAccount {
+        group_id: [7u8; 32],
+        ..member(bob, GroupRole::Member)
+    };
+    accounts[2] = member_account(&other);
+    accounts[3] = TestAccount::wallet(bob);
+    assert_eq!(
+        close_dissolved(&mut accounts),
+        Err(program_err(GroupError::DissolveAccountMismatch))
+    );
+}
+
+#[test]
+fn only_dissolved_empty_groups_are_closed() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        TestAccount::program_config(),
+        member_account(&member(bob, GroupRole::Member)),
+        TestAccount::wallet(bob),
+    ];
+    assert_eq!(
+        close_dissolved(&mut accounts),
+        Err(program_err(GroupError::GroupNotDissolved))
+    );
+
+    let mut accounts = vec![
+        group_account(&dissolved_group(owner, 1)),
+        TestAccount::wallet(owner),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::CloseDissolvedGroup {
+                _group_id: GROUP_ID,
+            }
+            .data()
+        ),
+        Err(program_err(GroupError::GroupNotEmpty))
+    );
+}
+
+#[test]
+fn invite_links_from_a_previous_group_are_void() {
+    let owner = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let recreated = GroupAccount {
+        created_at: now(),
+        ..group(owner)
+    };
+    let mut accounts = redeem_accounts(&recreated, &invite_link(owner, 0, 0), bob);
+
+    assert_eq!(
+        run(&mut accounts, redeem()),
+        Err(program_err(GroupError::InvalidInviteLink))
+    );
+}
+
+fn code_lookup(code: &str, group_id: [u8; 32]) -> TestAccount {
+    TestAccount::program_account(
+        code_pda(code).0,
+        &GroupCodeLookupAccount {
+            public_code: code.to_string(),
+            group_id,
+            bump: code_pda(code).1,
+        },
+    )
+}
+
+fn coded_group(owner: Pubkey, code: &str) -> GroupAccount {
+    GroupAccount {
+        public_code: code.to_string(),
+        ..group(owner)
+    }
+}
+
+fn change_code(code: &str) -> Vec<u8> {
+    instruction::ChangeGroupCode {
+        _group_id: GROUP_ID,
+        new_public_code: code.to_string(),
+    }
+    .data()
+}
+
+#[test]
+fn set_group_code_creates_lookup() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        TestAccount::uninit(code_pda("friends").0),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    let set_code = |code: &str| {
+        instruction::SetGroupCode {
+            _group_id: GROUP_ID,
+            public_code: code.to_string(),
+        }
+        .data()
+    };
+
+    run(&mut accounts, set_code("friends")).unwrap();
+
+    assert_eq!(accounts[0].decode::<GroupAccount>().public_code, "friends");
+    let lookup: GroupCodeLookupAccount = accounts[1].decode();
+    assert_eq!(lookup.group_id, GROUP_ID);
+    assert_eq!(lookup.public_code, "friends");
+    assert_eq!(
+        accounts[1].lamports,
+        Rent::default().minimum_balance(8 + GroupCodeLookupAccount::INIT_SPACE)
+    );
+
+    // Codes are replaced through change_group_code
+    accounts[1] = TestAccount::uninit(code_pda("family").0);
+    assert_eq!(
+        run(&mut accounts, set_code("family")),
+        Err(program_err(GroupError::PublicCodeAlreadySet))
+    );
+}
+
+#[test]
+fn taken_public_code_is_reported_cleanly() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&group(owner)),
+        code_lookup("friends", [7u8; 32]),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::SetGroupCode {
+                _group_id: GROUP_ID,
+                public_code: "friends".to_string(),
+            }
+            .data()
+        ),
+        Err(program_err(GroupError::PublicCodeTaken))
+    );
+
+    let mut accounts = vec![
+        group_account(&coded_group(owner, "family")),
+        code_lookup("family", GROUP_ID),
+        code_lookup("friends", [7u8; 32]),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, change_code("friends")),
+        Err(program_err(GroupError::PublicCodeTaken))
+    );
+}
+
+#[test]
+fn changing_code_moves_the_lookup() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&coded_group(owner, "family")),
+        code_lookup("family", GROUP_ID),
+        TestAccount::uninit(code_pda("friends").0),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(&mut accounts, change_code("friends")).unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert_eq!(
+        accounts[2].decode::<GroupCodeLookupAccount>().group_id,
+        GROUP_ID
+    );
+    let group: GroupAccount = accounts[0].decode();
+    assert_eq!(group.public_code, "friends");
+    assert_eq!(group.updated_at, now());
+}
+
+#[test]
+fn releasing_code_frees_it_and_refunds_the_owner() {
+    let owner = Pubkey::new_unique();
+    let mut accounts = vec![
+        group_account(&coded_group(owner, "family")),
+        code_lookup("family", GROUP_ID),
+        TestAccount::signer(owner),
+        TestAccount::program_config(),
+    ];
+    let lamports = accounts[2].lamports;
+
+    run(
+        &mut accounts,
+        instruction::ReleaseGroupCode {
+            _group_id: GROUP_ID,
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].lamports > lamports);
+    assert!(accounts[0].decode::<GroupAccount>().public_code.is_empty());
+}
@@ -0,0 +1,211 @@
+//! Tests for the encryption key history: rotations are recorded, the history
+//! is bounded, and compromised keys are revoked and can't be reused.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+use common::{events, now, pda, program_err, run, user, user_account, TestAccount};
+use key_registry::{
+    instruction, EncryptionKeyRevoked, KeyChangeReason, KeyError, KeyHistoryAccount,
+    KeyHistoryEntry, UserAccount, MAX_KEY_HISTORY,
+};
+
+/// A history of keys `[1; 32]`, `[2; 32]`, ... published a second apart,
+/// the last one being current.
+fn key_history(keys: u8) -> TestAccount {
+    let (key, bump) = pda(&[b"key_history", b"alice"]);
+    let entries = (1..=keys)
+        .map(|i| KeyHistoryEntry {
+            key: [i; 32],
+            valid_from: i as i64,
+            valid_until: if i == keys { 0 } else { i as i64 + 1 },
+            reason: KeyChangeReason::Rotated,
+            compromised_at: 0,
+        })
+        .collect();
+    TestAccount::program_account(
+        key,
+        &KeyHistoryAccount {
+            username: "alice".to_string(),
+            entries,
+            bump,
+        },
+    )
+}
+
+fn accounts(owner: Pubkey, current: u8, history: u8) -> Vec<TestAccount> {
+    vec![
+        user_account(&UserAccount {
+            encryption_key: [current; 32],
+            ..user(owner)
+        }),
+        key_history(history),
+        TestAccount::signer(owner),
+        TestAccount::program_config(),
+    ]
+}
+
+fn update(key: u8) -> Vec<u8> {
+    instruction::UpdateEncryptionKey {
+        _username: "alice".to_string(),
+        new_encryption_key: [key; 32],
+    }
+    .data()
+}
+
+fn revoke(key: u8, compromised_at: i64, replacement: Option<u8>) -> Vec<u8> {
+    instruction::RevokeEncryptionKey {
+        _username: "alice".to_string(),
+        key: [key; 32],
+        compromised_at,
+        replacement_key: replacement.map(|key| [key; 32]),
+    }
+    .data()
+}
+
+fn entries(account: &TestAccount) -> Vec<KeyHistoryEntry> {
+    account.decode::<KeyHistoryAccount>().entries
+}
+
+#[test]
+fn update_closes_previous_entry() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = accounts(alice, 2, 2);
+
+    run(&mut accounts, update(3)).unwrap();
+
+    let entries = entries(&accounts[1]);
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1].valid_until, now());
+    assert_eq!(entries[2].key, [3u8; 32]);
+    assert_eq!(entries[2].valid_from, now());
+    assert_eq!(entries[2].valid_until, 0);
+    assert_eq!(entries[2].reason, KeyChangeReason::Rotated);
+}
+
+#[test]
+fn history_drops_oldest_entry_when_full() {
+    let alice = Pubkey::new_unique();
+    let full = MAX_KEY_HISTORY as u8;
+    let mut accounts = accounts(alice, full, full);
+
+    run(&mut accounts, update(100)).unwrap();
+
+    let entries = entries(&accounts[1]);
+    assert_eq!(entries.len(), MAX_KEY_HISTORY);
+    assert_eq!(entries[0].key, [2u8; 32]);
+    assert_eq!(entries.last().unwrap().key, [100u8; 32]);
+}
+
+#[test]
+fn past_key_can_be_revoked() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = accounts(alice, 2, 2);
+
+    run(&mut accounts, revoke(1, 1, None)).unwrap();
+
+    let entries = entries(&accounts[1]);
+    assert_eq!(entries[0].compromised_at, 1);
+    assert_eq!(entries[1].compromised_at, 0);
+    // The current key is untouched
+    assert_eq!(
+        accounts[0].decode::<UserAccount>().encryption_key,
+        [2u8; 32]
+    );
+}
+
+#[test]
+fn revoking_current_key_requires_replacement() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = accounts(alice, 2, 2);
+
+    assert_eq!(
+        run(&mut accounts, revoke(2, 2, None)),
+        Err(program_err(KeyError::ReplacementKeyRequired))
+    );
+
+    run(&mut accounts, revoke(2, 2, Some(3))).unwrap();
+
+    assert_eq!(
+        accounts[0].decode::<UserAccount>().encryption_key,
+        [3u8; 32]
+    );
+    let entries = entries(&accounts[1]);
+    assert_eq!(entries[1].compromised_at, 2);
+    assert_eq!(entries[1].valid_until, now());
+    assert_eq!(entries[2].key, [3u8; 32]);
+    assert_eq!(entries[2].reason, KeyChangeReason::Compromised);
+    let event = &events::<EncryptionKeyRevoked>()[0];
+    assert_eq!((event.key, event.compromised_at), ([2u8; 32], 2));
+    assert_eq!(event.replacement_key, Some([3u8; 32]));
+}
+
+#[test]
+fn compromise_time_must_fall_within_key_lifetime() {
+    let alice = Pubkey::new_unique();
+
+    for compromised_at in [1, now() + 1] {
+        let mut accounts = accounts(alice, 2, 2);
+        assert_eq!(
+            run(&mut accounts, revoke(2, compromised_at, Some(3))),
+            Err(program_err(KeyError::InvalidCompromiseTime))
+        );
+    }
+}
+
+#[test]
+fn unknown_key_cannot_be_revoked() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = accounts(alice, 2, 2);
+
+    assert_eq!(
+        run(&mut accounts, revoke(9, 1, None)),
+        Err(program_err(KeyError::KeyNotInHistory))
+    );
+}
+
+#[test]
+fn compromised_key_cannot_be_reused() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = accounts(alice, 2, 2);
+    run(&mut accounts, revoke(1, 1, None)).unwrap();
+
+    assert_eq!(
+        run(&mut accounts, update(1)),
+        Err(program_err(KeyError::KeyCompromised))
+    );
+    assert_eq!(
+        run(&mut accounts, revoke(2, 2, Some(1))),
+        Err(program_err(KeyError::KeyCompromised))
+    );
+}
+
+#[test]
+fn legacy_username_can_start_history() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&UserAccount {
+            encryption_key: [4u8; 32],
+            ..user(alice)
+        }),
+        TestAccount::uninit(pda(&[b"key_history", b"alice"]).0),
+        TestAccount::signer(alice),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(
+        &mut accounts,
+        instruction::InitKeyHistory {
+            _username: "alice".to_string(),
+        }
+        .data(),
+    )
+    .unwrap();
+
+    let entries = entries(&accounts[1]);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].key, [4u8; 32]);
+    assert_eq!(entries[0].valid_until, 0);
+}
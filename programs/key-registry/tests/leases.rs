@@ -0,0 +1,310 @@
+//! Tests for username leases: registration and renewal under the registry's
+//! lease terms, expired names hidden from lookups and reclaimed after the
+//! grace period.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountSerialize, InstructionData, Space};
+use common::{
+    lookup_username, now, pda, program_authority, program_err, register_username, run, set_clock,
+    treasury, user, user_account, TestAccount,
+};
+use key_registry::{
+    instruction, FeeTier, KeyError, OwnerLookupAccount, PendingTransferAccount,
+    RegistryConfigAccount, UserAccount, ID, MIN_GRACE_PERIOD, MIN_LEASE_DURATION,
+};
+
+const LEASE: i64 = 365 * 24 * 60 * 60;
+const GRACE: i64 = 30 * 24 * 60 * 60;
+const FEE: u64 = 1_000_000;
+
+fn owner_lookup(owner: Pubkey) -> TestAccount {
+    let (key, bump) = pda(&[b"owner", owner.as_ref()]);
+    TestAccount::program_account(
+        key,
+        &OwnerLookupAccount {
+            owner,
+            username: "alice".to_string(),
+            bump,
+        },
+    )
+}
+
+/// Lease terms of a year plus a 30 day grace period; 5-character names cost
+/// `FEE`.
+fn registry_config() -> TestAccount {
+    let (key, bump) = pda(&[b"registry_config"]);
+    TestAccount::program_account(
+        key,
+        &RegistryConfigAccount {
+            fee_tiers: vec![FeeTier {
+                max_length: 5,
+                fee: FEE,
+            }],
+            reserved_names: vec![],
+            lease_duration: LEASE,
+            grace_period: GRACE,
+            bump,
+        },
+    )
+}
+
+fn leased_user(owner: Pubkey, expires_at: i64) -> TestAccount {
+    user_account(&UserAccount {
+        expires_at,
+        ..user(owner)
+    })
+}
+
+fn renew_accounts(owner: Pubkey, expires_at: i64) -> Vec<TestAccount> {
+    vec![
+        leased_user(owner, expires_at),
+        registry_config(),
+        treasury(),
+        TestAccount::signer(owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn renew() -> Vec<u8> {
+    instruction::RenewUsername {
+        _username: "alice".to_string(),
+    }
+    .data()
+}
+
+fn reclaim_accounts(owner: Pubkey, expires_at: i64) -> Vec<TestAccount> {
+    vec![
+        leased_user(owner, expires_at),
+        owner_lookup(owner),
+        TestAccount::uninit(pda(&[b"key_history", b"alice"]).0),
+        TestAccount::uninit(pda(&[b"transfer", b"alice"]).0),
+        TestAccount::wallet(owner),
+        registry_config(),
+        TestAccount::wallet(owner),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::program_config(),
+    ]
+}
+
+fn reclaim() -> Vec<u8> {
+    instruction::ReclaimExpiredUsername {
+        _username: "alice".to_string(),
+    }
+    .data()
+}
+
+#[test]
+fn registration_starts_a_lease() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = vec![
+        TestAccount::uninit(pda(&[b"username", b"alice"]).0),
+        TestAccount::uninit(pda(&[b"owner", alice.as_ref()]).0),
+        TestAccount::uninit(pda(&[b"key_history", b"alice"]).0),
+        registry_config(),
+        treasury(),
+        TestAccount::signer(alice),
+        TestAccount::none(),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(&mut accounts, register_username("alice")).unwrap();
+
+    assert_eq!(
+        accounts[0].decode::<UserAccount>().expires_at,
+        now() + LEASE
+    );
+}
+
+#[test]
+fn renewal_extends_lease_for_a_fee() {
+    let alice = Pubkey::new_unique();
+
+    // Renewing early extends the current lease
+    let mut accounts = renew_accounts(alice, now() + 10);
+    let treasury_lamports = accounts[2].lamports;
+    run(&mut accounts, renew()).unwrap();
+    assert_eq!(
+        accounts[0].decode::<UserAccount>().expires_at,
+        now() + 10 + LEASE
+    );
+    assert_eq!(accounts[2].lamports, treasury_lamports + FEE);
+
+    // Renewing during the grace period starts a new lease from now
+    let mut accounts = renew_accounts(alice, now() - 10);
+    run(&mut accounts, renew()).unwrap();
+    assert_eq!(
+        accounts[0].decode::<UserAccount>().expires_at,
+        now() + LEASE
+    );
+}
+
+#[test]
+fn permanent_username_cannot_be_renewed() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = renew_accounts(alice, 0);
+
+    assert_eq!(
+        run(&mut accounts, renew()),
+        Err(program_err(KeyError::NoLease))
+    );
+}
+
+#[test]
+fn expired_username_fails_lookup_username() {
+    let alice = Pubkey::new_unique();
+
+    let mut accounts = vec![leased_user(alice, now() + 1), TestAccount::program_config()];
+    run(&mut accounts, lookup_username()).unwrap();
+
+    let mut accounts = vec![leased_user(alice, now()), TestAccount::program_config()];
+    assert_eq!(
+        run(&mut accounts, lookup_username()),
+        Err(program_err(KeyError::UsernameExpired))
+    );
+}
+
+#[test]
+fn anyone_can_reclaim_after_grace_period() {
+    let alice = Pubkey::new_unique();
+    let expires_at = now() - GRACE;
+
+    let mut accounts = reclaim_accounts(alice, expires_at + 1);
+    assert_eq!(
+        run(&mut accounts, reclaim()),
+        Err(program_err(KeyError::LeaseNotReclaimable))
+    );
+
+    let mut accounts = reclaim_accounts(alice, expires_at);
+    let owner_lamports = accounts[6].lamports;
+    run(&mut accounts, reclaim()).unwrap();
+
+    assert!(accounts[0].is_closed());
+    assert!(accounts[1].is_closed());
+    assert!(accounts[6].lamports > owner_lamports);
+}
+
+#[test]
+fn reclaim_clears_pending_transfer() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let (key, bump) = pda(&[b"transfer", b"alice"]);
+    let mut accounts = reclaim_accounts(alice, now() - GRACE);
+    accounts[3] = TestAccount::program_account(
+        key,
+        &PendingTransferAccount {
+            username: "alice".to_string(),
+            current_owner: alice,
+            new_owner: bob,
+            proposed_at: 0,
+            unlocks_at: 0,
+            bump,
+        },
+    );
+    accounts[4] = TestAccount::wallet(bob);
+    assert_eq!(
+        run(&mut accounts, reclaim()),
+        Err(program_err(KeyError::TransferProposerMismatch))
+    );
+
+    accounts[4] = TestAccount::wallet(alice);
+    let lamports = accounts[4].lamports;
+    run(&mut accounts, reclaim()).unwrap();
+
+    // Bob can't take the name over from whoever registers it next
+    assert!(accounts[3].is_closed());
+    assert!(accounts[4].lamports > lamports);
+}
+
+#[test]
+fn permanent_username_cannot_be_reclaimed() {
+    let alice = Pubkey::new_unique();
+    set_clock(i64::MAX / 2);
+    let mut accounts = reclaim_accounts(alice, 0);
+
+    assert_eq!(
+        run(&mut accounts, reclaim()),
+        Err(program_err(KeyError::LeaseNotReclaimable))
+    );
+}
+
+#[test]
+fn lease_terms_are_bounded() {
+    let mut accounts = vec![
+        registry_config(),
+        TestAccount::program_config(),
+        TestAccount::signer(program_authority()),
+    ];
+    let update = |lease_duration, grace_period| {
+        instruction::UpdateLeaseTerms {
+            lease_duration,
+            grace_period,
+        }
+        .data()
+    };
+
+    for (lease_duration, grace_period) in [
+        (MIN_LEASE_DURATION - 1, MIN_GRACE_PERIOD),
+        (-1, MIN_GRACE_PERIOD),
+        (MIN_LEASE_DURATION, MIN_GRACE_PERIOD - 1),
+    ] {
+        assert_eq!(
+            run(&mut accounts, update(lease_duration, grace_period)),
+            Err(program_err(KeyError::InvalidLeaseTerms))
+        );
+    }
+
+    run(&mut accounts, update(0, MIN_GRACE_PERIOD)).unwrap();
+    let config: RegistryConfigAccount = accounts[0].decode();
+    assert_eq!(config.lease_duration, 0);
+    assert_eq!(config.grace_period, MIN_GRACE_PERIOD);
+}
+
+#[test]
+fn pre_lease_account_never_expires_and_migrates_without_lease() {
+    let alice = Pubkey::new_unique();
+    // Before leases the layout ended at the device list, and removing a
+    // device left its id behind where `expires_at` now sits
+    let layout = |devices| {
+        let mut data = vec![];
+        UserAccount {
+            devices,
+            ..user(alice)
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        data.truncate(data.len() - 8);
+        data
+    };
+    let mut data = layout(vec![1, 2]);
+    let shrunk = layout(vec![1]);
+    data[..shrunk.len()].copy_from_slice(&shrunk);
+    data.resize(8 + UserAccount::INIT_SPACE - 8, 0);
+    let legacy = TestAccount::new(pda(&[b"username", b"alice"]).0, ID, 10_000_000, &data);
+    assert_eq!(legacy.decode::<UserAccount>().expires_at, 2);
+
+    let mut accounts = vec![legacy, TestAccount::program_config()];
+    run(&mut accounts, lookup_username()).unwrap();
+
+    let mut accounts = vec![
+        accounts[0].clone(),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+    run(
+        &mut accounts,
+        instruction::MigrateUserAccount {
+            _username: "alice".to_string(),
+        }
+        .data(),
+    )
+    .unwrap();
+
+    let migrated: UserAccount = accounts[0].decode();
+    assert_eq!(migrated.devices, vec![1]);
+    assert_eq!(migrated.expires_at, 0);
+}
@@ -0,0 +1,331 @@
+//! Tests for X3DH prekey bundles: signed prekey verification through the
+//! Ed25519 program, the one-time prekey ring and stale bundles.
+
+mod common;
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::InstructionData;
+use common::{anchor_err, events, pda, program_err, run, user, user_account, TestAccount};
+use key_registry::{
+    instruction, KeyError, OneTimePrekey, OneTimePrekeyConsumed, PrekeyBundleAccount, SignedPrekey,
+    ID, MAX_ONE_TIME_PREKEYS,
+};
+
+const IDENTITY: [u8; 32] = [1u8; 32];
+
+fn signed_prekey(key_id: u32) -> SignedPrekey {
+    SignedPrekey {
+        key_id,
+        public_key: [key_id as u8; 32],
+        // Only the Ed25519 program checks the signature itself
+        signature: [0xAB; 64],
+    }
+}
+
+fn one_time_prekeys(ids: std::ops::Range<u32>) -> Vec<OneTimePrekey> {
+    ids.map(|key_id| OneTimePrekey {
+        key_id,
+        public_key: [key_id as u8; 32],
+    })
+    .collect()
+}
+
+fn prekey_bundle(username: &str, owner: Pubkey, otpk_ids: std::ops::Range<u32>) -> TestAccount {
+    let (key, bump) = pda(&[b"prekeys", username.as_bytes()]);
+    TestAccount::program_account(
+        key,
+        &PrekeyBundleAccount {
+            owner,
+            username: username.to_string(),
+            identity_key: IDENTITY,
+            signed_prekey: signed_prekey(1),
+            signed_prekey_updated_at: 0,
+            one_time_prekeys: one_time_prekeys(otpk_ids),
+            bump,
+        },
+    )
+}
+
+/// Ed25519 program instruction data verifying one signature, with every
+/// field stored inline.
+fn ed25519_ix(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> Vec<u8> {
+    let (public_key_offset, signature_offset) = (16u16, 48u16);
+    let message_offset = 112u16;
+    let mut data = vec![1, 0];
+    for field in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(public_key);
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+    data
+}
+
+/// Instructions sysvar for `[ed25519 verification, this program]`.
+fn verified(identity: &[u8; 32], prekey: &SignedPrekey) -> TestAccount {
+    TestAccount::instructions_sysvar(
+        &[
+            (
+                ed25519_program::ID,
+                ed25519_ix(identity, &prekey.public_key, &prekey.signature),
+            ),
+            (ID, vec![]),
+        ],
+        1,
+    )
+}
+
+fn publish(prekey: SignedPrekey, otpk_ids: std::ops::Range<u32>) -> Vec<u8> {
+    instruction::PublishPrekeyBundle {
+        _username: "alice".to_string(),
+        identity_key: IDENTITY,
+        signed_prekey: prekey,
+        one_time_prekeys: one_time_prekeys(otpk_ids),
+    }
+    .data()
+}
+
+fn publish_accounts(owner: Pubkey, instructions_sysvar: TestAccount) -> Vec<TestAccount> {
+    vec![
+        user_account(&user(owner)),
+        TestAccount::uninit(pda(&[b"prekeys", b"alice"]).0),
+        TestAccount::signer(owner),
+        instructions_sysvar,
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn consume_accounts(owner: Pubkey, bundle: TestAccount) -> Vec<TestAccount> {
+    vec![
+        user_account(&user(owner)),
+        bundle,
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::program_config(),
+    ]
+}
+
+fn consume() -> Vec<u8> {
+    instruction::ConsumeOneTimePrekey {
+        _username: "alice".to_string(),
+    }
+    .data()
+}
+
+#[test]
+fn publish_with_verified_signature() {
+    let alice = Pubkey::new_unique();
+    let prekey = signed_prekey(1);
+    let mut accounts = publish_accounts(alice, verified(&IDENTITY, &prekey));
+
+    run(&mut accounts, publish(prekey, 0..4)).unwrap();
+
+    let bundle: PrekeyBundleAccount = accounts[1].decode();
+    assert_eq!(bundle.owner, alice);
+    assert_eq!(bundle.identity_key, IDENTITY);
+    assert_eq!(bundle.signed_prekey, prekey);
+    assert_eq!(bundle.one_time_prekeys, one_time_prekeys(0..4));
+}
+
+#[test]
+fn publish_without_ed25519_instruction_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let sysvar = TestAccount::instructions_sysvar(&[(ID, vec![])], 0);
+    let mut accounts = publish_accounts(alice, sysvar);
+
+    assert_eq!(
+        run(&mut accounts, publish(signed_prekey(1), 0..0)),
+        Err(program_err(KeyError::InvalidPrekeySignature))
+    );
+}
+
+#[test]
+fn publish_with_mismatched_verification_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let prekey = signed_prekey(1);
+    let other = signed_prekey(2);
+
+    for sysvar in [
+        // Signed by some other identity
+        verified(&[9u8; 32], &prekey),
+        // Verifies a different prekey
+        verified(&IDENTITY, &other),
+    ] {
+        let mut accounts = publish_accounts(alice, sysvar);
+        assert_eq!(
+            run(&mut accounts, publish(prekey, 0..0)),
+            Err(program_err(KeyError::InvalidPrekeySignature))
+        );
+    }
+}
+
+#[test]
+fn publish_with_out_of_line_signature_data_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let prekey = signed_prekey(1);
+    let mut data = ed25519_ix(&IDENTITY, &prekey.public_key, &prekey.signature);
+    // Point the public key at another instruction's data
+    data[8..10].copy_from_slice(&0u16.to_le_bytes());
+    let sysvar = TestAccount::instructions_sysvar(&[(ed25519_program::ID, data), (ID, vec![])], 1);
+    let mut accounts = publish_accounts(alice, sysvar);
+
+    assert_eq!(
+        run(&mut accounts, publish(prekey, 0..0)),
+        Err(program_err(KeyError::InvalidPrekeySignature))
+    );
+}
+
+#[test]
+fn publish_with_fake_instructions_sysvar_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let prekey = signed_prekey(1);
+    let mut accounts = publish_accounts(alice, TestAccount::wallet(Pubkey::new_unique()));
+
+    assert_eq!(
+        run(&mut accounts, publish(prekey, 0..0)),
+        Err(anchor_err(ErrorCode::ConstraintAddress))
+    );
+}
+
+#[test]
+fn publish_too_many_one_time_prekeys_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let prekey = signed_prekey(1);
+    let mut accounts = publish_accounts(alice, verified(&IDENTITY, &prekey));
+
+    assert_eq!(
+        run(
+            &mut accounts,
+            publish(prekey, 0..MAX_ONE_TIME_PREKEYS as u32 + 1)
+        ),
+        Err(program_err(KeyError::TooManyOneTimePrekeys))
+    );
+}
+
+#[test]
+fn rotation_is_verified_against_stored_identity_key() {
+    let alice = Pubkey::new_unique();
+    let prekey = signed_prekey(2);
+    let data = instruction::RotateSignedPrekey {
+        _username: "alice".to_string(),
+        signed_prekey: prekey,
+    }
+    .data();
+
+    let mut accounts = vec![
+        user_account(&user(alice)),
+        prekey_bundle("alice", alice, 0..0),
+        TestAccount::signer(alice),
+        verified(&[9u8; 32], &prekey),
+        TestAccount::program_config(),
+    ];
+    assert_eq!(
+        run(&mut accounts, data.clone()),
+        Err(program_err(KeyError::InvalidPrekeySignature))
+    );
+
+    accounts[3] = verified(&IDENTITY, &prekey);
+    run(&mut accounts, data).unwrap();
+    assert_eq!(
+        accounts[1].decode::<PrekeyBundleAccount>().signed_prekey,
+        prekey
+    );
+}
+
+#[test]
+fn one_time_prekeys_are_consumed_oldest_first() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = consume_accounts(alice, prekey_bundle("alice", alice, 0..2));
+
+    run(&mut accounts, consume()).unwrap();
+    assert_eq!(
+        accounts[1].decode::<PrekeyBundleAccount>().one_time_prekeys,
+        one_time_prekeys(1..2)
+    );
+
+    run(&mut accounts, consume()).unwrap();
+    assert_eq!(
+        events::<OneTimePrekeyConsumed>()[0].prekey,
+        Some(one_time_prekeys(1..2)[0])
+    );
+    // An empty ring is not an error; X3DH proceeds without a one-time prekey
+    run(&mut accounts, consume()).unwrap();
+    assert_eq!(events::<OneTimePrekeyConsumed>()[0].prekey, None);
+    assert!(accounts[1]
+        .decode::<PrekeyBundleAccount>()
+        .one_time_prekeys
+        .is_empty());
+}
+
+#[test]
+fn ring_is_bounded_when_topping_up() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&user(alice)),
+        prekey_bundle("alice", alice, 0..MAX_ONE_TIME_PREKEYS as u32),
+        TestAccount::signer(alice),
+        TestAccount::instructions_sysvar(&[(ID, vec![])], 0),
+        TestAccount::program_config(),
+    ];
+
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::AddOneTimePrekeys {
+                _username: "alice".to_string(),
+                one_time_prekeys: one_time_prekeys(100..101),
+            }
+            .data()
+        ),
+        Err(program_err(KeyError::TooManyOneTimePrekeys))
+    );
+}
+
+#[test]
+fn stale_bundle_from_previous_owner_is_not_served() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    // The username now belongs to Bob, but the bundle is still Alice's
+    let mut accounts = consume_accounts(bob, prekey_bundle("alice", alice, 0..2));
+
+    assert_eq!(
+        run(&mut accounts, consume()),
+        Err(program_err(KeyError::StalePrekeyBundle))
+    );
+}
+
+#[test]
+fn new_owner_can_close_stale_bundle_refunding_publisher() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = vec![
+        user_account(&user(bob)),
+        prekey_bundle("alice", alice, 0..2),
+        TestAccount::wallet(alice),
+        TestAccount::signer(bob),
+        TestAccount::program_config(),
+    ];
+    let lamports = accounts[2].lamports;
+
+    run(
+        &mut accounts,
+        instruction::ClosePrekeyBundle {
+            _username: "alice".to_string(),
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].lamports > lamports);
+}
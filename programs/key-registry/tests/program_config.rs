@@ -0,0 +1,173 @@
+//! Tests for the program config: setup by the upgrade authority, the two-step
+//! authority handover, per-instruction pause flags and length limits.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use anchor_lang::InstructionData;
+use common::{
+    events, lookup_username, pda, program_authority, program_err, run, user, user_account,
+    TestAccount,
+};
+use key_registry::{
+    instruction, ConfigError, LengthLimits, Operation, OperationPauseUpdated, ProgramConfigAccount,
+    ID,
+};
+
+/// The upgradeable loader's program account, pointing at its program data.
+fn program_account(program_data: Pubkey) -> TestAccount {
+    let mut data = 2u32.to_le_bytes().to_vec();
+    data.extend_from_slice(program_data.as_ref());
+    let mut account = TestAccount::new(ID, bpf_loader_upgradeable::ID, 1, &data);
+    account.executable = true;
+    account.is_writable = false;
+    account
+}
+
+fn program_data(key: Pubkey, upgrade_authority: Pubkey) -> TestAccount {
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(upgrade_authority.as_ref());
+    TestAccount::new(key, bpf_loader_upgradeable::ID, 1, &data)
+}
+
+fn set_paused(operation: Operation, paused: bool) -> Vec<u8> {
+    instruction::SetPaused { operation, paused }.data()
+}
+
+fn limits(min_username_len: u8, max_username_len: u8) -> LengthLimits {
+    LengthLimits {
+        min_username_len,
+        max_username_len,
+        max_group_name_len: 100,
+        max_group_description_len: 500,
+    }
+}
+
+#[test]
+fn upgrade_authority_creates_config() {
+    let upgrader = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+    let program_data_key = Pubkey::new_unique();
+    let data = instruction::InitProgramConfig { authority }.data();
+
+    let mut accounts = vec![
+        TestAccount::uninit(pda(&[b"program_config"]).0),
+        program_account(program_data_key),
+        program_data(program_data_key, upgrader),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::system_program(),
+    ];
+    assert_eq!(
+        run(&mut accounts, data.clone()),
+        Err(program_err(ConfigError::NotProgramAuthority))
+    );
+
+    accounts[3] = TestAccount::signer(upgrader);
+    run(&mut accounts, data).unwrap();
+
+    let config: ProgramConfigAccount = accounts[0].decode();
+    assert_eq!(config.authority, authority);
+    assert_eq!(config.pending_authority, Pubkey::default());
+    assert_eq!(config.paused, 0);
+    assert_eq!(config.limits, limits(3, 20));
+}
+
+#[test]
+fn authority_handover_takes_two_steps() {
+    let successor = Pubkey::new_unique();
+    let propose = instruction::ProposeProgramAuthority {
+        new_authority: successor,
+    }
+    .data();
+    let accept = instruction::AcceptProgramAuthority {}.data();
+
+    let mut accounts = vec![
+        TestAccount::program_config(),
+        TestAccount::signer(successor),
+    ];
+    assert_eq!(
+        run(&mut accounts, propose.clone()),
+        Err(program_err(ConfigError::NotProgramAuthority))
+    );
+
+    accounts[1] = TestAccount::signer(program_authority());
+    run(&mut accounts, propose).unwrap();
+    // Proposing alone hands nothing over
+    assert_eq!(
+        accounts[0].decode::<ProgramConfigAccount>().authority,
+        program_authority()
+    );
+
+    accounts[1] = TestAccount::signer(Pubkey::new_unique());
+    assert_eq!(
+        run(&mut accounts, accept.clone()),
+        Err(program_err(ConfigError::NotPendingAuthority))
+    );
+
+    accounts[1] = TestAccount::signer(successor);
+    run(&mut accounts, accept).unwrap();
+    let config: ProgramConfigAccount = accounts[0].decode();
+    assert_eq!(config.authority, successor);
+    assert_eq!(config.pending_authority, Pubkey::default());
+}
+
+#[test]
+fn paused_instruction_is_rejected_until_resumed() {
+    let mut admin = vec![
+        TestAccount::program_config(),
+        TestAccount::signer(program_authority()),
+    ];
+    run(&mut admin, set_paused(Operation::LookupUsername, true)).unwrap();
+    let event = &events::<OperationPauseUpdated>()[0];
+    assert_eq!(event.operation, Operation::LookupUsername);
+    assert!(event.paused);
+
+    let mut accounts = vec![user_account(&user(Pubkey::new_unique())), admin[0].clone()];
+    assert_eq!(
+        run(&mut accounts, lookup_username()),
+        Err(program_err(ConfigError::InstructionPaused))
+    );
+
+    // Other instructions are unaffected
+    run(&mut admin, set_paused(Operation::RegisterUsername, true)).unwrap();
+    run(&mut admin, set_paused(Operation::LookupUsername, false)).unwrap();
+    accounts[1] = admin[0].clone();
+    run(&mut accounts, lookup_username()).unwrap();
+}
+
+#[test]
+fn length_limits_stay_within_account_space() {
+    let update = |limits| instruction::UpdateLengthLimits { limits }.data();
+    let mut accounts = vec![
+        TestAccount::program_config(),
+        TestAccount::signer(program_authority()),
+    ];
+
+    for invalid in [
+        limits(0, 20),
+        limits(6, 5),
+        limits(3, 21),
+        LengthLimits {
+            max_group_name_len: 101,
+            ..limits(3, 20)
+        },
+        LengthLimits {
+            max_group_description_len: 501,
+            ..limits(3, 20)
+        },
+    ] {
+        assert_eq!(
+            run(&mut accounts, update(invalid)),
+            Err(program_err(ConfigError::InvalidLengthLimits))
+        );
+    }
+
+    run(&mut accounts, update(limits(5, 12))).unwrap();
+    assert_eq!(
+        accounts[0].decode::<ProgramConfigAccount>().limits,
+        limits(5, 12)
+    );
+}
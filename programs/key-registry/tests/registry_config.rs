@@ -0,0 +1,265 @@
+//! Tests for the registry config: setup by the program authority, length-based
+//! registration fees paid into the treasury and names only the program
+//! authority can assign.
+
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::{InstructionData, Space};
+use common::{pda, program_authority, program_err, register_username, run, treasury, TestAccount};
+use key_registry::{
+    instruction, ConfigError, FeeTier, KeyError, RegistryConfigAccount, TreasuryAccount,
+    UserAccount, ID,
+};
+
+const SOL: u64 = 1_000_000_000;
+
+/// 3-character names cost 1 SOL, 4-character names 0.5 SOL, longer ones are
+/// free.
+fn fee_tiers() -> Vec<FeeTier> {
+    vec![
+        FeeTier {
+            max_length: 3,
+            fee: SOL,
+        },
+        FeeTier {
+            max_length: 4,
+            fee: SOL / 2,
+        },
+    ]
+}
+
+fn registry_config(reserved_names: &[&str]) -> TestAccount {
+    let (key, bump) = pda(&[b"registry_config"]);
+    TestAccount::program_account(
+        key,
+        &RegistryConfigAccount {
+            fee_tiers: fee_tiers(),
+            reserved_names: reserved_names.iter().map(|name| name.to_string()).collect(),
+            lease_duration: 0,
+            grace_period: 0,
+            bump,
+        },
+    )
+}
+
+fn register_accounts(
+    username: &str,
+    owner: Pubkey,
+    config: TestAccount,
+    registry_authority: TestAccount,
+) -> Vec<TestAccount> {
+    vec![
+        TestAccount::uninit(pda(&[b"username", username.as_bytes()]).0),
+        TestAccount::uninit(pda(&[b"owner", owner.as_ref()]).0),
+        TestAccount::uninit(pda(&[b"key_history", username.as_bytes()]).0),
+        config,
+        treasury(),
+        TestAccount::signer(owner),
+        registry_authority,
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+#[test]
+fn program_authority_creates_config() {
+    let data = instruction::InitRegistryConfig {
+        fee_tiers: fee_tiers(),
+    }
+    .data();
+
+    let mut accounts = vec![
+        TestAccount::uninit(pda(&[b"registry_config"]).0),
+        TestAccount::uninit(pda(&[b"treasury"]).0),
+        TestAccount::program_config(),
+        TestAccount::signer(Pubkey::new_unique()),
+        TestAccount::system_program(),
+    ];
+    assert_eq!(
+        run(&mut accounts, data.clone()),
+        Err(program_err(ConfigError::NotProgramAuthority))
+    );
+
+    accounts[3] = TestAccount::signer(program_authority());
+    run(&mut accounts, data).unwrap();
+
+    let config: RegistryConfigAccount = accounts[0].decode();
+    assert_eq!(config.fee_tiers, fee_tiers());
+    assert!(config.reserved_names.is_empty());
+    assert_eq!(accounts[1].owner, ID);
+}
+
+#[test]
+fn short_names_pay_tiered_fee_into_treasury() {
+    for (username, fee) in [("bob", SOL), ("carl", SOL / 2), ("alice", 0)] {
+        let owner = Pubkey::new_unique();
+        let mut accounts =
+            register_accounts(username, owner, registry_config(&[]), TestAccount::none());
+        let treasury_lamports = accounts[4].lamports;
+
+        run(&mut accounts, register_username(username)).unwrap();
+
+        assert_eq!(accounts[4].lamports, treasury_lamports + fee);
+        assert_eq!(accounts[0].decode::<UserAccount>().owner, owner);
+    }
+}
+
+#[test]
+fn reserved_name_is_assigned_by_authority_without_fee() {
+    let owner = Pubkey::new_unique();
+    let config = registry_config(&["bob", "satoshi"]);
+
+    // Lookalikes of reserved names are reserved too
+    for username in ["bob", "b0b", "satosh1"] {
+        let mut accounts = register_accounts(username, owner, config.clone(), TestAccount::none());
+        assert_eq!(
+            run(&mut accounts, register_username(username)),
+            Err(program_err(KeyError::ReservedUsername))
+        );
+    }
+
+    let mut accounts = register_accounts(
+        "bob",
+        owner,
+        config.clone(),
+        TestAccount::signer(Pubkey::new_unique()),
+    );
+    assert_eq!(
+        run(&mut accounts, register_username("bob")),
+        Err(program_err(ConfigError::NotProgramAuthority))
+    );
+
+    let mut accounts = register_accounts(
+        "bob",
+        owner,
+        config,
+        TestAccount::signer(program_authority()),
+    );
+    let treasury_lamports = accounts[4].lamports;
+    run(&mut accounts, register_username("bob")).unwrap();
+
+    assert_eq!(accounts[0].decode::<UserAccount>().owner, owner);
+    assert_eq!(accounts[4].lamports, treasury_lamports);
+}
+
+#[test]
+fn built_in_reserved_name_is_assigned_by_authority() {
+    let owner = Pubkey::new_unique();
+    let mut accounts =
+        register_accounts("support", owner, registry_config(&[]), TestAccount::none());
+    assert_eq!(
+        run(&mut accounts, register_username("support")),
+        Err(program_err(KeyError::ReservedUsername))
+    );
+
+    let mut accounts = register_accounts(
+        "support",
+        owner,
+        registry_config(&[]),
+        TestAccount::signer(program_authority()),
+    );
+    run(&mut accounts, register_username("support")).unwrap();
+
+    let user: UserAccount = accounts[0].decode();
+    assert_eq!((user.owner, user.username.as_str()), (owner, "support"));
+}
+
+#[test]
+fn authority_updates_fee_schedule() {
+    let update = |fee_tiers: Vec<FeeTier>| instruction::UpdateFeeSchedule { fee_tiers }.data();
+    let tier = |max_length, fee| FeeTier { max_length, fee };
+
+    let mut accounts = vec![
+        registry_config(&[]),
+        TestAccount::program_config(),
+        TestAccount::signer(Pubkey::new_unique()),
+    ];
+    assert_eq!(
+        run(&mut accounts, update(vec![])),
+        Err(program_err(ConfigError::NotProgramAuthority))
+    );
+
+    accounts[2] = TestAccount::signer(program_authority());
+    for unordered in [
+        vec![tier(4, 1), tier(3, 2)],
+        vec![tier(3, 1), tier(3, 2)],
+        (1..=9).map(|i| tier(i, 1)).collect(),
+    ] {
+        assert_eq!(
+            run(&mut accounts, update(unordered)),
+            Err(program_err(KeyError::InvalidFeeSchedule))
+        );
+    }
+
+    run(&mut accounts, update(vec![tier(5, 7)])).unwrap();
+    assert_eq!(
+        accounts[0].decode::<RegistryConfigAccount>().fee_tiers,
+        vec![tier(5, 7)]
+    );
+}
+
+#[test]
+fn authority_manages_reserved_names() {
+    let add = |name: &str| {
+        instruction::AddReservedName {
+            name: name.to_string(),
+        }
+        .data()
+    };
+    let remove = |name: &str| {
+        instruction::RemoveReservedName {
+            name: name.to_string(),
+        }
+        .data()
+    };
+    let mut accounts = vec![
+        registry_config(&[]),
+        TestAccount::program_config(),
+        TestAccount::signer(program_authority()),
+    ];
+
+    run(&mut accounts, add("Satoshi")).unwrap();
+    assert_eq!(
+        accounts[0].decode::<RegistryConfigAccount>().reserved_names,
+        vec!["satoshi".to_string()]
+    );
+    assert_eq!(
+        run(&mut accounts, add("satoshi")),
+        Err(program_err(KeyError::NameAlreadyReserved))
+    );
+
+    run(&mut accounts, remove("SATOSHI")).unwrap();
+    assert!(accounts[0]
+        .decode::<RegistryConfigAccount>()
+        .reserved_names
+        .is_empty());
+    assert_eq!(
+        run(&mut accounts, remove("satoshi")),
+        Err(program_err(KeyError::NameNotReserved))
+    );
+}
+
+#[test]
+fn withdrawal_keeps_treasury_rent_exempt() {
+    let recipient = Pubkey::new_unique();
+    let rent = Rent::default().minimum_balance(8 + TreasuryAccount::INIT_SPACE);
+    let mut accounts = vec![
+        TestAccount::program_config(),
+        treasury(),
+        TestAccount::wallet(recipient),
+        TestAccount::signer(program_authority()),
+    ];
+    accounts[1].lamports = rent + SOL;
+    let withdraw = |amount| instruction::WithdrawFees { amount }.data();
+
+    assert_eq!(
+        run(&mut accounts, withdraw(SOL + 1)),
+        Err(program_err(KeyError::InsufficientTreasuryBalance))
+    );
+
+    let recipient_lamports = accounts[2].lamports;
+    run(&mut accounts, withdraw(SOL)).unwrap();
+    assert_eq!(accounts[1].lamports, rent);
+    assert_eq!(accounts[2].lamports, recipient_lamports + SOL);
+}
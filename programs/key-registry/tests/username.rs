@@ -0,0 +1,667 @@
+//! Tests for username registration, the username PDA binding on owner-only
+//! instructions, the owner -> username reverse lookup and two-step transfers.
+
+mod common;
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+use common::{account_in_use, anchor_err, events, now, program_err, run, set_clock, TestAccount};
+use key_registry::{
+    instruction, KeyChangeReason, KeyError, KeyHistoryAccount, KeyHistoryEntry, OwnerLookupAccount,
+    PendingTransferAccount, RegistryConfigAccount, TreasuryAccount, UserAccount,
+    UsernameRegistered, UsernameTransferred, ID, MAX_TRANSFER_TIMELOCK,
+};
+
+fn username_pda(username: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"username", username.as_bytes()], &ID)
+}
+
+fn owner_lookup_pda(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"owner", owner.as_ref()], &ID)
+}
+
+fn pending_transfer_pda(username: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"transfer", username.as_bytes()], &ID)
+}
+
+fn key_history_pda(username: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"key_history", username.as_bytes()], &ID)
+}
+
+fn user_account(key: Pubkey, username: &str, owner: Pubkey, bump: u8) -> TestAccount {
+    TestAccount::program_account(
+        key,
+        &UserAccount {
+            owner,
+            username: username.to_string(),
+            created_at: 0,
+            bump,
+            encryption_key: [7u8; 32],
+            devices: vec![],
+            expires_at: 0,
+        },
+    )
+}
+
+/// A correctly derived `UserAccount` for `username`.
+fn canonical(username: &str, owner: Pubkey) -> TestAccount {
+    let (pda, bump) = username_pda(username);
+    user_account(pda, username, owner, bump)
+}
+
+/// A correctly derived reverse lookup pointing `owner` at `username`.
+fn owner_lookup(owner: Pubkey, username: &str) -> TestAccount {
+    let (pda, bump) = owner_lookup_pda(&owner);
+    TestAccount::program_account(
+        pda,
+        &OwnerLookupAccount {
+            owner,
+            username: username.to_string(),
+            bump,
+        },
+    )
+}
+
+/// A key history holding just the registration key of `canonical`.
+fn key_history(username: &str) -> TestAccount {
+    let (pda, bump) = key_history_pda(username);
+    TestAccount::program_account(
+        pda,
+        &KeyHistoryAccount {
+            username: username.to_string(),
+            entries: vec![KeyHistoryEntry {
+                key: [7u8; 32],
+                valid_from: 0,
+                valid_until: 0,
+                reason: KeyChangeReason::Registered,
+                compromised_at: 0,
+            }],
+            bump,
+        },
+    )
+}
+
+fn pending_transfer(
+    username: &str,
+    current_owner: Pubkey,
+    new_owner: Pubkey,
+    unlocks_at: i64,
+) -> TestAccount {
+    let (pda, bump) = pending_transfer_pda(username);
+    TestAccount::program_account(
+        pda,
+        &PendingTransferAccount {
+            username: username.to_string(),
+            current_owner,
+            new_owner,
+            proposed_at: 0,
+            unlocks_at,
+            bump,
+        },
+    )
+}
+
+/// Accounts for registering `username`, with a registry config that has no
+/// fees or reserved names.
+fn register_accounts(username: &str, owner: Pubkey, owner_lookup: TestAccount) -> Vec<TestAccount> {
+    let (config, config_bump) = Pubkey::find_program_address(&[b"registry_config"], &ID);
+    let (treasury, treasury_bump) = Pubkey::find_program_address(&[b"treasury"], &ID);
+    vec![
+        TestAccount::uninit(username_pda(username).0),
+        owner_lookup,
+        TestAccount::uninit(key_history_pda(username).0),
+        TestAccount::program_account(
+            config,
+            &RegistryConfigAccount {
+                fee_tiers: vec![],
+                reserved_names: vec![],
+                lease_duration: 0,
+                grace_period: 0,
+                bump: config_bump,
+            },
+        ),
+        TestAccount::program_account(
+            treasury,
+            &TreasuryAccount {
+                bump: treasury_bump,
+            },
+        ),
+        TestAccount::signer(owner),
+        TestAccount::none(),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn accept_accounts(username: &str, current_owner: Pubkey, new_owner: Pubkey) -> Vec<TestAccount> {
+    vec![
+        canonical(username, current_owner),
+        pending_transfer(username, current_owner, new_owner, now()),
+        owner_lookup(current_owner, username),
+        TestAccount::uninit(owner_lookup_pda(&new_owner).0),
+        key_history(username),
+        TestAccount::wallet(current_owner),
+        TestAccount::signer(new_owner),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ]
+}
+
+fn accept(username: &str) -> Vec<u8> {
+    instruction::AcceptTransfer {
+        _username: username.to_string(),
+        encryption_key: [5u8; 32],
+    }
+    .data()
+}
+
+fn cancel_accounts(
+    username: &str,
+    current_owner: Pubkey,
+    new_owner: Pubkey,
+    signer: Pubkey,
+) -> Vec<TestAccount> {
+    vec![
+        pending_transfer(username, current_owner, new_owner, now()),
+        TestAccount::wallet(current_owner),
+        TestAccount::signer(signer),
+        TestAccount::program_config(),
+    ]
+}
+
+fn cancel(username: &str) -> Vec<u8> {
+    instruction::CancelTransfer {
+        _username: username.to_string(),
+    }
+    .data()
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    ProposeTransfer(Pubkey, i64),
+    UpdateKey,
+    Close,
+}
+
+/// Every instruction guarded by the username PDA binding.
+fn guarded_ops(new_owner: Pubkey) -> [Op; 3] {
+    [Op::ProposeTransfer(new_owner, 0), Op::UpdateKey, Op::Close]
+}
+
+impl Op {
+    fn data(self, username: &str) -> Vec<u8> {
+        let _username = username.to_string();
+        match self {
+            Op::ProposeTransfer(new_owner, timelock) => instruction::ProposeTransfer {
+                _username,
+                new_owner,
+                timelock,
+            }
+            .data(),
+            Op::UpdateKey => instruction::UpdateEncryptionKey {
+                _username,
+                new_encryption_key: [9u8; 32],
+            }
+            .data(),
+            Op::Close => instruction::CloseAccount { _username }.data(),
+        }
+    }
+
+    /// Accounts for the instruction on `target`, with `user` as the username
+    /// record and `signer` (whose own lookup points at `signer_name`) as the
+    /// owner.
+    fn accounts(
+        self,
+        target: &str,
+        user: TestAccount,
+        signer: Pubkey,
+        signer_name: &str,
+    ) -> Vec<TestAccount> {
+        match self {
+            Op::ProposeTransfer(..) => vec![
+                user,
+                TestAccount::uninit(pending_transfer_pda(target).0),
+                TestAccount::signer(signer),
+                TestAccount::system_program(),
+                TestAccount::program_config(),
+            ],
+            Op::UpdateKey => vec![
+                user,
+                key_history(target),
+                TestAccount::signer(signer),
+                TestAccount::program_config(),
+            ],
+            Op::Close => vec![
+                user,
+                owner_lookup(signer, signer_name),
+                key_history(target),
+                TestAccount::uninit(pending_transfer_pda(target).0),
+                TestAccount::wallet(signer),
+                TestAccount::signer(signer),
+                TestAccount::program_config(),
+            ],
+        }
+    }
+}
+
+#[test]
+fn register_creates_reverse_lookup_and_key_history() {
+    let alice = Pubkey::new_unique();
+    let lookup = TestAccount::uninit(owner_lookup_pda(&alice).0);
+    let mut accounts = register_accounts("alice", alice, lookup);
+
+    run(
+        &mut accounts,
+        instruction::RegisterUsername {
+            username: "Alice".to_string(),
+            encryption_key: [1u8; 32],
+        }
+        .data(),
+    )
+    .unwrap();
+
+    let user: UserAccount = accounts[0].decode();
+    assert_eq!(user.owner, alice);
+    assert_eq!(user.username, "alice");
+    let lookup: OwnerLookupAccount = accounts[1].decode();
+    assert_eq!(lookup.owner, alice);
+    assert_eq!(lookup.username, "alice");
+    let history: KeyHistoryAccount = accounts[2].decode();
+    assert_eq!(history.entries.len(), 1);
+    assert_eq!(history.entries[0].key, [1u8; 32]);
+    let event = &events::<UsernameRegistered>()[0];
+    assert_eq!(event.username, "alice");
+    assert_eq!(event.owner, alice);
+    assert_eq!(event.encryption_key, [1u8; 32]);
+    assert_eq!((event.fee, event.expires_at), (0, 0));
+    assert_eq!(history.entries[0].reason, KeyChangeReason::Registered);
+}
+
+#[test]
+fn reserved_username_lookalikes_are_rejected() {
+    let mallory = Pubkey::new_unique();
+
+    for username in ["Admin", "adm1n", "supp0rt", "Key_App"] {
+        let lookup = TestAccount::uninit(owner_lookup_pda(&mallory).0);
+        let mut accounts = register_accounts(&username.to_lowercase(), mallory, lookup);
+        assert_eq!(
+            run(
+                &mut accounts,
+                instruction::RegisterUsername {
+                    username: username.to_string(),
+                    encryption_key: [1u8; 32],
+                }
+                .data(),
+            ),
+            Err(program_err(KeyError::ReservedUsername))
+        );
+    }
+}
+
+#[test]
+fn username_length_follows_program_config() {
+    let bob = Pubkey::new_unique();
+    let register = || {
+        instruction::RegisterUsername {
+            username: "bobby".to_string(),
+            encryption_key: [1u8; 32],
+        }
+        .data()
+    };
+    let lookup = TestAccount::uninit(owner_lookup_pda(&bob).0);
+    let mut accounts = register_accounts("bobby", bob, lookup);
+    let last = accounts.len() - 1;
+    accounts[last] = TestAccount::program_config_with(|config| config.limits.min_username_len = 6);
+
+    assert_eq!(
+        run(&mut accounts, register()),
+        Err(program_err(KeyError::InvalidUsernameLength))
+    );
+
+    accounts[last] = TestAccount::program_config();
+    run(&mut accounts, register()).unwrap();
+}
+
+#[test]
+fn second_username_for_same_wallet_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = register_accounts("alice2", alice, owner_lookup(alice, "alice"));
+
+    assert_eq!(
+        run(
+            &mut accounts,
+            instruction::RegisterUsername {
+                username: "alice2".to_string(),
+                encryption_key: [1u8; 32],
+            }
+            .data(),
+        ),
+        Err(account_in_use())
+    );
+}
+
+#[test]
+fn owner_can_propose_transfer() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let op = Op::ProposeTransfer(bob, 3600);
+    let mut accounts = op.accounts("alice", canonical("alice", alice), alice, "alice");
+
+    run(&mut accounts, op.data("alice")).unwrap();
+
+    // Nothing changes hands until the recipient accepts
+    assert_eq!(accounts[0].decode::<UserAccount>().owner, alice);
+    let pending: PendingTransferAccount = accounts[1].decode();
+    assert_eq!(pending.current_owner, alice);
+    assert_eq!(pending.new_owner, bob);
+    assert_eq!(pending.unlocks_at, now() + 3600);
+}
+
+#[test]
+fn propose_transfer_validates_arguments() {
+    let alice = Pubkey::new_unique();
+
+    for (op, err) in [
+        (
+            Op::ProposeTransfer(alice, 0),
+            KeyError::CannotTransferToSelf,
+        ),
+        (
+            Op::ProposeTransfer(Pubkey::new_unique(), -1),
+            KeyError::InvalidTransferTimelock,
+        ),
+        (
+            Op::ProposeTransfer(Pubkey::new_unique(), MAX_TRANSFER_TIMELOCK + 1),
+            KeyError::InvalidTransferTimelock,
+        ),
+    ] {
+        let mut accounts = op.accounts("alice", canonical("alice", alice), alice, "alice");
+        assert_eq!(run(&mut accounts, op.data("alice")), Err(program_err(err)));
+    }
+}
+
+#[test]
+fn recipient_accepts_transfer_with_own_key() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = accept_accounts("alice", alice, bob);
+
+    run(&mut accounts, accept("alice")).unwrap();
+
+    let user: UserAccount = accounts[0].decode();
+    assert_eq!(user.owner, bob);
+    assert_eq!(user.encryption_key, [5u8; 32]);
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].is_closed());
+    let lookup: OwnerLookupAccount = accounts[3].decode();
+    assert_eq!(lookup.owner, bob);
+    assert_eq!(lookup.username, "alice");
+    let history: KeyHistoryAccount = accounts[4].decode();
+    assert_eq!(history.entries[0].valid_until, now());
+    assert_eq!(history.entries[1].key, [5u8; 32]);
+    assert_eq!(history.entries[1].reason, KeyChangeReason::Transferred);
+    let event = &events::<UsernameTransferred>()[0];
+    assert_eq!((event.previous_owner, event.new_owner), (alice, bob));
+    assert_eq!(event.encryption_key, [5u8; 32]);
+}
+
+#[test]
+fn accept_before_timelock_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = accept_accounts("alice", alice, bob);
+    accounts[1] = pending_transfer("alice", alice, bob, now() + 60);
+
+    assert_eq!(
+        run(&mut accounts, accept("alice")),
+        Err(program_err(KeyError::TransferLocked))
+    );
+
+    set_clock(now() + 60);
+    run(&mut accounts, accept("alice")).unwrap();
+}
+
+#[test]
+fn accept_by_other_wallet_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+    let mut accounts = accept_accounts("alice", alice, mallory);
+    accounts[1] = pending_transfer("alice", alice, bob, now());
+
+    assert_eq!(
+        run(&mut accounts, accept("alice")),
+        Err(program_err(KeyError::NotTransferRecipient))
+    );
+}
+
+#[test]
+fn accept_after_username_changed_hands_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+    // Alice released the name and Mallory re-registered it while the
+    // proposal was still open
+    let mut accounts = accept_accounts("alice", alice, bob);
+    accounts[0] = canonical("alice", mallory);
+
+    assert_eq!(
+        run(&mut accounts, accept("alice")),
+        Err(program_err(KeyError::StaleTransfer))
+    );
+}
+
+#[test]
+fn accept_to_wallet_with_username_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = accept_accounts("alice", alice, bob);
+    accounts[3] = owner_lookup(bob, "bob");
+
+    assert_eq!(run(&mut accounts, accept("alice")), Err(account_in_use()));
+}
+
+#[test]
+fn either_party_can_cancel_transfer() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+
+    for signer in [alice, bob] {
+        let mut accounts = cancel_accounts("alice", alice, bob, signer);
+        let lamports = accounts[1].lamports;
+
+        run(&mut accounts, cancel("alice")).unwrap();
+
+        assert!(accounts[0].is_closed());
+        assert!(accounts[1].lamports > lamports);
+    }
+}
+
+#[test]
+fn outsider_cannot_cancel_transfer() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = cancel_accounts("alice", alice, bob, Pubkey::new_unique());
+
+    assert_eq!(
+        run(&mut accounts, cancel("alice")),
+        Err(program_err(KeyError::NotTransferParty))
+    );
+}
+
+#[test]
+fn owner_can_update_key_on_canonical_account() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = Op::UpdateKey.accounts("alice", canonical("alice", alice), alice, "alice");
+
+    run(&mut accounts, Op::UpdateKey.data("alice")).unwrap();
+
+    assert_eq!(
+        accounts[0].decode::<UserAccount>().encryption_key,
+        [9u8; 32]
+    );
+}
+
+#[test]
+fn close_releases_username_lookup_and_key_history() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = Op::Close.accounts("alice", canonical("alice", alice), alice, "alice");
+
+    run(&mut accounts, Op::Close.data("alice")).unwrap();
+
+    assert!(accounts[0].is_closed());
+    assert!(accounts[1].is_closed());
+    assert!(accounts[2].is_closed());
+}
+
+#[test]
+fn close_clears_pending_transfer() {
+    let alice = Pubkey::new_unique();
+    let bob = Pubkey::new_unique();
+    let mut accounts = Op::Close.accounts("alice", canonical("alice", alice), alice, "alice");
+    accounts[3] = pending_transfer("alice", alice, bob, 0);
+    let lamports = accounts[4].lamports;
+
+    run(&mut accounts, Op::Close.data("alice")).unwrap();
+
+    assert!(accounts[3].is_closed());
+    assert!(accounts[4].lamports > lamports);
+}
+
+#[test]
+fn backfill_creates_missing_lookup() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = [
+        canonical("alice", alice),
+        TestAccount::uninit(owner_lookup_pda(&alice).0),
+        TestAccount::signer(alice),
+        TestAccount::system_program(),
+        TestAccount::program_config(),
+    ];
+
+    run(
+        &mut accounts,
+        instruction::BackfillOwnerLookup {
+            _username: "alice".to_string(),
+        }
+        .data(),
+    )
+    .unwrap();
+
+    assert_eq!(accounts[1].decode::<OwnerLookupAccount>().username, "alice");
+}
+
+#[test]
+fn username_argument_is_case_insensitive() {
+    let alice = Pubkey::new_unique();
+    let mut accounts = Op::UpdateKey.accounts("alice", canonical("alice", alice), alice, "alice");
+
+    run(&mut accounts, Op::UpdateKey.data("Alice")).unwrap();
+}
+
+#[test]
+fn forged_account_off_pda_is_rejected() {
+    let mallory = Pubkey::new_unique();
+    let (_, bump) = username_pda("alice");
+
+    for op in guarded_ops(mallory) {
+        // Right layout and discriminator, but not at the derived address.
+        let forged = user_account(Pubkey::new_unique(), "alice", mallory, bump);
+        let mut accounts = op.accounts("alice", forged, mallory, "alice");
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(anchor_err(ErrorCode::ConstraintSeeds))
+        );
+    }
+}
+
+#[test]
+fn foreign_username_account_is_rejected() {
+    let mallory = Pubkey::new_unique();
+
+    for op in guarded_ops(Pubkey::new_unique()) {
+        // Mallory's own, legitimately derived record for a different name.
+        let mut accounts = op.accounts("alice", canonical("mallory", mallory), mallory, "mallory");
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(anchor_err(ErrorCode::ConstraintSeeds))
+        );
+    }
+}
+
+#[test]
+fn tampered_bump_is_rejected() {
+    let mallory = Pubkey::new_unique();
+    let (pda, bump) = username_pda("alice");
+
+    for op in guarded_ops(Pubkey::new_unique()) {
+        let forged = user_account(pda, "alice", mallory, bump.wrapping_sub(1));
+        let mut accounts = op.accounts("alice", forged, mallory, "alice");
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(anchor_err(ErrorCode::ConstraintSeeds))
+        );
+    }
+}
+
+#[test]
+fn account_owned_by_another_program_is_rejected() {
+    let mallory = Pubkey::new_unique();
+
+    for op in guarded_ops(Pubkey::new_unique()) {
+        let mut forged = canonical("alice", mallory);
+        forged.owner = Pubkey::new_unique();
+        let mut accounts = op.accounts("alice", forged, mallory, "alice");
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(anchor_err(ErrorCode::AccountOwnedByWrongProgram))
+        );
+    }
+}
+
+#[test]
+fn non_user_account_data_is_rejected() {
+    let mallory = Pubkey::new_unique();
+
+    for op in guarded_ops(Pubkey::new_unique()) {
+        let mut forged = canonical("alice", mallory);
+        forged.data_mut()[..8].copy_from_slice(&[0u8; 8]);
+        let mut accounts = op.accounts("alice", forged, mallory, "alice");
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(anchor_err(ErrorCode::AccountDiscriminatorMismatch))
+        );
+    }
+}
+
+#[test]
+fn non_owner_signer_is_rejected() {
+    let alice = Pubkey::new_unique();
+    let mallory = Pubkey::new_unique();
+
+    for op in guarded_ops(mallory) {
+        let mut accounts = op.accounts("alice", canonical("alice", alice), mallory, "mallory");
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(program_err(KeyError::NotOwner))
+        );
+        assert_eq!(accounts[0].decode::<UserAccount>().owner, alice);
+    }
+}
+
+#[test]
+fn owner_must_sign() {
+    let alice = Pubkey::new_unique();
+
+    for op in guarded_ops(Pubkey::new_unique()) {
+        let mut accounts = op.accounts("alice", canonical("alice", alice), alice, "alice");
+        for account in accounts.iter_mut() {
+            account.is_signer = false;
+        }
+        assert_eq!(
+            run(&mut accounts, op.data("alice")),
+            Err(anchor_err(ErrorCode::AccountNotSigner))
+        );
+    }
+}